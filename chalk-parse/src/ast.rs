@@ -1,15 +1,40 @@
 use lalrpop_intern::InternedString;
 use std::fmt;
 
+/// A range of byte offsets in the source text. `line` and `column` give the
+/// position of its start, both counted from 1, for error reporting.
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct Span {
     pub lo: usize,
     pub hi: usize,
+    pub line: usize,
+    pub column: usize,
 }
 
-impl Span {
-    pub fn new(lo: usize, hi: usize) -> Self {
-        Span { lo: lo, hi: hi }
+impl fmt::Display for Span {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "line {}, column {}", self.line, self.column)
+    }
+}
+
+/// The offsets at which the lines of a source text start, used by the
+/// parser to locate spans.
+pub struct LineIndex {
+    starts: Vec<usize>,
+}
+
+impl LineIndex {
+    pub fn new(text: &str) -> Self {
+        let starts = Some(0).into_iter().chain(text.match_indices('\n').map(|(i, _)| i + 1)).collect();
+        LineIndex { starts: starts }
+    }
+
+    pub fn span(&self, lo: usize, hi: usize) -> Span {
+        let line = match self.starts.binary_search(&lo) {
+            Ok(line) => line,
+            Err(next_line) => next_line - 1,
+        };
+        Span { lo: lo, hi: hi, line: line + 1, column: lo - self.starts[line] + 1 }
     }
 }
 
@@ -23,24 +48,44 @@ pub enum Item {
     Impl(Impl),
 }
 
+impl Item {
+    pub fn span(&self) -> Span {
+        match *self {
+            Item::StructDefn(ref d) => d.span,
+            Item::TraitDefn(ref d) => d.span,
+            Item::Impl(ref d) => d.span,
+        }
+    }
+}
+
 pub struct StructDefn {
     pub name: Identifier,
     pub parameter_kinds: Vec<ParameterKind>,
     pub where_clauses: Vec<WhereClause>,
     pub fields: Vec<Field>,
+    pub upstream: bool,
+    pub fundamental: bool,
+    pub span: Span,
 }
 
 pub struct TraitDefn {
     pub name: Identifier,
     pub parameter_kinds: Vec<ParameterKind>,
+    /// Supertraits, as in `trait Foo: Bar + Baz`.
+    pub bounds: Vec<InlineBound>,
     pub where_clauses: Vec<WhereClause>,
     pub assoc_ty_defns: Vec<AssocTyDefn>,
     pub auto: bool,
+    pub upstream: bool,
+    pub fundamental: bool,
+    pub span: Span,
 }
 
 pub struct AssocTyDefn {
     pub name: Identifier,
     pub parameter_kinds: Vec<ParameterKind>,
+    /// Bounds on the associated type, as in `type Item: Clone;`.
+    pub bounds: Vec<InlineBound>,
 }
 
 pub enum ParameterKind {
@@ -97,6 +142,9 @@ pub struct Impl {
     pub trait_ref: PolarizedTraitRef,
     pub where_clauses: Vec<WhereClause>,
     pub assoc_ty_values: Vec<AssocTyValue>,
+    pub default: bool,
+    pub upstream: bool,
+    pub span: Span,
 }
 
 pub struct AssocTyValue {
@@ -104,6 +152,7 @@ pub struct AssocTyValue {
     pub parameter_kinds: Vec<ParameterKind>,
     pub where_clauses: Vec<WhereClause>,
     pub value: Ty,
+    pub default: bool,
 }
 
 pub enum Ty {
@@ -120,7 +169,44 @@ pub enum Ty {
     ForAll {
         lifetime_names: Vec<Identifier>,
         ty: Box<Ty>
-    }
+    },
+    Ref {
+        lifetime: Lifetime,
+        mutable: bool,
+        ty: Box<Ty>,
+    },
+    Tuple {
+        tys: Vec<Ty>,
+    },
+    Slice {
+        ty: Box<Ty>,
+    },
+    FnPtr {
+        args: Vec<Ty>,
+        ret: Box<Ty>,
+    },
+    Dyn {
+        bounds: Vec<InlineBound>,
+    },
+}
+
+/// One of the `+`-separated bounds of a `dyn` type, of a trait (its
+/// supertraits) or of an associated type, whose self type is left implicit.
+pub enum InlineBound {
+    /// `Iterator<Item = u32>`
+    Trait {
+        trait_name: Identifier,
+        args: Vec<Parameter>,
+        assoc_ty_bindings: Vec<AssocTyBinding>,
+    },
+    Lifetime {
+        lifetime: Lifetime,
+    },
+}
+
+pub struct AssocTyBinding {
+    pub name: Identifier,
+    pub ty: Ty,
 }
 
 pub enum Lifetime {
@@ -166,8 +252,12 @@ pub enum WhereClause {
     ProjectionEq { projection: ProjectionTy, ty: Ty },
     TyWellFormed { ty: Ty },
     TraitRefWellFormed { trait_ref: TraitRef },
+    TyFromEnv { ty: Ty },
+    TraitRefFromEnv { trait_ref: TraitRef },
     UnifyTys { a: Ty, b: Ty },
     UnifyLifetimes { a: Lifetime, b: Lifetime },
+    TyOutlives { a: Ty, b: Lifetime },
+    LifetimeOutlives { a: Lifetime, b: Lifetime },
 }
 
 pub struct Field {
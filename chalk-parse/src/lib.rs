@@ -15,21 +15,21 @@ use lalrpop_util::ParseError;
 use std::fmt::Write;
 
 pub fn parse_program(text: &str) -> Result<ast::Program> {
-    match parser::parse_Program(text) {
+    match parser::parse_Program(&ast::LineIndex::new(text), text) {
         Ok(v) => Ok(v),
         Err(e) => bail!("parse error: {:?}", e),
     }
 }
 
 pub fn parse_ty(text: &str) -> Result<ast::Ty> {
-    match parser::parse_Ty(text) {
+    match parser::parse_Ty(&ast::LineIndex::new(text), text) {
         Ok(v) => Ok(v),
         Err(e) => bail!("error parsing `{}`: {:?}", text, e),
     }
 }
 
 pub fn parse_goal(text: &str) -> Result<Box<ast::Goal>> {
-    match parser::parse_Goal(text) {
+    match parser::parse_Goal(&ast::LineIndex::new(text), text) {
         Ok(v) => Ok(v),
         Err(e) => {
             let position_string = |start: usize, end: usize| {
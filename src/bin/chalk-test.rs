@@ -0,0 +1,63 @@
+extern crate chalk;
+
+#[macro_use]
+extern crate error_chain;
+
+use std::env;
+use std::io::Read;
+use std::fs::File;
+
+use chalk::solve::harness::TestFile;
+use chalk::solve::solver::SolverConfig;
+
+error_chain! {
+    links {
+        Chalk(chalk::errors::Error, chalk::errors::ErrorKind);
+    }
+
+    foreign_links {
+        Io(::std::io::Error);
+    }
+}
+
+quick_main!(run);
+
+/// Run the `.chalk` test files given as arguments, see `TestFile`, and report
+/// the goals whose result was not the expected one.
+fn run() -> Result<()> {
+    let paths: Vec<String> = env::args().skip(1).collect();
+    if paths.is_empty() {
+        bail!("usage: chalk-test <file.chalk>...");
+    }
+
+    let mut failed_files = 0;
+    for path in &paths {
+        match run_file(path) {
+            Ok(0) => println!("{}: ok", path),
+            Ok(failures) => {
+                println!("{}: {} failed\n", path, failures);
+                failed_files += 1;
+            }
+            Err(e) => {
+                println!("{}: error: {}\n", path, e);
+                failed_files += 1;
+            }
+        }
+    }
+
+    if failed_files > 0 {
+        bail!("{} of {} test files failed", failed_files, paths.len());
+    }
+    Ok(())
+}
+
+/// Run a test file, printing its failures; returns how many there were.
+fn run_file(path: &str) -> Result<usize> {
+    let mut text = String::new();
+    File::open(path)?.read_to_string(&mut text)?;
+    let failures = TestFile::parse(&text)?.run(SolverConfig::default())?;
+    for failure in &failures {
+        println!("{}: {}", path, failure);
+    }
+    Ok(failures.len())
+}
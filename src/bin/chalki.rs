@@ -5,13 +5,16 @@ extern crate chalk;
 #[macro_use]
 extern crate error_chain;
 
+use std::env;
 use std::io::Read;
 use std::fs::File;
 use std::sync::Arc;
 
 use chalk::ir;
 use chalk::lower::*;
-use chalk::solve::solver::{self, Solver, CycleStrategy};
+use chalk::solve::Solution;
+use chalk::solve::json::{Json, ToJson};
+use chalk::solve::solver::{CycleStrategy, Engine, Solver, SolverConfig};
 
 use rustyline::error::ReadlineError;
 
@@ -31,29 +34,204 @@ struct Program {
     text: String,
     ir: Arc<ir::Program>,
     env: Arc<ir::ProgramEnvironment>,
+
+    /// Kept around so that answers are cached across goals.
+    solver: Solver,
+
+    format: Format,
+}
+
+/// How the solutions of goals are printed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Format {
+    Text,
+
+    /// One JSON object per goal, with the goal and its solution (see
+    /// `chalk::solve::json::ToJson`).
+    Json,
 }
 
 impl Program {
-    fn new(text: String) -> Result<Program> {
-        let ir = Arc::new(chalk_parse::parse_program(&text)?.lower()?);
+    fn new(text: String, config: SolverConfig, format: Format) -> Result<Program> {
+        let ir = Arc::new(chalk_parse::parse_program(&text)?.lower(config)?);
         let env = Arc::new(ir.environment());
-        Ok(Program { text, ir, env })
+        ir.verify_well_formedness(&env, config)?;
+        let solver = Solver::new(&env, config);
+        Ok(Program { text, ir, env, solver, format })
+    }
+
+    /// Run `op` with the solver. The program is made current meanwhile, so
+    /// that trace events show item names; results are rendered with it
+    /// explicitly.
+    fn with_solver<F, R>(&mut self, op: F) -> R
+        where F: FnOnce(&mut Solver) -> R
+    {
+        let solver = &mut self.solver;
+        ir::set_current_program(&self.ir, || op(solver))
     }
 }
 
 quick_main!(run);
 
+/// The command-line arguments.
+#[derive(Debug)]
+struct Args {
+    /// The file to load the program from.
+    program: Option<String>,
+
+    /// Goals to solve, in batch mode; the REPL is started if there are none.
+    goals: Vec<String>,
+
+    /// Whether goals in batch mode fail unless they have a unique solution.
+    require_unique: bool,
+
+    config: SolverConfig,
+    format: Format,
+}
+
 fn run() -> Result<()> {
-    // Initialize global overflow depth before everything
-    let overflow_depth = 10;
-    solver::set_overflow_depth(overflow_depth);
-
-    let mut prog = None;
-    readline_loop(&mut rustyline::Editor::new(), "?- ", |rl, line| {
-        if let Err(e) = process(line, rl, &mut prog) {
-            println!("error: {}", e);
+    let args = match parse_args(env::args().skip(1))? {
+        Some(args) => args,
+        None => {
+            usage();
+            return Ok(());
         }
-    })
+    };
+
+    let mut prog = match args.program {
+        Some(ref filename) => Some(Program::new(read_file(filename)?, args.config, args.format)?),
+        None => None,
+    };
+
+    if args.goals.is_empty() {
+        readline_loop(&mut rustyline::Editor::new(), "?- ", |rl, line| {
+            if let Err(e) = process(line, rl, &mut prog, args.config, args.format) {
+                println!("error: {}", e);
+            }
+        })
+    } else {
+        let prog = prog.as_mut().ok_or("goals given without a program, use `--program <file>`")?;
+        batch(&args.goals, prog, args.require_unique)
+    }
+}
+
+/// Parse the command-line arguments; `None` if help was asked for.
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Option<Args>> {
+    let mut program = None;
+    let mut goals = vec![];
+    let mut require_unique = false;
+    let mut config = SolverConfig::default();
+    let mut format = Format::Text;
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("missing value for `{}`", arg));
+        match &arg[..] {
+            "-h" | "--help" => return Ok(None),
+            "--program" => program = Some(value()?),
+            "--goal" => goals.push(value()?),
+            "--goals" => goals.extend(goal_lines(&read_file(&value()?)?)),
+            "--require-unique" => require_unique = true,
+            "--overflow-depth" => {
+                let depth = value()?;
+                config.overflow_depth = depth.parse()
+                                             .map_err(|_| format!("invalid overflow depth `{}`", depth))?;
+            }
+            "--cycle-strategy" => {
+                config.cycle_strategy = match &value()?[..] {
+                    "tabling" => CycleStrategy::Tabling,
+                    "error" => CycleStrategy::Error,
+                    strategy => bail!("invalid cycle strategy `{}`, expected `tabling` or `error`", strategy),
+                };
+            }
+            "--engine" => {
+                config.engine = match &value()?[..] {
+                    "recursive" => Engine::Recursive,
+                    "slg" => Engine::Slg,
+                    engine => bail!("invalid engine `{}`, expected `recursive` or `slg`", engine),
+                };
+            }
+            "--format" => {
+                format = match &value()?[..] {
+                    "text" => Format::Text,
+                    "json" => Format::Json,
+                    format => bail!("invalid format `{}`, expected `text` or `json`", format),
+                };
+            }
+            _ => bail!("unknown argument `{}`, see `--help`", arg),
+        }
+    }
+
+    Ok(Some(Args { program, goals, require_unique, config, format }))
+}
+
+/// The goals of a `--goals` file: its lines, but for empty ones and `//`
+/// comments.
+fn goal_lines(text: &str) -> Vec<String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with("//"))
+        .map(str::to_string)
+        .collect()
+}
+
+fn usage() {
+    println!("Usage: chalki [options]");
+    println!();
+    println!("Starts a REPL, unless goals are given: then each goal is solved in turn");
+    println!("and the exit code is non-zero if any of them has no solution. An ambiguous");
+    println!("solution, or one that cannot be proven, counts as a solution unless");
+    println!("`--require-unique` is given.");
+    println!();
+    println!("Options:");
+    println!("  --program <file>          load the program from <file>");
+    println!("  --goal <goal>             solve <goal>; may be repeated");
+    println!("  --goals <file>            solve each line of <file> as a goal, skipping");
+    println!("                            empty lines and `//` comments");
+    println!("  --require-unique          fail goals whose solution is not unique");
+    println!("  --overflow-depth <n>      report goals nested more than <n> deep as an");
+    println!("                            overflow (default: {})", SolverConfig::default().overflow_depth);
+    println!("  --cycle-strategy <s>      `tabling` (default) or `error`");
+    println!("  --engine <e>              `recursive` (default) or `slg`");
+    println!("  --format <f>              print solutions as `text` (default) or `json`");
+    println!("  -h, --help                print this output");
+}
+
+/// Solve each of the goals, failing if some have no solution, or no unique
+/// one if `require_unique`.
+fn batch(goals: &[String], prog: &mut Program, require_unique: bool) -> Result<()> {
+    let failed = goals.iter()
+        .filter(|text| {
+            if prog.format == Format::Text {
+                println!("?- {}", text);
+            }
+            match goal(text, prog) {
+                Ok(Some(ref solution)) => require_unique && !solution.is_unique(),
+                Ok(None) => true,
+                Err(e) => {
+                    let e = ir::with_program(&prog.ir, &e).to_string();
+                    match prog.format {
+                        Format::Text => println!("error: {}\n", e),
+                        Format::Json => println!("{}", Json::Object(vec![
+                            ("goal".to_string(), Json::String(text.to_string())),
+                            ("error".to_string(), Json::String(e)),
+                        ])),
+                    }
+                    true
+                }
+            }
+        })
+        .count();
+
+    if failed > 0 {
+        bail!("{} of {} goals failed", failed, goals.len());
+    }
+    Ok(())
+}
+
+fn read_file(filename: &str) -> Result<String> {
+    let mut text = String::new();
+    File::open(filename)?.read_to_string(&mut text)?;
+    Ok(text)
 }
 
 /// Repeatedly calls `f`, passing in each line, using the given promt, until EOF is received
@@ -75,26 +253,33 @@ fn readline_loop<F>(rl: &mut rustyline::Editor<()>, prompt: &str, mut f: F) -> R
 }
 
 /// Process a single command
-fn process(command: &str, rl: &mut rustyline::Editor<()>, prog: &mut Option<Program>) -> Result<()> {
+fn process(command: &str,
+           rl: &mut rustyline::Editor<()>,
+           prog: &mut Option<Program>,
+           config: SolverConfig,
+           format: Format)
+           -> Result<()>
+{
     if command == "help" {
         help()
     } else if command == "program" {
-        *prog = Some(Program::new(read_program(rl)?)?);
+        *prog = Some(Program::new(read_program(rl)?, config, format)?);
     } else if command.starts_with("load ") {
         let filename = &command["load ".len()..];
-        let mut text = String::new();
-        File::open(filename)?.read_to_string(&mut text)?;
-        *prog = Some(Program::new(text)?);
+        *prog = Some(Program::new(read_file(filename)?, config, format)?);
     } else {
-        let prog = prog.as_ref().ok_or("no program currently loaded")?;
-        ir::set_current_program(&prog.ir, || -> Result<()> {
-            match command {
-                "print" => println!("{}", prog.text),
-                "lowered" => println!("{:#?}", prog.env),
-                _ => goal(command, prog)?,
+        let prog = prog.as_mut().ok_or("no program currently loaded")?;
+        match command {
+            "print" => println!("{}", prog.text),
+            "lowered" => println!("{:#?}", ir::with_program(&prog.ir, &*prog.env)),
+            "stats" => println!("{}\n", prog.solver.stats()),
+            _ if command.starts_with("proof ") => proof(&command["proof ".len()..], prog)?,
+            _ if command.starts_with("why ") => why(&command["why ".len()..], prog)?,
+            _ if command.starts_with("answers ") => answers(&command["answers ".len()..], prog)?,
+            _ => {
+                goal(command, prog)?;
             }
-            Ok(())
-        })?
+        }
     }
 
     Ok(())
@@ -107,6 +292,11 @@ fn help() {
     println!("  load <file>  load program from <file>");
     println!("  print        print the current program");
     println!("  lowered      print the lowered program");
+    println!("  stats        print counters of the work done by the solver");
+    println!("  proof <goal> attempt to solve <goal>, printing the proof");
+    println!("  why <goal>   explain why <goal> cannot be proven");
+    println!("  answers <goal>");
+    println!("               list the distinct answers to <goal>");
     println!("  <goal>       attempt to solve <goal>");
 }
 
@@ -120,13 +310,130 @@ fn read_program(rl: &mut rustyline::Editor<()>) -> Result<String> {
     Ok(text)
 }
 
-fn goal(text: &str, prog: &Program) -> Result<()> {
+/// Solve a goal, printing the solution; returns it if there was one.
+fn goal(text: &str, prog: &mut Program) -> Result<Option<Solution>> {
+    let goal = chalk_parse::parse_goal(text)?.lower(&*prog.ir)?;
+    let goal = ir::InEnvironment::new(&ir::Environment::new(), *goal);
+    let result = prog.with_solver(|solver| solver.solve_closed_goal(goal));
+    match prog.format {
+        Format::Text => match result {
+            Ok(ref v) => println!("{}\n", ir::with_program(&prog.ir, v)),
+            Err(ref e) => println!("No possible solution: {}\n", ir::with_program(&prog.ir, e)),
+        },
+        Format::Json => println!("{}", Json::Object(vec![
+            ("goal".to_string(), Json::String(text.to_string())),
+            ("solution".to_string(), result.to_json(&prog.ir)),
+        ])),
+    }
+    Ok(result.ok())
+}
+
+/// How many answers `answers` lists.
+const ANSWER_LIMIT: usize = 20;
+
+fn answers(text: &str, prog: &mut Program) -> Result<()> {
+    let goal = chalk_parse::parse_goal(text)?.lower(&*prog.ir)?;
+    let goal = ir::InEnvironment::new(&ir::Environment::new(), *goal);
+    let answers = prog.with_solver(|solver| solver.answers(goal).take_answers(ANSWER_LIMIT));
+    println!("{}\n", ir::with_program(&prog.ir, &answers));
+    Ok(())
+}
+
+/// How many nested goals `why` explains.
+const EXPLANATION_DEPTH: usize = 4;
+
+fn why(text: &str, prog: &mut Program) -> Result<()> {
     let goal = chalk_parse::parse_goal(text)?.lower(&*prog.ir)?;
-    let mut solver = Solver::new(&prog.env, CycleStrategy::Tabling, solver::get_overflow_depth());
     let goal = ir::InEnvironment::new(&ir::Environment::new(), *goal);
-    match solver.solve_closed_goal(goal) {
-        Ok(v) => println!("{}\n", v),
-        Err(e) => println!("No possible solution: {}\n", e),
+    match prog.with_solver(|solver| solver.explain_failure(goal, EXPLANATION_DEPTH)) {
+        Ok(Some(failure)) => println!("{}", ir::with_program(&prog.ir, &failure)),
+        Ok(None) => println!("The goal has a solution.\n"),
+        Err(e @ chalk::errors::Error(chalk::errors::ErrorKind::UnsupportedWithSlg(_), _)) => Err(e)?,
+        Err(e) => println!("No possible solution: {}\n", ir::with_program(&prog.ir, &e)),
     }
     Ok(())
 }
+
+fn proof(text: &str, prog: &mut Program) -> Result<()> {
+    let goal = chalk_parse::parse_goal(text)?.lower(&*prog.ir)?;
+    let goal = ir::InEnvironment::new(&ir::Environment::new(), *goal);
+    match prog.with_solver(|solver| solver.solve_closed_goal_with_proof(goal)) {
+        Ok((v, proofs)) => {
+            println!("{}\n", ir::with_program(&prog.ir, &v));
+            for proof in &proofs {
+                print!("{}", ir::with_program(&prog.ir, proof));
+            }
+            println!();
+        }
+        Err(e @ chalk::errors::Error(chalk::errors::ErrorKind::UnsupportedWithSlg(_), _)) => Err(e)?,
+        Err(e) => println!("No possible solution: {}\n", ir::with_program(&prog.ir, &e)),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Option<Args>> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn no_args() {
+        let args = parse(&[]).unwrap().unwrap();
+        assert_eq!(args.program, None);
+        assert!(args.goals.is_empty());
+        assert!(!args.require_unique);
+        assert_eq!(args.config, SolverConfig::default());
+        assert_eq!(args.format, Format::Text);
+    }
+
+    #[test]
+    fn flags() {
+        let args = parse(&["--program", "lib.chalk",
+                           "--goal", "Foo: Clone",
+                           "--goal", "Bar: Clone",
+                           "--require-unique",
+                           "--overflow-depth", "3",
+                           "--cycle-strategy", "error",
+                           "--engine", "slg",
+                           "--format", "json"]).unwrap().unwrap();
+        assert_eq!(args.program, Some("lib.chalk".to_string()));
+        assert_eq!(args.goals, vec!["Foo: Clone", "Bar: Clone"]);
+        assert!(args.require_unique);
+        assert_eq!(args.config, SolverConfig {
+            overflow_depth: 3,
+            cycle_strategy: CycleStrategy::Error,
+            engine: Engine::Slg,
+        });
+        assert_eq!(args.format, Format::Json);
+
+        assert!(parse(&["--goal", "Foo: Clone", "--help"]).unwrap().is_none());
+    }
+
+    #[test]
+    fn invalid_args() {
+        let error = |args| parse(args).unwrap_err().to_string();
+        assert_eq!(error(&["--verbose"]), "unknown argument `--verbose`, see `--help`");
+        assert_eq!(error(&["--program"]), "missing value for `--program`");
+        assert_eq!(error(&["--goal", "Foo: Clone", "--engine"]), "missing value for `--engine`");
+        assert_eq!(error(&["--overflow-depth", "deep"]), "invalid overflow depth `deep`");
+        assert_eq!(error(&["--cycle-strategy", "retry"]),
+                   "invalid cycle strategy `retry`, expected `tabling` or `error`");
+        assert_eq!(error(&["--engine", "slow"]), "invalid engine `slow`, expected `recursive` or `slg`");
+        assert_eq!(error(&["--format", "xml"]), "invalid format `xml`, expected `text` or `json`");
+    }
+
+    #[test]
+    fn goals_file() {
+        let text = "
+            // Both of these should hold.
+            Foo: Clone
+
+              Vec<Foo>: Clone
+            // Vec<Bar>: Clone
+        ";
+        assert_eq!(goal_lines(text), vec!["Foo: Clone", "Vec<Foo>: Clone"]);
+    }
+}
@@ -69,6 +69,25 @@ impl Cast<Goal> for WellFormed {
     }
 }
 
+impl Cast<DomainGoal> for FromEnv {
+    fn cast(self) -> DomainGoal {
+        DomainGoal::FromEnv(self)
+    }
+}
+
+impl Cast<LeafGoal> for FromEnv {
+    fn cast(self) -> LeafGoal {
+        LeafGoal::DomainGoal(self.cast())
+    }
+}
+
+impl Cast<Goal> for FromEnv {
+    fn cast(self) -> Goal {
+        let wcg: LeafGoal = self.cast();
+        wcg.cast()
+    }
+}
+
 impl Cast<Goal> for Normalize {
     fn cast(self) -> Goal {
         let wcg: LeafGoal = self.cast();
@@ -76,6 +95,25 @@ impl Cast<Goal> for Normalize {
     }
 }
 
+impl Cast<DomainGoal> for Outlives {
+    fn cast(self) -> DomainGoal {
+        DomainGoal::Outlives(self)
+    }
+}
+
+impl Cast<LeafGoal> for Outlives {
+    fn cast(self) -> LeafGoal {
+        LeafGoal::DomainGoal(self.cast())
+    }
+}
+
+impl Cast<Goal> for Outlives {
+    fn cast(self) -> Goal {
+        let wcg: LeafGoal = self.cast();
+        wcg.cast()
+    }
+}
+
 impl Cast<LeafGoal> for DomainGoal {
     fn cast(self) -> LeafGoal {
         LeafGoal::DomainGoal(self)
@@ -1,26 +1,94 @@
 use petgraph::prelude::*;
+use std::fmt;
+use std::sync::Arc;
 
-use errors::Result;
-use ir::{Program, ItemId};
+use chalk_parse::ast::Span;
+use errors::*;
+use ir::{Identifier, Program, ItemId};
+use solve::solver::SolverConfig;
 
+mod orphan;
 mod solve;
 
+pub use self::orphan::is_knowable;
+
+/// Two impls of the same trait which overlap, without either of them
+/// specializing the other.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ImplOverlap {
+    pub trait_id: Identifier,
+    pub lhs: Span,
+    pub rhs: Span,
+
+    /// A trait reference covered by both impls, e.g. `Bar: Foo`.
+    pub witness: String,
+}
+
+impl fmt::Display for ImplOverlap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "overlapping impls of trait {:?}: impl at {} and impl at {} both apply to `{}`",
+               self.trait_id,
+               self.lhs,
+               self.rhs,
+               self.witness)
+    }
+}
+
+/// An impl which violates the orphan rules.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OrphanImpl {
+    pub trait_id: Identifier,
+    pub span: Span,
+}
+
+impl fmt::Display for OrphanImpl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "impl of trait {:?} at {} violates the orphan rules", self.trait_id, self.span)
+    }
+}
+
+/// An impl which overrides the value of an associated type that the impl it
+/// specializes did not declare `default`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NonDefaultOverride {
+    pub trait_id: Identifier,
+    pub name: Identifier,
+    pub span: Span,
+}
+
+impl fmt::Display for NonDefaultOverride {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "impl at {} cannot specialize associated type {:?} of trait {:?}: it is not marked `default`",
+               self.span,
+               self.name,
+               self.trait_id)
+    }
+}
 
 impl Program {
-    pub fn record_specialization_priorities(&mut self) -> Result<()> {
-        let forest = self.build_specialization_forest()?;
+    /// Check the impls of `program` for overlap, and record the priorities of
+    /// those which specialize others. The program is shared while overlaps
+    /// are looked for, so that they can be reported with its item names; it
+    /// is then updated in place, and only cloned if it is still shared.
+    pub fn record_specialization_priorities(program: &mut Arc<Program>, config: SolverConfig) -> Result<()> {
+        let forest = Program::build_specialization_forest(program, config)?;
+
+        let program = Arc::make_mut(program);
+        program.check_overridden_values(&forest)?;
 
         // Visit every root in the forest & set specialization
         // priority for the tree that is the root of.
         for root_idx in forest.externals(Direction::Incoming) {
-            self.set_priorities(root_idx, &forest, 0);
+            program.set_priorities(root_idx, &forest, 0);
         }
 
         Ok(())
     }
 
     // Build the forest of specialization relationships.
-    fn build_specialization_forest(&self) -> Result<Graph<ItemId, ()>> {
+    fn build_specialization_forest(program: &Arc<Program>, config: SolverConfig) -> Result<Graph<ItemId, ()>> {
         // The forest is returned as a graph but built as a GraphMap; this is
         // so that we never add multiple nodes with the same ItemId.
         let mut forest = DiGraphMap::new();
@@ -28,20 +96,64 @@ impl Program {
         // Find all specializations (implemented in coherence/solve)
         // Record them in the forest by adding an edge from the less special
         // to the more special.
-        self.visit_specializations(|less_special, more_special| {
+        Program::visit_specializations(program, config, |less_special, more_special| {
             forest.add_edge(less_special, more_special, ());
         })?;
 
         Ok(forest.into_graph())
     }
 
+    // Only values declared `default` may be overridden by a more specialized
+    // impl. Since the forest records every specialization relationship (not
+    // only the direct ones), checking each edge covers all ancestors.
+    fn check_overridden_values(&self, forest: &Graph<ItemId, ()>) -> Result<()> {
+        let mut overrides = vec![];
+        for edge in forest.raw_edges() {
+            let less_special = &self.impl_data[&forest[edge.source()]].binders.value;
+            let more_special_id = forest[edge.target()];
+            let more_special = &self.impl_data[&more_special_id].binders.value;
+
+            for value in &more_special.associated_ty_values {
+                let overrides_final = less_special.associated_ty_values
+                    .iter()
+                    .any(|v| v.associated_ty_id == value.associated_ty_id && !v.default);
+
+                if overrides_final {
+                    let datum = &self.associated_ty_data[&value.associated_ty_id];
+                    let over = NonDefaultOverride {
+                        trait_id: self.type_kinds[&datum.trait_id].name,
+                        name: datum.name,
+                        span: self.item_spans[&more_special_id],
+                    };
+                    // An impl may override the same value of several
+                    // ancestors; it is reported once.
+                    if !overrides.contains(&over) {
+                        overrides.push(over);
+                    }
+                }
+            }
+        }
+
+        if overrides.is_empty() {
+            Ok(())
+        } else {
+            overrides.sort_by_key(|over| (over.span.lo, over.name.to_string()));
+            Err(Error::from_kind(ErrorKind::OverrideNonDefault(overrides)))
+        }
+    }
+
     // Recursively set priorities for those node and all of its children.
     fn set_priorities(&mut self, idx: NodeIndex, forest: &Graph<ItemId, ()>, p: usize) {
     
-        // Get the impl datum recorded at this node and reset its priority
+        // Get the impl datum recorded at this node and reset its priority. An
+        // impl may be reached through several chains, in which case it gets the
+        // priority of the longest one.
         {
             let impl_id = forest.node_weight(idx).expect("index should be a valid index into graph");
             let impl_datum = self.impl_data.get_mut(impl_id).expect("node should be valid impl id");
+            if impl_datum.binders.value.specialization_priority > p {
+                return;
+            }
             impl_datum.binders.value.specialization_priority = p;
         }
 
@@ -0,0 +1,139 @@
+use errors::*;
+use ir::*;
+
+use super::OrphanImpl;
+
+impl Program {
+    /// Check every impl declared in the current crate against the orphan
+    /// rules of RFC 1023, reporting all the impls which violate them. Impls
+    /// marked `#[upstream]` are assumed to have been checked when their own
+    /// crate was compiled.
+    pub fn perform_orphan_check(&self) -> Result<()> {
+        let mut impl_ids: Vec<_> = self.impl_data.keys().cloned().collect();
+        impl_ids.sort();
+
+        let mut orphans = vec![];
+        for impl_id in impl_ids {
+            let impl_datum = &self.impl_data[&impl_id].binders.value;
+            if impl_datum.upstream {
+                continue;
+            }
+
+            let trait_ref = impl_datum.trait_ref.trait_ref();
+            if !self.is_orphan(trait_ref) {
+                continue;
+            }
+
+            orphans.push(OrphanImpl {
+                trait_id: self.type_kinds[&trait_ref.trait_id].name,
+                span: self.item_spans[&impl_id],
+            });
+        }
+
+        if orphans.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::from_kind(ErrorKind::FailedOrphanCheck(orphans)))
+        }
+    }
+
+    // Given `impl<P1..Pn> Trait<T1..Tn> for T0`, the impl is allowed if
+    // `Trait` is local, or if at least one of `T0..Tn` is a local type and,
+    // for the first such type `Ti`, none of `T0..Ti` (excluding `Ti`) contains
+    // an uncovered type parameter. Here `trait_ref` is still under the impl
+    // binders, so `Ty::Var` refers to one of the `Pi`.
+    fn is_orphan(&self, trait_ref: &TraitRef) -> bool {
+        if !self.trait_data[&trait_ref.trait_id].binders.value.upstream {
+            return false;
+        }
+
+        let struct_datum = |id| self.struct_data.get(&id);
+        for ty in type_parameters(&trait_ref.parameters) {
+            if is_local_ty(&struct_datum, ty) {
+                return false;
+            }
+
+            if has_uncovered_parameters(&struct_datum, ty) {
+                return true;
+            }
+        }
+
+        true
+    }
+}
+
+/// See `ProgramDatabase::is_knowable`.
+pub fn is_knowable<D: ProgramDatabase + ?Sized>(program: &D, trait_ref: &TraitRef) -> bool {
+    let struct_datum = |id| program.struct_datum(id);
+    let input_types = type_parameters(&trait_ref.parameters);
+
+    if input_types.iter().any(|ty| may_be_downstream(&struct_datum, ty)) {
+        return false;
+    }
+
+    let trait_datum = &program.trait_datum(trait_ref.trait_id).binders.value;
+    if !trait_datum.upstream || trait_datum.fundamental {
+        return true;
+    }
+
+    input_types.iter().any(|ty| is_local_ty(&struct_datum, ty))
+}
+
+fn type_parameters(parameters: &[Parameter]) -> Vec<&Ty> {
+    parameters.iter().filter_map(|p| p.as_ref().ty()).collect()
+}
+
+/// Whether `name` is a `#[fundamental]` struct. As in Rust, references are
+/// fundamental too, while the other built-in types are foreign to every crate.
+fn is_fundamental<'a>(struct_datum: &Fn(ItemId) -> Option<&'a StructDatum>, name: TypeName) -> bool {
+    match name {
+        TypeName::ItemId(id) => struct_datum(id).map_or(false, |s| s.binders.value.fundamental),
+        TypeName::Ref(_) => true,
+        _ => false,
+    }
+}
+
+/// A type is local if it is a struct of the current crate, or a
+/// `#[fundamental]` type applied to a local type (e.g. `Box<Local>`). Trait
+/// objects are conservatively treated as foreign, even if their trait is local.
+fn is_local_ty<'a>(struct_datum: &Fn(ItemId) -> Option<&'a StructDatum>, ty: &Ty) -> bool {
+    match *ty {
+        Ty::Apply(ApplicationTy { name, ref parameters }) => {
+            let upstream = match name {
+                TypeName::ItemId(id) => struct_datum(id).map_or(true, |s| s.binders.value.upstream),
+                _ => true,
+            };
+            !upstream || (is_fundamental(struct_datum, name) &&
+                          type_parameters(parameters).into_iter().any(|ty| is_local_ty(struct_datum, ty)))
+        }
+        _ => false,
+    }
+}
+
+/// Whether an impl parameter appears in `ty` without being covered by a
+/// (non-fundamental) type constructor. As in rustc, projections are
+/// treated like type parameters.
+fn has_uncovered_parameters<'a>(struct_datum: &Fn(ItemId) -> Option<&'a StructDatum>, ty: &Ty) -> bool {
+    match *ty {
+        Ty::Var(_) | Ty::Projection(_) => true,
+        Ty::Apply(ApplicationTy { name, ref parameters }) => {
+            is_fundamental(struct_datum, name) &&
+                type_parameters(parameters).into_iter().any(|ty| has_uncovered_parameters(struct_datum, ty))
+        }
+        Ty::ForAll(_) | Ty::Dyn(_) => false,
+    }
+}
+
+/// Whether `ty` could be a local type from the point of view of a downstream
+/// crate, once its unknown parts are replaced by that crate's types.
+fn may_be_downstream<'a>(struct_datum: &Fn(ItemId) -> Option<&'a StructDatum>, ty: &Ty) -> bool {
+    match *ty {
+        Ty::Var(_) | Ty::Projection(_) => true,
+        Ty::Apply(ApplicationTy { name: TypeName::ForAll(_), .. }) => true,
+        Ty::Apply(ApplicationTy { name, ref parameters }) => {
+            is_fundamental(struct_datum, name) &&
+                type_parameters(parameters).into_iter().any(|ty| may_be_downstream(struct_datum, ty))
+        }
+        Ty::ForAll(_) | Ty::Dyn(_) => false,
+    }
+}
@@ -2,22 +2,32 @@ use std::sync::Arc;
 
 use itertools::Itertools;
 use errors::*;
+use fold::Subst;
 use ir::*;
-use solve::solver::{self, Solver, CycleStrategy};
+use solve::Guidance;
+use solve::solver::{Solver, SolverConfig};
+
+use super::ImplOverlap;
 
 impl Program {
-    pub(super) fn visit_specializations<F>(&self, mut record_specialization: F) -> Result<()>
+    /// Overlaps are reported with the item names of `program`.
+    pub(super) fn visit_specializations<F>(program: &Arc<Program>,
+                                           config: SolverConfig,
+                                           mut record_specialization: F)
+                                           -> Result<()>
         where F: FnMut(ItemId, ItemId)
     {
-        let mut solver = Solver::new(
-            &Arc::new(self.environment()),
-            CycleStrategy::Tabling,
-            solver::get_overflow_depth()
-        );
+        // Overlap must also take into account impls that other crates could
+        // add in the future, hence the compatible modality.
+        let mut solver = Solver::new(&Arc::new(program.environment()), config).in_compatible_modality();
 
         // Create a vector of references to impl datums, sorted by trait ref
-        let impl_data = self.impl_data.iter().sorted_by(|&(_, lhs), &(_, rhs)| {
-            lhs.binders.value.trait_ref.trait_ref().trait_id.cmp(&rhs.binders.value.trait_ref.trait_ref().trait_id)
+        // and then by position in the program, so that overlaps are reported
+        // in a deterministic order.
+        let impl_data = program.impl_data.iter().sorted_by(|&(l_id, lhs), &(r_id, rhs)| {
+            let lhs_trait = lhs.binders.value.trait_ref.trait_ref().trait_id;
+            let rhs_trait = rhs.binders.value.trait_ref.trait_ref().trait_id;
+            (lhs_trait, l_id).cmp(&(rhs_trait, r_id))
         });
 
         // Group impls by trait.
@@ -26,7 +36,9 @@ impl Program {
         });
 
 
-        // Iterate over every pair of impls for the same trait.
+        // Iterate over every pair of impls for the same trait, collecting
+        // all of the overlaps so that they can be reported at once.
+        let mut overlaps = vec![];
         for (trait_id, impls) in &impl_groupings {
             let impls: Vec<(&ItemId, &ImplDatum)> = impls.collect();
 
@@ -39,21 +51,36 @@ impl Program {
                 // Check if the impls overlap, then if they do, check if one specializes
                 // the other. Note that specialization can only run one way - if both
                 // specialization checks return *either* true or false, that's an error.
-                if solver.overlaps(lhs, rhs) {
-                    match (solver.specializes(lhs, rhs), solver.specializes(rhs, lhs)) {
+                if let Some(witness) = solver.overlaps(lhs, rhs)? {
+                    match (solver.specializes(lhs, rhs)?, solver.specializes(rhs, lhs)?) {
                         (true, false)   => record_specialization(l_id, r_id),
                         (false, true)   => record_specialization(r_id, l_id),
                         (_, _)          => {
-                            let trait_id = self.type_kinds.get(&trait_id).unwrap().name;
-                            return Err(Error::from_kind(ErrorKind::OverlappingImpls(trait_id)))
+                            overlaps.push(ImplOverlap {
+                                trait_id: program.type_kinds[&trait_id].name,
+                                lhs: program.item_spans[&l_id],
+                                rhs: program.item_spans[&r_id],
+                                witness: describe_witness(program, witness),
+                            });
                         }
                     }
                 }
             }
         }
 
-        Ok(())
+        if overlaps.is_empty() {
+            Ok(())
+        } else {
+            overlaps.sort_by_key(|o| (o.lhs.lo, o.rhs.lo));
+            Err(Error::from_kind(ErrorKind::OverlappingImpls(overlaps)))
+        }
     }
+
+}
+
+/// Render a witness trait reference as `Bar: Foo<Baz>`, with item names resolved.
+fn describe_witness(program: &Arc<Program>, witness: TraitRef) -> String {
+    format!("{:?}", with_program(program, &DomainGoal::Implemented(witness)))
 }
 
 impl Solver {
@@ -87,7 +114,14 @@ impl Solver {
     //  Generates:
     //      exists<T, U> { Vec<T> = Vec<U>, T: Bar, U: Baz }
     //
-    fn overlaps(&mut self, lhs: &ImplDatum, rhs: &ImplDatum) -> bool {
+    // If the impls overlap, we return the trait reference of `lhs` with the
+    // substitution found by the solver applied, as an example of a trait
+    // reference which both impls cover. Variables that were not determined by
+    // the solver are left as (canonical) variables.
+    //
+    // Overflow is reported as an error, since it tells us nothing about
+    // whether the impls overlap.
+    fn overlaps(&mut self, lhs: &ImplDatum, rhs: &ImplDatum) -> Result<Option<TraitRef>> {
         let lhs_len = lhs.binders.len();
 
         // Join the two impls' binders together
@@ -112,13 +146,39 @@ impl Solver {
                     .map(|wc| Goal::Leaf(LeafGoal::DomainGoal(wc)));
 
         // Join all the goals we've created together with And, then quantify them
-        // over the joined binders. This is our query.
-        let goal = params_goals.chain(wc_goals)
+        // over the joined binders. This is our query. Obligations are processed
+        // last-in first-out, so putting the equality goals last means the impl
+        // headers are unified before we try (possibly deeply recursive) where
+        // clauses.
+        let goal = wc_goals.chain(params_goals)
                     .fold1(|goal, leaf| Goal::And(Box::new(goal), Box::new(leaf)))
                     .expect("Every trait takes at least one input type")
                     .quantify(QuantifierKind::Exists, binders);
 
-        self.solve_closed_goal(InEnvironment::empty(goal)).ok().map_or(false, |sol| !sol.cannot_be_proven())
+        let solution = match self.solve_closed_goal(InEnvironment::empty(goal)) {
+            Ok(ref solution) if solution.cannot_be_proven() => return Ok(None),
+            Ok(solution) => solution,
+            Err(e) => return if e.is_overflow() { Err(e) } else { Ok(None) },
+        };
+
+        // The substitution of the solution gives the values of the
+        // existential variables in the order they are bound, starting with
+        // those of `lhs`.
+        let trait_ref = lhs.binders.value.trait_ref.trait_ref();
+        let subst = match solution.into_guidance() {
+            Guidance::Definite(subst) | Guidance::Suggested(subst) => subst.value,
+            Guidance::Unknown => return Ok(Some(trait_ref.clone())),
+        };
+        let mut tys = subst.tys.into_iter().map(|(_, ty)| ty);
+        let mut lifetimes = subst.lifetimes.into_iter().map(|(_, lifetime)| lifetime);
+        let parameters: Vec<Parameter> = lhs.binders.binders.iter().enumerate().map(|(i, kind)| {
+            match *kind {
+                ParameterKind::Ty(()) => ParameterKind::Ty(tys.next().unwrap_or(Ty::Var(i))),
+                ParameterKind::Lifetime(()) => ParameterKind::Lifetime(lifetimes.next().unwrap_or(Lifetime::Var(i))),
+            }
+        }).collect();
+
+        Ok(Some(Subst::apply(&parameters, trait_ref)))
     }
 
     // Test for specialization.
@@ -137,35 +197,40 @@ impl Solver {
     //    }
     //  }
     // }
-    fn specializes(&mut self, less_special: &ImplDatum, more_special: &ImplDatum) -> bool {
+    fn specializes(&mut self, less_special: &ImplDatum, more_special: &ImplDatum) -> Result<bool> {
         // Negative impls cannot specialize.
         if !less_special.binders.value.trait_ref.is_positive() || !more_special.binders.value.trait_ref.is_positive() {
-            return false;
+            return Ok(false);
         }
 
-        let more_len = more_special.binders.len();
+        let less_len = less_special.binders.len();
 
-        // Create parameter equality goals.
-        let more_special_params = params(more_special).iter().cloned();
-        let less_special_params = params(less_special).iter().map(|p| p.up_shift(more_len));
+        // Create parameter equality goals. The less special parameters are bound
+        // by the innermost `exists`, so the more special ones must be shifted
+        // past them.
+        let more_special_params = params(more_special).iter().map(|p| p.up_shift(less_len));
+        let less_special_params = params(less_special).iter().cloned();
         let params_goals = more_special_params.zip(less_special_params)
                             .map(|(a, b)| Goal::Leaf(LeafGoal::EqGoal(EqGoal { a, b })));
 
         // Create the where clause goals.
         let more_special_wc = more_special.binders.value.where_clauses.clone();
         let less_special_wc = less_special.binders.value.where_clauses.iter().map(|wc| {
-            Goal::Leaf(LeafGoal::DomainGoal(wc.up_shift(more_len)))
+            Goal::Leaf(LeafGoal::DomainGoal(wc.clone()))
         });
 
-        // Join all of the goals together.
-        let goal = params_goals.chain(less_special_wc)
+        // Join all of the goals together, equality goals last (see `overlaps`).
+        let goal = less_special_wc.chain(params_goals)
                     .fold1(|goal, leaf| Goal::And(Box::new(goal), Box::new(leaf)))
                     .expect("Every trait takes at least one input type")
                     .quantify(QuantifierKind::Exists, less_special.binders.binders.clone())
                     .implied_by(more_special_wc)
                     .quantify(QuantifierKind::ForAll, more_special.binders.binders.clone());
 
-        self.solve_closed_goal(InEnvironment::empty(goal)).ok().map_or(false, |sol| sol.is_unique())
+        match self.solve_closed_goal(InEnvironment::empty(goal)) {
+            Ok(solution) => Ok(solution.is_unique()),
+            Err(e) => if e.is_overflow() { Err(e) } else { Ok(false) },
+        }
     }
 }
 
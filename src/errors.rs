@@ -1,5 +1,7 @@
 use chalk_parse::{self, ast};
+use coherence;
 use ir;
+use lower;
 
 error_chain! {
     links {
@@ -29,10 +31,62 @@ error_chain! {
             display("expected a trait, found `{}`, which is not a trait", identifier.str)
         }
 
-        OverlappingImpls(trait_id: ir::Identifier) {
+        OverlappingImpls(overlaps: Vec<coherence::ImplOverlap>) {
             description("overlapping impls")
-            display("overlapping impls of trait {:?}", trait_id)
+            display("{}", overlaps.iter().map(|o| o.to_string()).collect::<Vec<_>>().join("\n"))
+        }
+
+        FailedOrphanCheck(orphans: Vec<coherence::OrphanImpl>) {
+            description("impls violate the orphan rules")
+            display("{}", orphans.iter().map(|o| o.to_string()).collect::<Vec<_>>().join("\n"))
+        }
+
+        IllFormedItems(items: Vec<lower::wf::IllFormedItem>) {
+            description("ill-formed items")
+            display("{}", items.iter().map(|i| i.to_string()).collect::<Vec<_>>().join("\n"))
+        }
+
+        NotObjectSafe(trait_id: ir::Identifier, violation: String) {
+            description("trait is not object safe")
+            display("the trait `{}` cannot be made into an object: {}", trait_id, violation)
+        }
+
+        CannotEquate(a: ir::Ty, b: ir::Ty) {
+            description("cannot equate types")
+            display("cannot equate `{:?}` and `{:?}`", a, b)
+        }
+
+        Overflow(stack: Vec<ir::FullyReducedGoal>) {
+            description("overflow depth reached")
+            display("overflow depth reached while solving:\n{}",
+                    stack.iter().map(|goal| format!("  {:?}", goal)).collect::<Vec<_>>().join("\n"))
+        }
+
+        UnsupportedWithSlg(feature: &'static str) {
+            description("unsupported with the SLG engine")
+            display("{} are unsupported with the SLG engine", feature)
+        }
+
+        MalformedTestFile(line: usize, message: String) {
+            description("malformed test file")
+            display("malformed test file at line {}: {}", line, message)
+        }
+
+        OverrideNonDefault(overrides: Vec<coherence::NonDefaultOverride>) {
+            description("cannot specialize a non-default associated type")
+            display("{}", overrides.iter().map(|o| o.to_string()).collect::<Vec<_>>().join("\n"))
         }
     }
 }
 
+
+impl Error {
+    /// Whether the solver gave up because it reached its overflow depth; such
+    /// an error says nothing about whether the goal holds.
+    pub fn is_overflow(&self) -> bool {
+        match *self.kind() {
+            ErrorKind::Overflow(_) => true,
+            _ => false,
+        }
+    }
+}
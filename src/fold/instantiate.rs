@@ -26,6 +26,13 @@ impl QuantifiedTy {
     }
 }
 
+impl<T: Fold> Binders<T> {
+    pub fn subst(&self, parameters: &[Parameter]) -> T::Result {
+        assert_eq!(self.binders.len(), parameters.len());
+        Subst::apply(parameters, &self.value)
+    }
+}
+
 macro_rules! subst_method {
     ($t:ty) => {
         impl $t {
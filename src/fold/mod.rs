@@ -103,6 +103,7 @@ impl Fold for Ty {
             Ty::ForAll(ref quantified_ty) => {
                 Ok(Ty::ForAll(quantified_ty.fold_with(folder, binders)?))
             }
+            Ty::Dyn(ref bounds) => Ok(Ty::Dyn(bounds.fold_with(folder, binders)?)),
         }
     }
 }
@@ -176,6 +177,7 @@ copy_fold!(UniverseIndex);
 copy_fold!(ItemId);
 copy_fold!(TypeName);
 copy_fold!(usize);
+copy_fold!(bool);
 copy_fold!(QuantifierKind);
 
 macro_rules! enum_fold {
@@ -197,10 +199,11 @@ macro_rules! enum_fold {
 
 enum_fold!(PolarizedTraitRef[] { Positive(a), Negative(a) });
 enum_fold!(ParameterKind[T,L] { Ty(a), Lifetime(a) } where T: Fold, L: Fold);
-enum_fold!(DomainGoal[] { Implemented(a), Normalize(a), WellFormed(a) });
+enum_fold!(DomainGoal[] { Implemented(a), Normalize(a), WellFormed(a), FromEnv(a), Outlives(a) });
 enum_fold!(WellFormed[] { Ty(a), TraitRef(a) });
+enum_fold!(FromEnv[] { Ty(a), TraitRef(a) });
 enum_fold!(LeafGoal[] { EqGoal(a), DomainGoal(a) });
-enum_fold!(Constraint[] { LifetimeEq(a, b) });
+enum_fold!(Constraint[] { LifetimeEq(a, b), Outlives(a, b) });
 enum_fold!(Goal[] { Quantified(qkind, subgoal), Implies(wc, subgoal), And(g1, g2), Not(g), Leaf(wc) });
 
 macro_rules! struct_fold {
@@ -220,7 +223,8 @@ struct_fold!(ApplicationTy { name, parameters });
 struct_fold!(ProjectionTy { associated_ty_id, parameters });
 struct_fold!(TraitRef { trait_id, parameters });
 struct_fold!(Normalize { projection, ty });
-struct_fold!(AssociatedTyValue { associated_ty_id, value });
+struct_fold!(Outlives { a, b });
+struct_fold!(AssociatedTyValue { associated_ty_id, value, default });
 struct_fold!(AssociatedTyValueBound { ty, where_clauses });
 struct_fold!(Environment { universe, clauses });
 struct_fold!(InEnvironment[F] { environment, goal } where F: Fold);
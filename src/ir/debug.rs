@@ -31,6 +31,23 @@ impl Debug for TypeName {
             TypeName::ItemId(id) => write!(fmt, "{:?}", id),
             TypeName::ForAll(universe) => write!(fmt, "!{}", universe.counter),
             TypeName::AssociatedType(assoc_ty) => write!(fmt, "{:?}", assoc_ty),
+            TypeName::Ref(Mutability::Not) => write!(fmt, "&_"),
+            TypeName::Ref(Mutability::Mut) => write!(fmt, "&mut _"),
+            TypeName::Tuple(arity) => {
+                write!(fmt, "(")?;
+                for i in 0..arity {
+                    write!(fmt, "{}_", if i > 0 { ", " } else { "" })?;
+                }
+                write!(fmt, "{})", if arity == 1 { "," } else { "" })
+            }
+            TypeName::Slice => write!(fmt, "[_]"),
+            TypeName::FnPtr(arity) => {
+                write!(fmt, "fn(")?;
+                for i in 0..arity {
+                    write!(fmt, "{}_", if i > 0 { ", " } else { "" })?;
+                }
+                write!(fmt, ") -> _")
+            }
         }
     }
 }
@@ -51,10 +68,47 @@ impl Debug for Ty {
             Ty::Apply(ref apply) => write!(fmt, "{:?}", apply),
             Ty::Projection(ref proj) => write!(fmt, "{:?}", proj),
             Ty::ForAll(ref quantified_ty) => write!(fmt, "{:?}", quantified_ty),
+            Ty::Dyn(ref bounds) => fmt_dyn(fmt, bounds),
         }
     }
 }
 
+/// Writes `dyn Iterator<Item = u32> + 'a`; when the program is known, the
+/// bindings of associated types are shown along with their trait.
+fn fmt_dyn(fmt: &mut Formatter, bounds: &Binders<Vec<DomainGoal>>) -> Result<(), Error> {
+    let parts: Vec<String> = with_current_program(|p| {
+        let binding = |bound: &DomainGoal| match (p, bound) {
+            (Some(program), &DomainGoal::Normalize(ref normalize)) => {
+                let (associated_ty_data, _, other_params) = program.split_projection(&normalize.projection);
+                Some((associated_ty_data.trait_id,
+                      format!("{}{:?} = {:?}", associated_ty_data.name, Angle(other_params), normalize.ty)))
+            }
+            _ => None,
+        };
+
+        bounds.value.iter().filter_map(|bound| match *bound {
+            DomainGoal::Implemented(ref trait_ref) => {
+                let args: Vec<_> = trait_ref.parameters[1..].iter()
+                    .map(|p| format!("{:?}", p))
+                    .chain(bounds.value.iter()
+                                 .filter_map(&binding)
+                                 .filter(|&(trait_id, _)| trait_id == trait_ref.trait_id)
+                                 .map(|(_, binding)| binding))
+                    .collect();
+                if args.is_empty() {
+                    Some(format!("{:?}", trait_ref.trait_id))
+                } else {
+                    Some(format!("{:?}<{}>", trait_ref.trait_id, args.join(", ")))
+                }
+            }
+            DomainGoal::Normalize(_) if p.is_some() => None,
+            DomainGoal::Outlives(ref outlives) => Some(format!("{:?}", outlives.b)),
+            ref goal => Some(format!("{:?}", goal)),
+        }).collect()
+    });
+    write!(fmt, "dyn {}", parts.join(" + "))
+}
+
 impl Debug for QuantifiedTy {
     fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
         // FIXME -- we should introduce some names or something here
@@ -74,7 +128,18 @@ impl Debug for Lifetime {
 
 impl Debug for ApplicationTy {
     fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
-        write!(fmt, "{:?}{:?}", self.name, Angle(&self.parameters))
+        let parameters = &self.parameters;
+        match self.name {
+            TypeName::Ref(Mutability::Not) => write!(fmt, "&{:?} {:?}", parameters[0], parameters[1]),
+            TypeName::Ref(Mutability::Mut) => write!(fmt, "&{:?} mut {:?}", parameters[0], parameters[1]),
+            TypeName::Tuple(1) => write!(fmt, "({:?},)", parameters[0]),
+            TypeName::Tuple(_) => write!(fmt, "({:?})", Comma(parameters)),
+            TypeName::Slice => write!(fmt, "[{:?}]", parameters[0]),
+            TypeName::FnPtr(arity) => {
+                write!(fmt, "fn({:?}) -> {:?}", Comma(&parameters[..arity]), parameters[arity])
+            }
+            _ => write!(fmt, "{:?}{:?}", self.name, Angle(parameters)),
+        }
     }
 }
 
@@ -117,15 +182,22 @@ pub struct Angle<'a, T: 'a>(pub &'a [T]);
 impl<'a, T: Debug> Debug for Angle<'a, T> {
     fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
         if self.0.len() > 0 {
-            write!(fmt, "<")?;
-            for (index, elem) in self.0.iter().enumerate() {
-                if index > 0 {
-                    write!(fmt, ", {:?}", elem)?;
-                } else {
-                    write!(fmt, "{:?}", elem)?;
-                }
+            write!(fmt, "<{:?}>", Comma(self.0))?;
+        }
+        Ok(())
+    }
+}
+
+pub struct Comma<'a, T: 'a>(pub &'a [T]);
+
+impl<'a, T: Debug> Debug for Comma<'a, T> {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        for (index, elem) in self.0.iter().enumerate() {
+            if index > 0 {
+                write!(fmt, ", {:?}", elem)?;
+            } else {
+                write!(fmt, "{:?}", elem)?;
             }
-            write!(fmt, ">")?;
         }
         Ok(())
     }
@@ -145,6 +217,12 @@ impl Debug for Normalize {
     }
 }
 
+impl Debug for Outlives {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        write!(fmt, "{:?}: {:?}", self.a, self.b)
+    }
+}
+
 impl Debug for DomainGoal {
     fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
         match *self {
@@ -157,6 +235,8 @@ impl Debug for DomainGoal {
                        Angle(&n.parameters[1..]))
             }
             DomainGoal::WellFormed(ref n) => write!(fmt, "{:?}", n),
+            DomainGoal::FromEnv(ref n) => write!(fmt, "{:?}", n),
+            DomainGoal::Outlives(ref n) => write!(fmt, "{:?}", n),
         }
     }
 }
@@ -180,6 +260,16 @@ impl Debug for WellFormed {
     }
 }
 
+impl Debug for FromEnv {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        let value: &Debug = match *self {
+            FromEnv::Ty(ref t) => t,
+            FromEnv::TraitRef(ref t) => t,
+        };
+        write!(fmt, "FromEnv({:?})", value)
+    }
+}
+
 impl Debug for EqGoal {
     fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
         write!(fmt, "({:?} = {:?})", self.a, self.b)
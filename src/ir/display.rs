@@ -0,0 +1,29 @@
+use std::fmt::{Debug, Display, Error, Formatter};
+use std::sync::Arc;
+
+use super::Program;
+use super::tls::set_current_program;
+
+/// Formats a value of the IR with the item names of an explicitly given
+/// program, e.g. `Vec<Foo>: Clone` rather than `ItemId { index: 2 }<...>`.
+/// The program is only made current while the value is being formatted.
+pub struct WithProgram<'a, T: ?Sized + 'a> {
+    program: &'a Arc<Program>,
+    value: &'a T,
+}
+
+pub fn with_program<'a, T: ?Sized>(program: &'a Arc<Program>, value: &'a T) -> WithProgram<'a, T> {
+    WithProgram { program, value }
+}
+
+impl<'a, T: ?Sized + Debug> Debug for WithProgram<'a, T> {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        set_current_program(self.program, || self.value.fmt(fmt))
+    }
+}
+
+impl<'a, T: ?Sized + Display> Display for WithProgram<'a, T> {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        set_current_program(self.program, || self.value.fmt(fmt))
+    }
+}
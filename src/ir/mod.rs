@@ -1,5 +1,6 @@
 use cast::Cast;
 use chalk_parse::ast;
+use fold::Subst;
 use lalrpop_intern::InternedString;
 use solve::infer::{TyInferenceVariable, LifetimeInferenceVariable};
 use std::collections::{HashSet, HashMap, BTreeMap};
@@ -29,6 +30,9 @@ pub struct Program {
 
     /// For each default impl (automatically generated for auto traits):
     pub default_impl_data: Vec<DefaultImplDatum>,
+
+    /// For each struct/trait/impl, its location in the source (used for error reporting):
+    pub item_spans: HashMap<ItemId, ast::Span>,
 }
 
 impl Program {
@@ -47,6 +51,9 @@ impl Program {
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct ProgramEnvironment {
+    /// For each struct (used for coherence):
+    pub struct_data: HashMap<ItemId, StructDatum>,
+
     /// For each trait (used for debugging):
     pub trait_data: HashMap<ItemId, TraitDatum>,
 
@@ -55,9 +62,50 @@ pub struct ProgramEnvironment {
 
     /// Compiled forms of the above:
     pub program_clauses: Vec<ProgramClause>,
+
+    /// Positions in `program_clauses`, indexed by the shape of their consequence:
+    clause_index: ClauseIndex,
 }
 
 impl ProgramEnvironment {
+    pub fn new(struct_data: HashMap<ItemId, StructDatum>,
+               trait_data: HashMap<ItemId, TraitDatum>,
+               associated_ty_data: HashMap<ItemId, AssociatedTyDatum>,
+               program_clauses: Vec<ProgramClause>)
+               -> Self {
+        let mut clause_index = ClauseIndex::default();
+        for (i, clause) in program_clauses.iter().enumerate() {
+            let (key, self_ty) = clause.implication.value.consequence.clause_key();
+            clause_index.all.entry(key).or_insert_with(Vec::new).push(i);
+            match self_ty {
+                Some(id) => clause_index.by_self_ty.entry((key, id)).or_insert_with(Vec::new).push(i),
+                None => clause_index.generic.entry(key).or_insert_with(Vec::new).push(i),
+            }
+        }
+
+        ProgramEnvironment { struct_data, trait_data, associated_ty_data, program_clauses, clause_index }
+    }
+
+    /// The program clauses whose consequence may unify with `goal`. This is
+    /// a superset of the clauses that are actually applicable.
+    pub fn clauses_for(&self, goal: &DomainGoal) -> Vec<&ProgramClause> {
+        let (key, self_ty) = goal.clause_key();
+        let index = &self.clause_index;
+        let mut positions: Vec<usize> = match self_ty {
+            Some(id) => {
+                index.by_self_ty.get(&(key, id)).into_iter()
+                    .chain(index.generic.get(&key))
+                    .flat_map(|positions| positions.iter().cloned())
+                    .collect()
+            }
+            None => index.all.get(&key).map_or(vec![], |positions| positions.clone()),
+        };
+
+        // Keep the clauses in program order.
+        positions.sort();
+        positions.into_iter().map(|i| &self.program_clauses[i]).collect()
+    }
+
     /// Used for debugging output
     pub fn split_projection<'p>(&self, projection: &'p ProjectionTy)
                             -> (&AssociatedTyDatum, &'p [Parameter], &'p [Parameter]) {
@@ -71,6 +119,79 @@ impl ProgramEnvironment {
     }
 }
 
+/// The knowledge about a program that the solver consults. A
+/// `ProgramEnvironment` holds all of it up front, but other implementations
+/// may compute it on demand, e.g. in a tool which only discovers the impls of
+/// a program as they are needed.
+pub trait ProgramDatabase {
+    /// The program clauses whose consequence may unify with `goal`, in
+    /// program order. This may be a superset of the clauses that are
+    /// actually applicable. The clauses for built-in types are not among
+    /// them: the solvers add `DomainGoal::builtin_clauses` themselves.
+    fn program_clauses_for(&self, goal: &DomainGoal) -> Vec<ProgramClause>;
+
+    fn trait_datum(&self, id: ItemId) -> &TraitDatum;
+
+    fn associated_ty_datum(&self, id: ItemId) -> &AssociatedTyDatum;
+
+    /// The datum of the struct `id`, or `None` if `id` is not a struct.
+    fn struct_datum(&self, id: ItemId) -> Option<&StructDatum>;
+
+    /// Whether the current crate may conclude that `trait_ref` does not hold
+    /// because no impl proves it. This is not the case if a downstream crate
+    /// could instantiate `trait_ref` with one of its own types and implement
+    /// it, nor if an upstream crate could add an impl for it without this
+    /// being considered a breaking change. Inference variables and skolemized
+    /// types in `trait_ref` may stand for downstream types.
+    fn is_knowable(&self, trait_ref: &TraitRef) -> bool {
+        ::coherence::is_knowable(self, trait_ref)
+    }
+}
+
+impl ProgramDatabase for ProgramEnvironment {
+    fn program_clauses_for(&self, goal: &DomainGoal) -> Vec<ProgramClause> {
+        self.clauses_for(goal).into_iter().cloned().collect()
+    }
+
+    fn trait_datum(&self, id: ItemId) -> &TraitDatum {
+        &self.trait_data[&id]
+    }
+
+    fn associated_ty_datum(&self, id: ItemId) -> &AssociatedTyDatum {
+        &self.associated_ty_data[&id]
+    }
+
+    fn struct_datum(&self, id: ItemId) -> Option<&StructDatum> {
+        self.struct_data.get(&id)
+    }
+}
+
+/// The part of a domain goal which a program clause must agree on in order
+/// to be applicable to it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ClauseKey {
+    Implemented(ItemId),
+    Normalize(ItemId),
+    WellFormedTy,
+    WellFormedTraitRef(ItemId),
+    FromEnvTy,
+    FromEnvTraitRef(ItemId),
+    Outlives,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct ClauseIndex {
+    /// All the clauses for a given key.
+    all: HashMap<ClauseKey, Vec<usize>>,
+
+    /// Clauses whose self type is not a known type constructor, and which
+    /// may thus apply whatever the self type of the goal is.
+    generic: HashMap<ClauseKey, Vec<usize>>,
+
+    /// Clauses whose self type is the given type constructor.
+    by_self_ty: HashMap<(ClauseKey, ItemId), Vec<usize>>,
+}
+
 #[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 /// The set of assumptions we've made so far, and the current number of
 /// universal (forall) quantifiers we're within.
@@ -135,6 +256,19 @@ pub enum TypeName {
 
     /// an associated type like `Iterator::Item`; see `AssociatedType` for details
     AssociatedType(ItemId),
+
+    /// a reference `&'a T` or `&'a mut T`; the parameters are `'a` and `T`
+    Ref(Mutability),
+
+    /// a tuple of the given arity
+    Tuple(usize),
+
+    /// a slice `[T]`
+    Slice,
+
+    /// a function pointer taking the given number of arguments; the
+    /// parameters are the argument types followed by the return type
+    FnPtr(usize),
 }
 
 impl TypeName {
@@ -144,6 +278,65 @@ impl TypeName {
             _ => false,
         }
     }
+
+    /// The well-formedness clause of a built-in type constructor, e.g.
+    ///
+    /// ```notrust
+    /// forall<'a, T> { WF(&'a T) :- WF(T), T: 'a }
+    /// forall<A, B> { WF((A, B)) :- WF(A), WF(B) }
+    /// ```
+    ///
+    /// Other type names have no such clause.
+    pub fn builtin_wf_clause(self) -> Option<ProgramClause> {
+        let num_tys = match self {
+            TypeName::Ref(_) | TypeName::Slice => 1,
+            TypeName::Tuple(arity) => arity,
+            TypeName::FnPtr(arity) => arity + 1,
+            TypeName::ItemId(_) | TypeName::ForAll(_) | TypeName::AssociatedType(_) => return None,
+        };
+
+        // The lifetime of a reference comes first, as in `&'a T`.
+        let num_lifetimes = match self {
+            TypeName::Ref(_) => 1,
+            _ => 0,
+        };
+
+        let binders: Vec<_> = (0..num_lifetimes).map(|_| ParameterKind::Lifetime(()))
+            .chain((0..num_tys).map(|_| ParameterKind::Ty(())))
+            .collect();
+        let parameters: Vec<_> = binders.iter()
+            .zip(0..)
+            .map(|(kind, depth)| match *kind {
+                ParameterKind::Lifetime(()) => ParameterKind::Lifetime(Lifetime::Var(depth)),
+                ParameterKind::Ty(()) => ParameterKind::Ty(Ty::Var(depth)),
+            })
+            .collect();
+
+        let mut conditions: Vec<Goal> = (num_lifetimes..binders.len())
+            .map(|depth| WellFormed::Ty(Ty::Var(depth)).cast())
+            .collect();
+        if num_lifetimes > 0 {
+            conditions.push(Outlives { a: ParameterKind::Ty(Ty::Var(1)), b: Lifetime::Var(0) }.cast());
+        }
+
+        let self_ty = Ty::Apply(ApplicationTy { name: self, parameters });
+        Some(ProgramClause {
+            implication: Binders {
+                binders,
+                value: ProgramClauseImplication {
+                    consequence: WellFormed::Ty(self_ty).cast(),
+                    conditions,
+                },
+            },
+            fallback_clause: false,
+        })
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Mutability {
+    Not,
+    Mut,
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -190,6 +383,14 @@ pub struct ImplDatumBound {
     pub where_clauses: Vec<DomainGoal>,
     pub associated_ty_values: Vec<AssociatedTyValue>,
     pub specialization_priority: usize,
+
+    /// True if this impl is declared in an upstream crate (`#[upstream]`);
+    /// such impls are not subject to the orphan check.
+    pub upstream: bool,
+
+    /// True for a `default impl`, which only supplies default values to the
+    /// impls specializing it: it does not implement the trait by itself.
+    pub default: bool,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
@@ -213,6 +414,13 @@ pub struct StructDatumBound {
     pub self_ty: ApplicationTy,
     pub fields: Vec<Ty>,
     pub where_clauses: Vec<DomainGoal>,
+
+    /// True if this struct is declared in an upstream crate (`#[upstream]`).
+    pub upstream: bool,
+
+    /// True if this struct is `#[fundamental]`, like `Box<T>`: `Box<Local>`
+    /// is then treated as a local type by the orphan rules.
+    pub fundamental: bool,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
@@ -225,6 +433,17 @@ pub struct TraitDatumBound {
     pub trait_ref: TraitRef,
     pub where_clauses: Vec<DomainGoal>,
     pub auto: bool,
+
+    /// True if this trait is declared in an upstream crate (`#[upstream]`).
+    pub upstream: bool,
+
+    /// True if this trait is `#[fundamental]`: adding a blanket impl of it
+    /// is considered a breaking change.
+    pub fundamental: bool,
+
+    /// True if some trait object type implements this trait: it can be made
+    /// into an object, or it is a supertrait of a trait that can.
+    pub implemented_by_objects: bool,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
@@ -252,6 +471,10 @@ pub struct AssociatedTyValue {
 
     // note: these binders are in addition to those from the impl
     pub value: Binders<AssociatedTyValueBound>,
+
+    /// True if the value was declared `default` (directly or through a
+    /// `default impl`), in which case a more specialized impl may override it.
+    pub default: bool,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
@@ -273,6 +496,85 @@ pub enum Ty {
     Apply(ApplicationTy),
     Projection(ProjectionTy),
     ForAll(Box<QuantifiedTy>),
+
+    /// A trait object like `dyn Iterator<Item = u32> + 'a`, which is
+    /// represented by the conditions its (existentially bound) self type
+    /// satisfies, in a canonical order:
+    ///
+    /// ```notrust
+    /// exists<T> { T: Iterator, <T as Iterator>::Item = u32, T: 'a }
+    /// ```
+    Dyn(Binders<Vec<DomainGoal>>),
+}
+
+impl Ty {
+    pub fn is_dyn(&self) -> bool {
+        match *self {
+            Ty::Dyn(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Whether the variable bound at `depth` (outside of this type) appears
+    /// in it.
+    pub fn mentions_var(&self, depth: usize) -> bool {
+        match *self {
+            Ty::Var(d) => d == depth,
+            Ty::Apply(ApplicationTy { ref parameters, .. }) |
+            Ty::Projection(ProjectionTy { ref parameters, .. }) => parameters_mention_var(parameters, depth),
+            Ty::ForAll(ref quantified_ty) => quantified_ty.ty.mentions_var(depth + quantified_ty.num_binders),
+            Ty::Dyn(ref bounds) => {
+                let depth = depth + bounds.binders.len();
+                bounds.value.iter().any(|bound| match *bound {
+                    DomainGoal::Implemented(ref trait_ref) => parameters_mention_var(&trait_ref.parameters, depth),
+                    DomainGoal::Normalize(Normalize { ref projection, ref ty }) => {
+                        parameters_mention_var(&projection.parameters, depth) || ty.mentions_var(depth)
+                    }
+                    _ => false,
+                })
+            }
+        }
+    }
+
+    /// The bounds of a trait object type, applied to the type itself. Other
+    /// types have no bounds.
+    pub fn dyn_bounds(&self) -> Vec<DomainGoal> {
+        match *self {
+            Ty::Dyn(ref bounds) => bounds.subst(&[ParameterKind::Ty(self.clone())]),
+            _ => vec![],
+        }
+    }
+
+    /// The facts which hold of a trait object type: its bounds, and
+    /// (transitively) the where clauses of their traits which apply to
+    /// `Self`, i.e. its supertraits. Other types have no such facts.
+    pub fn dyn_facts(&self, program: &ProgramDatabase) -> Vec<DomainGoal> {
+        let self_ty = ParameterKind::Ty(self.clone());
+        let mut facts = self.dyn_bounds();
+        let mut i = 0;
+        while i < facts.len() {
+            if let DomainGoal::Implemented(trait_ref) = facts[i].clone() {
+                let trait_datum = program.trait_datum(trait_ref.trait_id);
+                let where_clauses = Subst::apply(&trait_ref.parameters, &trait_datum.binders.value.where_clauses);
+                for where_clause in where_clauses {
+                    let applies = match where_clause {
+                        DomainGoal::Implemented(ref trait_ref) => trait_ref.parameters[0] == self_ty,
+                        DomainGoal::Normalize(ref normalize) => normalize.projection.self_ty(program) == self,
+                        _ => false,
+                    };
+                    if applies && !facts.contains(&where_clause) {
+                        facts.push(where_clause);
+                    }
+                }
+            }
+            i += 1;
+        }
+        facts
+    }
+}
+
+fn parameters_mention_var(parameters: &[Parameter], depth: usize) -> bool {
+    parameters.iter().any(|p| p.as_ref().ty().map_or(false, |ty| ty.mentions_var(depth)))
 }
 
 /// for<'a...'z> X -- all binders are instantiated at once,
@@ -353,6 +655,16 @@ pub struct ProjectionTy {
     pub parameters: Vec<Parameter>,
 }
 
+impl ProjectionTy {
+    /// The self type of the trait, e.g. `T` in `<T as Iterator>::Item`.
+    pub fn self_ty(&self, program: &ProgramDatabase) -> &Ty {
+        let trait_id = program.associated_ty_datum(self.associated_ty_id).trait_id;
+        let trait_num_params = program.trait_datum(trait_id).binders.len();
+        let split_point = self.parameters.len() - trait_num_params;
+        self.parameters[split_point].as_ref().ty().unwrap()
+    }
+}
+
 #[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct TraitRef {
     pub trait_id: ItemId,
@@ -389,6 +701,8 @@ pub enum DomainGoal {
     Implemented(TraitRef),
     Normalize(Normalize),
     WellFormed(WellFormed),
+    FromEnv(FromEnv),
+    Outlives(Outlives),
 }
 
 impl DomainGoal {
@@ -407,6 +721,136 @@ impl DomainGoal {
         }
     }
 
+    /// The key used to index program clauses, along with the type
+    /// constructor of the self type (if it is known).
+    pub fn clause_key(&self) -> (ClauseKey, Option<ItemId>) {
+        fn type_constructor(ty: &Ty) -> Option<ItemId> {
+            match *ty {
+                Ty::Apply(ApplicationTy { name: TypeName::ItemId(id), .. }) => Some(id),
+                _ => None,
+            }
+        }
+
+        fn self_ty(trait_ref: &TraitRef) -> Option<ItemId> {
+            trait_ref.parameters[0].as_ref().ty().and_then(type_constructor)
+        }
+
+        match *self {
+            DomainGoal::Implemented(ref trait_ref) =>
+                (ClauseKey::Implemented(trait_ref.trait_id), self_ty(trait_ref)),
+            DomainGoal::Normalize(ref normalize) =>
+                (ClauseKey::Normalize(normalize.projection.associated_ty_id), None),
+            DomainGoal::WellFormed(WellFormed::Ty(ref ty)) =>
+                (ClauseKey::WellFormedTy, type_constructor(ty)),
+            DomainGoal::WellFormed(WellFormed::TraitRef(ref trait_ref)) =>
+                (ClauseKey::WellFormedTraitRef(trait_ref.trait_id), self_ty(trait_ref)),
+            DomainGoal::FromEnv(FromEnv::Ty(ref ty)) =>
+                (ClauseKey::FromEnvTy, type_constructor(ty)),
+            DomainGoal::FromEnv(FromEnv::TraitRef(ref trait_ref)) =>
+                (ClauseKey::FromEnvTraitRef(trait_ref.trait_id), self_ty(trait_ref)),
+            DomainGoal::Outlives(_) => (ClauseKey::Outlives, None),
+        }
+    }
+
+    /// The clauses for built-in types which may prove this goal. These are
+    /// not part of the program clauses, since there is one for each arity of
+    /// tuples and function pointers, and for each trait object type; when the
+    /// self type is not known, only the clauses for references and slices are
+    /// returned, see `has_unlisted_builtin_candidates`.
+    pub fn builtin_clauses(&self, program: &ProgramDatabase) -> Vec<ProgramClause> {
+        let self_ty = match *self {
+            DomainGoal::Implemented(ref trait_ref) => trait_ref.parameters[0].as_ref().ty(),
+            DomainGoal::Normalize(ref normalize) => {
+                // Looking up the self type of a projection has a cost, so
+                // first check whether it could be a trait object at all.
+                if normalize.projection.parameters.iter().any(|p| p.as_ref().ty().map_or(false, Ty::is_dyn)) {
+                    Some(normalize.projection.self_ty(program))
+                } else {
+                    None
+                }
+            }
+            DomainGoal::WellFormed(WellFormed::Ty(ref ty)) => Some(ty),
+            _ => None,
+        };
+
+        match (self, self_ty) {
+            (&DomainGoal::WellFormed(WellFormed::Ty(_)), Some(dyn_ty @ &Ty::Dyn(_))) => {
+                // A trait object is well-formed if the types in its bounds
+                // are, e.g. `WF(dyn Iterator<Item = T>) :- WF(T)`. Requiring
+                // `WF(dyn Iterator<Item = T>: Iterator)` instead would be
+                // circular, since that requires the self type to be WF.
+                let self_ty = ParameterKind::Ty(dyn_ty.clone());
+                let mut conditions = vec![];
+                for bound in dyn_ty.dyn_bounds() {
+                    let (parameters, ty) = match bound {
+                        DomainGoal::Implemented(trait_ref) => (trait_ref.parameters, None),
+                        DomainGoal::Normalize(normalize) => (normalize.projection.parameters, Some(normalize.ty)),
+                        _ => continue,
+                    };
+                    let tys = parameters.into_iter()
+                                        .filter(|p| *p != self_ty)
+                                        .filter_map(|p| p.ty())
+                                        .chain(ty);
+                    for ty in tys {
+                        let condition = WellFormed::Ty(ty).cast();
+                        if !conditions.contains(&condition) {
+                            conditions.push(condition);
+                        }
+                    }
+                }
+                let implication = ProgramClauseImplication { consequence: self.clone(), conditions };
+                vec![ProgramClause {
+                    implication: Binders { binders: vec![], value: implication },
+                    fallback_clause: false,
+                }]
+            }
+            (_, Some(dyn_ty @ &Ty::Dyn(_))) => {
+                let key = self.clause_key().0;
+                dyn_ty.dyn_facts(program)
+                      .into_iter()
+                      .filter(|fact| fact.clause_key().0 == key)
+                      .map(DomainGoal::into_program_clause)
+                      .collect()
+            }
+            (&DomainGoal::WellFormed(WellFormed::Ty(Ty::Apply(ref apply))), _) => {
+                apply.name.builtin_wf_clause().into_iter().collect()
+            }
+            (&DomainGoal::WellFormed(WellFormed::Ty(_)), _) => {
+                vec![TypeName::Ref(Mutability::Not), TypeName::Ref(Mutability::Mut), TypeName::Slice]
+                    .into_iter()
+                    .filter_map(TypeName::builtin_wf_clause)
+                    .collect()
+            }
+            _ => vec![],
+        }
+    }
+
+    /// Whether built-in clauses which `builtin_clauses` does not return may
+    /// prove this goal, because its self type is not known. The solvers
+    /// answer such a goal ambiguously where they would otherwise answer it
+    /// uniquely or fail.
+    pub fn has_unlisted_builtin_candidates(&self, program: &ProgramDatabase) -> bool {
+        let (self_ty, trait_id) = match *self {
+            // Any tuple, function pointer or trait object type may be WF.
+            DomainGoal::WellFormed(WellFormed::Ty(Ty::Var(_))) => return true,
+            DomainGoal::Implemented(ref trait_ref) => match trait_ref.parameters[0] {
+                ParameterKind::Ty(ref ty) => (ty, trait_ref.trait_id),
+                ParameterKind::Lifetime(_) => return false,
+            },
+            DomainGoal::Normalize(ref normalize) => {
+                let trait_id = program.associated_ty_datum(normalize.projection.associated_ty_id).trait_id;
+                (normalize.projection.self_ty(program), trait_id)
+            }
+            _ => return false,
+        };
+
+        // Some trait object type, e.g. `dyn Trait`, may implement the trait.
+        match *self_ty {
+            Ty::Var(_) => program.trait_datum(trait_id).binders.value.implemented_by_objects,
+            _ => false,
+        }
+    }
+
     /// A clause of the form (T: Foo) expands to (T: Foo), WF(T: Foo).
     /// A clause of the form (T: Foo<Item = U>) expands to (T: Foo<Item = U>), WF(T: Foo).
     pub fn expanded(self, program: &Program) -> impl Iterator<Item = DomainGoal> {
@@ -427,6 +871,38 @@ impl DomainGoal {
         expanded.push(self.cast());
         expanded.into_iter()
     }
+
+    /// The clauses that hold in an environment where this goal is assumed.
+    /// An assumption of the form (T: Foo) gives (T: Foo), FromEnv(T: Foo).
+    /// An assumption of the form (T: Foo<Item = U>) gives (T: Foo<Item = U>), FromEnv(T: Foo).
+    pub fn assumed(self, program: &Program) -> impl Iterator<Item = DomainGoal> {
+        let mut assumed = vec![];
+        match self {
+            DomainGoal::Implemented(ref trait_ref) =>
+                assumed.push(FromEnv::TraitRef(trait_ref.clone()).cast()),
+            DomainGoal::Normalize(Normalize { ref projection, .. }) => {
+                let (associated_ty_data, trait_params, _) = program.split_projection(&projection);
+                let trait_ref = TraitRef {
+                    trait_id: associated_ty_data.trait_id,
+                    parameters: trait_params.to_owned()
+                };
+                assumed.push(FromEnv::TraitRef(trait_ref).cast());
+            }
+            _ => ()
+        };
+        assumed.push(self);
+        assumed.into_iter()
+    }
+
+    /// The goal which is implied by this where clause of an item, when the
+    /// item is assumed: (T: Foo) becomes FromEnv(T: Foo), and the other
+    /// where clauses are implied as they are.
+    pub fn into_from_env_goal(self) -> DomainGoal {
+        match self {
+            DomainGoal::Implemented(trait_ref) => FromEnv::TraitRef(trait_ref).cast(),
+            goal => goal,
+        }
+    }
 }
 
 #[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
@@ -450,12 +926,32 @@ pub enum WellFormed {
     TraitRef(TraitRef),
 }
 
+/// Holds if the type or trait reference is assumed in the environment,
+/// e.g. `FromEnv(T: Ord)` in a function with a `T: Ord` where clause. Unlike
+/// `WellFormed`, which is what must be proven of the types and where clauses
+/// of an item, this is what the item may assume about them: its implied
+/// bounds.
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum FromEnv {
+    Ty(Ty),
+    TraitRef(TraitRef),
+}
+
 #[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct Normalize {
     pub projection: ProjectionTy,
     pub ty: Ty,
 }
 
+/// `a: b`, where `a` is a type or a lifetime: every reference in `a` is
+/// valid for the lifetime `b`. The solver does not prove these goals itself,
+/// but defers them to region checking as `Constraint::Outlives`.
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Outlives {
+    pub a: Parameter,
+    pub b: Lifetime,
+}
+
 /// Indicates that the `value` is universally quantified over `N`
 /// parameters of the given kinds, where `N == self.binders.len()`. A
 /// variable with depth `i < N` refers to the value at
@@ -546,7 +1042,7 @@ impl FullyReducedGoal {
     }
 
     /// A goal has coinductive semantics if it is of the form `T: AutoTrait`.
-    pub fn is_coinductive(&self, program: &ProgramEnvironment) -> bool {
+    pub fn is_coinductive(&self, program: &ProgramDatabase) -> bool {
         if let FullyReducedGoal::DomainGoal(Canonical {
                 value: InEnvironment {
                     goal: DomainGoal::Implemented(ref tr),
@@ -554,8 +1050,7 @@ impl FullyReducedGoal {
                 },
                 ..
         }) = *self {
-            let trait_datum = &program.trait_data[&tr.trait_id];
-            return trait_datum.binders.value.auto;
+            return program.trait_datum(tr.trait_id).binders.value.auto;
         }
 
         false
@@ -606,6 +1101,7 @@ pub enum QuantifierKind {
 #[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum Constraint {
     LifetimeEq(Lifetime, Lifetime),
+    Outlives(Parameter, Lifetime),
 }
 
 /// A mapping of inference variables to instantiations thereof.
@@ -655,7 +1151,9 @@ pub struct ConstrainedSubst {
 }
 
 pub mod debug;
+mod display;
 mod tls;
 
+pub use self::display::{WithProgram, with_program};
 pub use self::tls::set_current_program;
-pub use self::tls::with_current_program;
+use self::tls::with_current_program;
@@ -1,5 +1,17 @@
+//! The current program of the thread, which the `Debug` impls of the IR
+//! consult to render item names (e.g. `Vec<Foo>: Clone` rather than
+//! `ItemId { index: 2 }<...>`); `Debug` gives them no other way to find it.
+//!
+//! This is the only thread-local state left: lowering, coherence and the
+//! solver neither read nor set it, so it cannot change their results. To
+//! render a value, use `with_program`, which makes a program current only
+//! while formatting. `set_current_program` remains for tools which want the
+//! IR formatted deep inside the solver, i.e. in trace events, to show item
+//! names as well.
+
 use ir;
 use std::cell::RefCell;
+use std::mem;
 use std::sync::Arc;
 
 thread_local! {
@@ -15,13 +27,15 @@ pub fn with_current_program<OP, R>(op: OP) -> R
     })
 }
 
+/// Make `p` the current program while running `op`; the previous current
+/// program (if any) is restored afterwards, so calls may be nested.
 pub fn set_current_program<OP, R>(p: &Arc<ir::Program>, op: OP) -> R
     where OP: FnOnce() -> R
 {
     PROGRAM.with(|prog_cell| {
-        *prog_cell.borrow_mut() = Some(p.clone());
+        let previous = mem::replace(&mut *prog_cell.borrow_mut(), Some(p.clone()));
         let r = op();
-        *prog_cell.borrow_mut() = None;
+        *prog_cell.borrow_mut() = previous;
         r
     })
 }
@@ -10,8 +10,6 @@ extern crate ena;
 extern crate itertools;
 extern crate petgraph;
 extern crate lalrpop_intern;
-#[macro_use]
-extern crate lazy_static;
 
 #[macro_use]
 mod macros;
@@ -23,4 +21,5 @@ pub mod fold;
 pub mod ir;
 pub mod lower;
 pub mod solve;
+pub mod trace;
 pub mod zip;
@@ -1,26 +1,42 @@
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::sync::Arc;
 
 use chalk_parse::ast::*;
+use itertools::Itertools;
 use lalrpop_intern::intern;
 
 use cast::{Cast, Caster};
 use errors::*;
 use ir;
+use solve::solver::SolverConfig;
 
 mod test;
 mod default;
+pub mod wf;
 
 type TypeIds = HashMap<ir::Identifier, ir::ItemId>;
 type TypeKinds = HashMap<ir::ItemId, ir::TypeKind>;
 type AssociatedTyInfos = HashMap<(ir::ItemId, ir::Identifier), AssociatedTyInfo>;
 type ParameterMap = HashMap<ir::ParameterKind<ir::Identifier>, usize>;
+type TraitObjectInfos = HashMap<ir::ItemId, TraitObjectInfo>;
 
 #[derive(Clone, Debug)]
 struct Env<'k> {
     type_ids: &'k TypeIds,
     type_kinds: &'k TypeKinds,
     associated_ty_infos: &'k AssociatedTyInfos,
+    trait_object_infos: &'k TraitObjectInfos,
     parameter_map: ParameterMap,
+
+    /// The non-auto traits of the trait objects lowered so far. Whether they
+    /// can be made into objects is checked once they are all lowered, see
+    /// `check_object_safety`.
+    trait_objects: &'k RefCell<Vec<(ir::ItemId, Identifier)>>,
+
+    /// The lowered program, when lowering goals against it (used to
+    /// elaborate where clauses).
+    program: Option<&'k ir::Program>,
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -29,6 +45,12 @@ struct AssociatedTyInfo {
     addl_parameter_kinds: Vec<ir::ParameterKind<ir::Identifier>>,
 }
 
+/// What we need to know about a trait to use it in a `dyn` type.
+#[derive(Debug, PartialEq, Eq)]
+struct TraitObjectInfo {
+    auto: bool,
+}
+
 enum NameLookup {
     Type(ir::ItemId),
     Parameter(usize),
@@ -94,11 +116,14 @@ impl<'k> Env<'k> {
 }
 
 pub trait LowerProgram {
-    fn lower(&self) -> Result<ir::Program>;
+    /// Lower the program; `config` is used for the solver queries made by
+    /// the coherence checks.
+    fn lower(&self, config: SolverConfig) -> Result<ir::Program>;
 }
 
 impl LowerProgram for Program {
-    fn lower(&self) -> Result<ir::Program> {
+    fn lower(&self, config: SolverConfig) -> Result<ir::Program> {
+        info_heading!("lowering {} items", self.items.len());
         let mut index = 0;
         let mut next_item_id = || -> ir::ItemId {
             let i = index;
@@ -116,11 +141,14 @@ impl LowerProgram for Program {
 
         // Create ids for associated types
         let mut associated_ty_infos = HashMap::new();
+        let mut trait_object_infos = HashMap::new();
         for (item, &item_id) in self.items.iter().zip(&item_ids) {
             if let Item::TraitDefn(ref d) = *item {
                 if d.auto && !d.assoc_ty_defns.is_empty() {
                     bail!("auto trait cannot define associated types");
                 }
+                let info = TraitObjectInfo { auto: d.auto };
+                trait_object_infos.insert(item_id, info);
                 for defn in &d.assoc_ty_defns {
                     let addl_parameter_kinds = defn.all_parameters();
                     let info = AssociatedTyInfo { id: next_item_id(), addl_parameter_kinds };
@@ -145,19 +173,25 @@ impl LowerProgram for Program {
         let mut trait_data = HashMap::new();
         let mut impl_data = HashMap::new();
         let mut associated_ty_data = HashMap::new();
+        let trait_objects = RefCell::new(vec![]);
         for (item, &item_id) in self.items.iter().zip(&item_ids) {
             let empty_env = Env {
                 type_ids: &type_ids,
                 type_kinds: &type_kinds,
                 associated_ty_infos: &associated_ty_infos,
+                trait_object_infos: &trait_object_infos,
                 parameter_map: HashMap::new(),
+                trait_objects: &trait_objects,
+                program: None,
             };
 
             match *item {
                 Item::StructDefn(ref d) => {
+                    debug!("lowering struct `{}`", d.name.str);
                     struct_data.insert(item_id, d.lower_struct(item_id, &empty_env)?);
                 }
                 Item::TraitDefn(ref d) => {
+                    debug!("lowering trait `{}`", d.name.str);
                     trait_data.insert(item_id, d.lower_trait(item_id, &empty_env)?);
 
                     for defn in &d.assoc_ty_defns {
@@ -166,20 +200,37 @@ impl LowerProgram for Program {
                         let mut parameter_kinds = defn.all_parameters();
                         parameter_kinds.extend(d.all_parameters());
 
+                        // The bounds apply to the projection `<Self as Trait>::Item<..>`,
+                        // whose parameters are those of the associated type datum.
+                        let env = empty_env.introduce(parameter_kinds.iter().cloned());
+                        let projection = ir::ProjectionTy {
+                            associated_ty_id: info.id,
+                            parameters: parameter_kinds.anonymize()
+                                                       .iter()
+                                                       .zip(0..)
+                                                       .map(|p| p.to_parameter())
+                                                       .collect(),
+                        };
+                        let self_ty = ir::ParameterKind::Ty(ir::Ty::Projection(projection));
+                        let where_clauses = defn.bounds.lower_bound(&env, self_ty)?;
+
                         associated_ty_data.insert(info.id, ir::AssociatedTyDatum {
                             trait_id: item_id,
                             id: info.id,
                             name: defn.name.str,
                             parameter_kinds: parameter_kinds,
-                            where_clauses: vec![],
+                            where_clauses: where_clauses,
                         });
                     }
                 }
                 Item::Impl(ref d) => {
+                    debug!("lowering impl at {}..{}", d.span.lo, d.span.hi);
                     impl_data.insert(item_id, d.lower_impl(&empty_env)?);
                 }
             }
         }
+        check_object_safety(&trait_objects.into_inner(), &trait_data, &associated_ty_data)?;
+        record_traits_implemented_by_objects(&mut trait_data, &associated_ty_data);
 
         let mut program = ir::Program {
             type_ids,
@@ -189,10 +240,16 @@ impl LowerProgram for Program {
             impl_data,
             associated_ty_data,
             default_impl_data: Vec::new(),
+            item_spans: self.items.iter().zip(&item_ids).map(|(item, &id)| (id, item.span())).collect(),
         };
         program.add_default_impls();
-        program.record_specialization_priorities()?;
-        Ok(program)
+        debug!("checking coherence");
+        program.perform_orphan_check()?;
+        let mut program = Arc::new(program);
+        ir::Program::record_specialization_priorities(&mut program, config)?;
+
+        // Nothing else holds on to the program by now, so it is not cloned.
+        Ok(Arc::try_unwrap(program).unwrap_or_else(|program| (*program).clone()))
     }
 }
 
@@ -401,6 +458,24 @@ impl LowerWhereClause<ir::DomainGoal> for WhereClause {
             WhereClause::TraitRefWellFormed { ref trait_ref } => {
                 ir::WellFormed::TraitRef(trait_ref.lower(env)?).cast()
             }
+            WhereClause::TyFromEnv { ref ty } => {
+                ir::FromEnv::Ty(ty.lower(env)?).cast()
+            }
+            WhereClause::TraitRefFromEnv { ref trait_ref } => {
+                ir::FromEnv::TraitRef(trait_ref.lower(env)?).cast()
+            }
+            WhereClause::TyOutlives { ref a, ref b } => {
+                ir::Outlives {
+                    a: ir::ParameterKind::Ty(a.lower(env)?),
+                    b: b.lower(env)?,
+                }.cast()
+            }
+            WhereClause::LifetimeOutlives { ref a, ref b } => {
+                ir::Outlives {
+                    a: ir::ParameterKind::Lifetime(a.lower(env)?),
+                    b: b.lower(env)?,
+                }.cast()
+            }
             WhereClause::UnifyTys { .. } |
             WhereClause::UnifyLifetimes { .. } => {
                 bail!("this form of where-clause not allowed here")
@@ -417,7 +492,11 @@ impl LowerWhereClause<ir::LeafGoal> for WhereClause {
     fn lower(&self, env: &Env) -> Result<ir::LeafGoal> {
         Ok(match *self {
             WhereClause::Implemented { .. } |
-            WhereClause::ProjectionEq { .. } => {
+            WhereClause::ProjectionEq { .. } |
+            WhereClause::TyFromEnv { .. } |
+            WhereClause::TraitRefFromEnv { .. } |
+            WhereClause::TyOutlives { .. } |
+            WhereClause::LifetimeOutlives { .. } => {
                 let g: ir::DomainGoal = self.lower(env)?;
                 g.cast()
             }
@@ -463,7 +542,13 @@ impl LowerStructDefn for StructDefn {
             let fields: Result<_> = self.fields.iter().map(|f| f.ty.lower(env)).collect();
             let where_clauses = self.lower_where_clauses(env)?;
 
-            Ok(ir::StructDatumBound { self_ty, fields: fields?, where_clauses })
+            Ok(ir::StructDatumBound {
+                self_ty,
+                fields: fields?,
+                where_clauses,
+                upstream: self.upstream,
+                fundamental: self.fundamental,
+            })
         })?;
 
         Ok(ir::StructDatum { binders })
@@ -486,31 +571,95 @@ trait LowerTraitRef {
 
 impl LowerTraitRef for TraitRef {
     fn lower(&self, env: &Env) -> Result<ir::TraitRef> {
-        let id = match env.lookup(self.trait_name)? {
-            NameLookup::Type(id) => id,
-            NameLookup::Parameter(_) => bail!(ErrorKind::NotTrait(self.trait_name)),
+        let parameters = self.args.iter().map(|a| Ok(a.lower(env)?)).collect::<Result<Vec<_>>>()?;
+        lower_trait_ref(env, self.trait_name, parameters)
+    }
+}
+
+/// The reference to the trait `trait_name` with the given (lowered)
+/// parameters, the first of which is the self type.
+fn lower_trait_ref(env: &Env, trait_name: Identifier, parameters: Vec<ir::Parameter>) -> Result<ir::TraitRef> {
+    let id = match env.lookup(trait_name)? {
+        NameLookup::Type(id) => id,
+        NameLookup::Parameter(_) => bail!(ErrorKind::NotTrait(trait_name)),
+    };
+
+    let k = env.type_kind(id);
+    if k.sort != ir::TypeSort::Trait {
+        bail!(ErrorKind::NotTrait(trait_name));
+    }
+
+    if parameters.len() != k.binders.len() + 1 {
+        bail!("wrong number of parameters, expected `{:?}`, got `{:?}`",
+              k.binders.len() + 1, parameters.len())
+    }
+
+    for (binder, param) in k.binders.binders.iter().zip(parameters.iter().skip(1)) {
+        check_type_kinds("incorrect kind for trait parameter", binder, param)?;
+    }
+
+    Ok(ir::TraitRef {
+        trait_id: id,
+        parameters: parameters,
+    })
+}
+
+/// Lowers a trait bound `Foo<A, Item = B>` on `self_ty` to the trait reference
+/// `self_ty: Foo<A>` and the normalizations of the associated types it binds.
+fn lower_trait_bound(env: &Env,
+                     self_ty: ir::Parameter,
+                     trait_name: Identifier,
+                     args: &[Parameter],
+                     assoc_ty_bindings: &[AssocTyBinding])
+                     -> Result<(ir::TraitRef, Vec<ir::Normalize>)> {
+    let parameters = Some(Ok(self_ty))
+        .into_iter()
+        .chain(args.iter().map(|a| a.lower(env)))
+        .collect::<Result<Vec<_>>>()?;
+    let trait_ref = lower_trait_ref(env, trait_name, parameters)?;
+
+    let normalizes = assoc_ty_bindings.iter().map(|binding| {
+        let info = match env.associated_ty_infos.get(&(trait_ref.trait_id, binding.name.str)) {
+            Some(info) => info,
+            None => bail!("no associated type `{}` defined in trait", binding.name.str),
         };
+        let projection = ir::ProjectionTy {
+            associated_ty_id: info.id,
+            parameters: trait_ref.parameters.clone(),
+        };
+        Ok(ir::Normalize { projection, ty: binding.ty.lower(env)? })
+    }).collect::<Result<_>>()?;
 
-        let k = env.type_kind(id);
-        if k.sort != ir::TypeSort::Trait {
-            bail!(ErrorKind::NotTrait(self.trait_name));
-        }
+    Ok((trait_ref, normalizes))
+}
 
-        let parameters = self.args.iter().map(|a| Ok(a.lower(env)?)).collect::<Result<Vec<_>>>()?;
+trait LowerInlineBound {
+    /// Lowers the bound as applied to `self_ty`, e.g. `Iterator<Item = u32>`
+    /// on `T` to `T: Iterator` and `<T as Iterator>::Item ==> u32`.
+    fn lower_bound(&self, env: &Env, self_ty: ir::Parameter) -> Result<Vec<ir::DomainGoal>>;
+}
 
-        if parameters.len() != k.binders.len() + 1 {
-            bail!("wrong number of parameters, expected `{:?}`, got `{:?}`",
-                  k.binders.len() + 1, parameters.len())
+impl LowerInlineBound for InlineBound {
+    fn lower_bound(&self, env: &Env, self_ty: ir::Parameter) -> Result<Vec<ir::DomainGoal>> {
+        match *self {
+            InlineBound::Trait { trait_name, ref args, ref assoc_ty_bindings } => {
+                let (trait_ref, normalizes) = lower_trait_bound(env, self_ty, trait_name, args, assoc_ty_bindings)?;
+                Ok(Some(trait_ref.cast()).into_iter().chain(normalizes.into_iter().casted()).collect())
+            }
+            InlineBound::Lifetime { ref lifetime } => {
+                Ok(vec![ir::Outlives { a: self_ty, b: lifetime.lower(env)? }.cast()])
+            }
         }
+    }
+}
 
-        for (binder, param) in k.binders.binders.iter().zip(parameters.iter().skip(1)) {
-            check_type_kinds("incorrect kind for trait parameter", binder, param)?;
+impl LowerInlineBound for [InlineBound] {
+    fn lower_bound(&self, env: &Env, self_ty: ir::Parameter) -> Result<Vec<ir::DomainGoal>> {
+        let mut goals = vec![];
+        for bound in self {
+            goals.extend(bound.lower_bound(env, self_ty.clone())?);
         }
-
-        Ok(ir::TraitRef {
-            trait_id: id,
-            parameters: parameters,
-        })
+        Ok(goals)
     }
 }
 
@@ -618,6 +767,105 @@ impl LowerTy for Ty {
                 let quantified_ty = ir::QuantifiedTy { num_binders: lifetime_names.len(), ty };
                 Ok(ir::Ty::ForAll(Box::new(quantified_ty)))
             }
+
+            Ty::Ref { ref lifetime, mutable, ref ty } => {
+                let mutability = if mutable { ir::Mutability::Mut } else { ir::Mutability::Not };
+                Ok(ir::Ty::Apply(ir::ApplicationTy {
+                    name: ir::TypeName::Ref(mutability),
+                    parameters: vec![
+                        ir::ParameterKind::Lifetime(lifetime.lower(env)?),
+                        ir::ParameterKind::Ty(ty.lower(env)?),
+                    ],
+                }))
+            }
+
+            Ty::Tuple { ref tys } => {
+                let parameters = tys.iter().map(|t| Ok(ir::ParameterKind::Ty(t.lower(env)?)))
+                                           .collect::<Result<Vec<_>>>()?;
+                Ok(ir::Ty::Apply(ir::ApplicationTy {
+                    name: ir::TypeName::Tuple(tys.len()),
+                    parameters: parameters,
+                }))
+            }
+
+            Ty::Slice { ref ty } => {
+                Ok(ir::Ty::Apply(ir::ApplicationTy {
+                    name: ir::TypeName::Slice,
+                    parameters: vec![ir::ParameterKind::Ty(ty.lower(env)?)],
+                }))
+            }
+
+            Ty::FnPtr { ref args, ref ret } => {
+                let parameters = args.iter().chain(Some(&**ret))
+                                            .map(|t| Ok(ir::ParameterKind::Ty(t.lower(env)?)))
+                                            .collect::<Result<Vec<_>>>()?;
+                Ok(ir::Ty::Apply(ir::ApplicationTy {
+                    name: ir::TypeName::FnPtr(args.len()),
+                    parameters: parameters,
+                }))
+            }
+
+            Ty::Dyn { ref bounds } => {
+                // The self type is bound by the `dyn` type itself. It has no
+                // name in the source, so we name it after the keyword, which
+                // cannot be used as an identifier.
+                let env = env.introduce(Some(ir::ParameterKind::Ty(intern("dyn"))));
+                let self_ty = ir::ParameterKind::Ty(ir::Ty::Var(0));
+
+                let mut goals = vec![];
+                let mut principal: Option<Identifier> = None;
+                for bound in bounds {
+                    let (trait_name, args, assoc_ty_bindings) = match *bound {
+                        InlineBound::Trait { trait_name, ref args, ref assoc_ty_bindings } => {
+                            (trait_name, args, assoc_ty_bindings)
+                        }
+                        InlineBound::Lifetime { ref lifetime } => {
+                            let outlives = ir::Outlives { a: self_ty.clone(), b: lifetime.lower(&env)? };
+                            goals.push(outlives.cast());
+                            continue;
+                        }
+                    };
+
+                    let (trait_ref, normalizes) =
+                        lower_trait_bound(&env, self_ty.clone(), trait_name, args, assoc_ty_bindings)?;
+                    let info = &env.trait_object_infos[&trait_ref.trait_id];
+
+                    if !info.auto {
+                        if let Some(principal) = principal {
+                            bail!("only auto traits can be added to a trait object, but `{}` and `{}` are not",
+                                  principal.str, trait_name.str);
+                        }
+                        env.trait_objects.borrow_mut().push((trait_ref.trait_id, trait_name));
+                        principal = Some(trait_name);
+                    }
+
+                    // Associated types with parameters cannot be given a
+                    // value here; they make the trait not object safe.
+                    let mut unbound: Vec<_> =
+                        env.associated_ty_infos
+                           .iter()
+                           .filter(|&(&(trait_id, name), info)| {
+                               trait_id == trait_ref.trait_id && info.addl_parameter_kinds.is_empty() &&
+                                   !assoc_ty_bindings.iter().any(|binding| binding.name.str == name)
+                           })
+                           .map(|(&(_, name), _)| name)
+                           .collect();
+                    unbound.sort();
+                    if let Some(name) = unbound.first() {
+                        bail!("the value of the associated type `{}` in trait `{}` must be specified",
+                              name, trait_name.str);
+                    }
+
+                    goals.push(trait_ref.cast());
+                    goals.extend(normalizes.into_iter().casted());
+                }
+
+                // Put the bounds in a canonical order, so that e.g. `dyn Foo + Send`
+                // and `dyn Send + Foo` are the same type.
+                goals.sort();
+                goals.dedup();
+                Ok(ir::Ty::Dyn(ir::Binders { binders: vec![ir::ParameterKind::Ty(())], value: goals }))
+            }
         }
     }
 }
@@ -667,13 +915,15 @@ impl LowerImpl for Impl {
             let trait_id = trait_ref.trait_ref().trait_id;
             let where_clauses = self.lower_where_clauses(&env)?;
             let associated_ty_values = try!(self.assoc_ty_values.iter()
-                                            .map(|v| v.lower(trait_id, env))
+                                            .map(|v| v.lower(trait_id, self.default, env))
                                             .collect());
             Ok(ir::ImplDatumBound {
                 trait_ref,
                 where_clauses,
                 associated_ty_values,
                 specialization_priority: 0,
+                upstream: self.upstream,
+                default: self.default,
             })
         })?;
 
@@ -682,11 +932,17 @@ impl LowerImpl for Impl {
 }
 
 trait LowerAssocTyValue {
-    fn lower(&self, trait_id: ir::ItemId, env: &Env) -> Result<ir::AssociatedTyValue>;
+    fn lower(&self,
+             trait_id: ir::ItemId,
+             default_impl: bool,
+             env: &Env) -> Result<ir::AssociatedTyValue>;
 }
 
 impl LowerAssocTyValue for AssocTyValue {
-    fn lower(&self, trait_id: ir::ItemId, env: &Env) -> Result<ir::AssociatedTyValue> {
+    fn lower(&self,
+             trait_id: ir::ItemId,
+             default_impl: bool,
+             env: &Env) -> Result<ir::AssociatedTyValue> {
         let info = &env.associated_ty_infos[&(trait_id, self.name.str)];
         let value = env.in_binders(self.all_parameters(), |env| {
             Ok(ir::AssociatedTyValueBound {
@@ -694,7 +950,11 @@ impl LowerAssocTyValue for AssocTyValue {
                 where_clauses: self.where_clauses.lower(env)?,
             })
         })?;
-        Ok(ir::AssociatedTyValue { associated_ty_id: info.id, value: value })
+        Ok(ir::AssociatedTyValue {
+            associated_ty_id: info.id,
+            value: value,
+            default: self.default || default_impl,
+        })
     }
 }
 
@@ -714,15 +974,22 @@ impl LowerTrait for TraitDefn {
                 if trait_ref.parameters.len() > 1 {
                     bail!("auto trait cannot have parameters");
                 }
-                if !self.where_clauses.is_empty() {
+                if !self.where_clauses.is_empty() || !self.bounds.is_empty() {
                     bail!("auto trait cannot have where clauses");
                 }
             }
 
+            // Supertraits are where clauses on `Self`.
+            let mut where_clauses = self.bounds.lower_bound(env, trait_ref.parameters[0].clone())?;
+            where_clauses.extend(self.lower_where_clauses(env)?);
+
             Ok(ir::TraitDatumBound {
                 trait_ref: trait_ref,
-                where_clauses: self.lower_where_clauses(env)?,
+                where_clauses: where_clauses,
                 auto: self.auto,
+                upstream: self.upstream,
+                fundamental: self.fundamental,
+                implemented_by_objects: false,
             })
         })?;
 
@@ -730,6 +997,104 @@ impl LowerTrait for TraitDefn {
     }
 }
 
+/// Fail if one of the `trait_objects` recorded while lowering cannot be made
+/// into an object.
+fn check_object_safety(trait_objects: &[(ir::ItemId, Identifier)],
+                       trait_data: &HashMap<ir::ItemId, ir::TraitDatum>,
+                       associated_ty_data: &HashMap<ir::ItemId, ir::AssociatedTyDatum>)
+                       -> Result<()>
+{
+    for &(trait_id, trait_name) in trait_objects {
+        if let Some(violation) = object_safety_violation(trait_id, trait_data, associated_ty_data) {
+            bail!(ErrorKind::NotObjectSafe(trait_name.str, violation));
+        }
+    }
+    Ok(())
+}
+
+/// Set `implemented_by_objects` for the traits which can be made into
+/// objects, and for their supertraits.
+fn record_traits_implemented_by_objects(trait_data: &mut HashMap<ir::ItemId, ir::TraitDatum>,
+                                        associated_ty_data: &HashMap<ir::ItemId, ir::AssociatedTyDatum>)
+{
+    let mut pending: Vec<_> = trait_data.keys()
+        .cloned()
+        .filter(|&trait_id| object_safety_violation(trait_id, trait_data, associated_ty_data).is_none())
+        .collect();
+
+    while let Some(trait_id) = pending.pop() {
+        let bound = &mut trait_data.get_mut(&trait_id).unwrap().binders.value;
+        if bound.implemented_by_objects {
+            continue;
+        }
+        bound.implemented_by_objects = true;
+
+        let self_ty = &bound.trait_ref.parameters[0];
+        pending.extend(bound.where_clauses.iter().filter_map(|wc| match *wc {
+            ir::DomainGoal::Implemented(ref trait_ref) if trait_ref.parameters[0] == *self_ty =>
+                Some(trait_ref.trait_id),
+            _ => None,
+        }));
+    }
+}
+
+/// Why the trait `trait_id` cannot be made into an object, if it cannot.
+/// Since traits have no methods here, this is the case if one of its
+/// associated types has parameters, or if it refers to `Self` in its where
+/// clauses other than as the type being bounded (e.g. `where Self: Eq<Self>`).
+fn object_safety_violation(trait_id: ir::ItemId,
+                           trait_data: &HashMap<ir::ItemId, ir::TraitDatum>,
+                           associated_ty_data: &HashMap<ir::ItemId, ir::AssociatedTyDatum>)
+                           -> Option<String>
+{
+    let num_trait_params = |trait_id| trait_data[&trait_id].binders.len();
+
+    let with_parameters = associated_ty_data.values()
+        .filter(|datum| datum.trait_id == trait_id && datum.parameter_kinds.len() > num_trait_params(trait_id))
+        .min_by_key(|datum| datum.id);
+    if let Some(datum) = with_parameters {
+        return Some(format!("its associated type `{}` has parameters", datum.name));
+    }
+
+    let bound = &trait_data[&trait_id].binders.value;
+    let self_ty = bound.trait_ref.parameters[0].as_ref().ty().unwrap();
+    let self_depth = match *self_ty {
+        ir::Ty::Var(depth) => depth,
+        _ => unreachable!(),
+    };
+
+    // Whether `Self` appears in `parameters` other than as the one at index
+    // `bounded`, the type being bounded.
+    let mentions_self = |parameters: &[ir::Parameter], bounded: usize| {
+        parameters.iter().enumerate().any(|(i, parameter)| match *parameter {
+            ir::ParameterKind::Ty(ref ty) => !(i == bounded && ty == self_ty) && ty.mentions_var(self_depth),
+            ir::ParameterKind::Lifetime(_) => false,
+        })
+    };
+    let refers_to_self = |wc: &ir::DomainGoal| match *wc {
+        ir::DomainGoal::Implemented(ref trait_ref) |
+        ir::DomainGoal::WellFormed(ir::WellFormed::TraitRef(ref trait_ref)) |
+        ir::DomainGoal::FromEnv(ir::FromEnv::TraitRef(ref trait_ref)) => mentions_self(&trait_ref.parameters, 0),
+        ir::DomainGoal::Normalize(ir::Normalize { ref projection, ref ty }) => {
+            // The parameters of the associated type come before those of
+            // its trait, starting with the type being bounded.
+            let parameters = &projection.parameters;
+            let trait_id = associated_ty_data[&projection.associated_ty_id].trait_id;
+            mentions_self(parameters, parameters.len() - num_trait_params(trait_id)) || ty.mentions_var(self_depth)
+        }
+        ir::DomainGoal::Outlives(ir::Outlives { a: ir::ParameterKind::Ty(ref ty), .. }) |
+        ir::DomainGoal::WellFormed(ir::WellFormed::Ty(ref ty)) |
+        ir::DomainGoal::FromEnv(ir::FromEnv::Ty(ref ty)) => ty != self_ty && ty.mentions_var(self_depth),
+        ir::DomainGoal::Outlives(_) => false,
+    };
+
+    if bound.where_clauses.iter().any(refers_to_self) {
+        return Some("its where clauses refer to `Self` other than as the type being bounded".to_string());
+    }
+
+    None
+}
+
 pub trait LowerGoal<A> {
     fn lower(&self, arg: &A) -> Result<Box<ir::Goal>>;
 }
@@ -749,14 +1114,26 @@ impl LowerGoal<ir::Program> for Goal {
                    })
                    .collect();
 
+        let trait_object_infos: HashMap<_, _> =
+            program.trait_data
+                   .iter()
+                   .map(|(&trait_id, datum)| (trait_id, TraitObjectInfo { auto: datum.binders.value.auto }))
+                   .collect();
+
+        let trait_objects = RefCell::new(vec![]);
         let env = Env {
             type_ids: &program.type_ids,
             type_kinds: &program.type_kinds,
             associated_ty_infos: &associated_ty_infos,
-            parameter_map: HashMap::new()
+            trait_object_infos: &trait_object_infos,
+            parameter_map: HashMap::new(),
+            trait_objects: &trait_objects,
+            program: Some(program),
         };
 
-        self.lower(&env)
+        let goal = self.lower(&env)?;
+        check_object_safety(&trait_objects.into_inner(), &program.trait_data, &program.associated_ty_data)?;
+        Ok(goal)
     }
 }
 
@@ -770,13 +1147,11 @@ impl<'k> LowerGoal<Env<'k>> for Goal {
             Goal::Implies(ref wc, ref g, elaborate) => {
                 let mut where_clauses = wc.lower(env)?;
                 if elaborate {
-                    where_clauses = ir::with_current_program(|program| {
-                        let program = program.expect("cannot elaborate without a program");
-                        where_clauses.into_iter()
-                                     .flat_map(|wc| wc.expanded(program))
-                                     .casted()
-                                     .collect()
-                    });
+                    let program = env.program.ok_or("cannot elaborate without a program")?;
+                    where_clauses = where_clauses.into_iter()
+                                                 .flat_map(|wc| wc.assumed(program))
+                                                 .casted()
+                                                 .collect();
                 }
                 Ok(Box::new(ir::Goal::Implies(where_clauses, g.lower(env)?)))
             }
@@ -828,21 +1203,30 @@ impl ir::Program {
         program_clauses.extend(self.associated_ty_data.values().flat_map(|d| d.to_program_clauses(self)));
         program_clauses.extend(self.default_impl_data.iter().map(|d| d.to_program_clause()));
 
-        for datum in self.impl_data.values() {
+        // Impls are taken in program order, so that their clauses are tried
+        // (and answers enumerated) in a deterministic order.
+        let mut impl_ids: Vec<_> = self.impl_data.keys().collect();
+        impl_ids.sort();
+        for datum in impl_ids.into_iter().map(|id| &self.impl_data[id]) {
             // If we encounter a negative impl, do not generate any rule. Negative impls
             // are currently just there to deactivate default impls for auto traits.
+            // A `default impl` does not implement its trait: its values are
+            // only inherited by the impls that do.
             if datum.binders.value.trait_ref.is_positive() {
-                program_clauses.push(datum.to_program_clause(self));
+                if !datum.binders.value.default {
+                    program_clauses.push(datum.to_program_clause());
+                }
                 program_clauses.extend(datum.binders.value.associated_ty_values.iter().flat_map(|atv| {
-                    atv.to_program_clauses(datum)
+                    atv.to_program_clauses(self, datum)
                 }));
             }
         }
 
+        let struct_data = self.struct_data.clone();
         let trait_data = self.trait_data.clone();
         let associated_ty_data = self.associated_ty_data.clone();
 
-        ir::ProgramEnvironment { trait_data, associated_ty_data, program_clauses }
+        ir::ProgramEnvironment::new(struct_data, trait_data, associated_ty_data, program_clauses)
     }
 }
 
@@ -850,19 +1234,14 @@ impl ir::ImplDatum {
     /// Given `impl<T: Clone> Clone for Vec<T>`, generate:
     ///
     /// ```notrust
-    /// forall<T> { (Vec<T>: Clone) :- (T: Clone), WF(T: Clone) }
+    /// forall<T> { (Vec<T>: Clone) :- (T: Clone) }
     /// ```
-    fn to_program_clause(&self, program: &ir::Program) -> ir::ProgramClause {
+    fn to_program_clause(&self) -> ir::ProgramClause {
         ir::ProgramClause {
             implication: self.binders.map_ref(|bound| {
                 ir::ProgramClauseImplication {
                     consequence: bound.trait_ref.trait_ref().clone().cast(),
-                    conditions: bound.where_clauses
-                                     .iter()
-                                     .cloned()
-                                     .flat_map(|wc| wc.expanded(program))
-                                     .casted()
-                                     .collect(),
+                    conditions: bound.where_clauses.clone().cast(),
                 }
             }),
             fallback_clause: false,
@@ -934,7 +1313,29 @@ impl ir::AssociatedTyValue {
     ///         (T: 'a)              // (2)
     /// }
     /// ```
-    fn to_program_clauses(&self, impl_datum: &ir::ImplDatum) -> Vec<ir::ProgramClause> {
+    ///
+    /// A `default` value may be overridden by more specialized impls, so it
+    /// can only be used once we know that this impl is the final one, i.e.
+    /// that no impl with a higher specialization priority providing its own
+    /// value applies. Given:
+    ///
+    /// ```notrust
+    /// impl<T> Foo for T { default type Assoc = A; }
+    /// impl Foo for i32 { type Assoc = B; }
+    /// ```
+    ///
+    /// the clause for the first impl becomes:
+    ///
+    /// ```notrust
+    /// forall<T> {
+    ///     (T: Foo<Assoc = A>) :-
+    ///         (T: Foo),
+    ///         not { T = i32 }    // (3)
+    /// }
+    /// ```
+    fn to_program_clauses(&self,
+                          program: &ir::Program,
+                          impl_datum: &ir::ImplDatum) -> Vec<ir::ProgramClause> {
         // Begin with the innermost parameters (`'a`) and then add those from impl (`T`).
         let all_binders: Vec<_> =
             self.value.binders
@@ -944,15 +1345,18 @@ impl ir::AssociatedTyValue {
                       .collect();
 
         // Assemble the full list of conditions for projection to be valid.
-        // This comes in two parts, marked as (1) and (2) in example above:
+        // This comes in up to three parts, marked as (1), (2) and (3) in the
+        // examples above:
         //
         // 1. require that the trait is implemented
         // 2. any where-clauses from the `type` declaration in the impl
+        // 3. for a `default` value, no more specialized impl overriding it applies
         let impl_trait_ref = impl_datum.binders.value.trait_ref.trait_ref().up_shift(self.value.len());
         let conditions: Vec<ir::Goal> =
             Some(impl_trait_ref.clone().cast())
             .into_iter()
             .chain(self.value.value.where_clauses.clone().cast())
+            .chain(self.specialization_guards(program, impl_datum, &impl_trait_ref))
             .collect();
 
         let projection = {
@@ -985,6 +1389,59 @@ impl ir::AssociatedTyValue {
 
         vec![normalization]
     }
+
+    /// Build a `not { exists<..> { .. } }` goal for each impl that specializes
+    /// `impl_datum` with its own value for this associated type. Since
+    /// overlapping impls must form a specialization chain, any overlapping impl
+    /// with a higher priority is a more specialized one; impls which do not
+    /// overlap are simply refuted. Values which are not `default` are final
+    /// and need no guard.
+    fn specialization_guards(&self,
+                             program: &ir::Program,
+                             impl_datum: &ir::ImplDatum,
+                             impl_trait_ref: &ir::TraitRef) -> Vec<ir::Goal> {
+        if !self.default {
+            return vec![];
+        }
+
+        let priority = impl_datum.binders.value.specialization_priority;
+        let trait_id = impl_trait_ref.trait_id;
+
+        program.impl_data
+               .values()
+               .filter(|other| {
+                   let bound = &other.binders.value;
+                   bound.trait_ref.is_positive() &&
+                       bound.trait_ref.trait_ref().trait_id == trait_id &&
+                       bound.specialization_priority > priority &&
+                       bound.associated_ty_values
+                            .iter()
+                            .any(|atv| atv.associated_ty_id == self.associated_ty_id)
+               })
+               .map(|other| {
+                   let other_len = other.binders.len();
+                   let bound = &other.binders.value;
+
+                   let params_goals = impl_trait_ref.parameters
+                       .iter()
+                       .map(|p| p.up_shift(other_len))
+                       .zip(bound.trait_ref.trait_ref().parameters.iter().cloned())
+                       .map(|(a, b)| ir::Goal::Leaf(ir::LeafGoal::EqGoal(ir::EqGoal { a, b })));
+
+                   let wc_goals = bound.where_clauses
+                       .iter()
+                       .cloned()
+                       .map(|wc| ir::Goal::Leaf(ir::LeafGoal::DomainGoal(wc)));
+
+                   let applies = params_goals.chain(wc_goals)
+                       .fold1(|goal, leaf| ir::Goal::And(Box::new(goal), Box::new(leaf)))
+                       .expect("Every trait takes at least one input type")
+                       .quantify(ir::QuantifierKind::Exists, other.binders.binders.clone());
+
+                   ir::Goal::Not(Box::new(applies))
+               })
+               .collect()
+    }
 }
 
 trait ToParameter {
@@ -1024,9 +1481,12 @@ impl ir::StructDatum {
         //
         //    struct Foo<T: Eq> { }
         //
-        // we generate the following clause:
+        // we generate the following clauses:
         //
         //    for<?T> WF(Foo<?T>) :- WF(?T), (?T: Eq), WF(?T: Eq).
+        //
+        //    // where clauses are implied by the type:
+        //    for<?T> FromEnv(?T: Eq) :- FromEnv(Foo<?T>).
 
         let wf = ir::ProgramClause {
             implication: self.binders.map_ref(|bound_datum| {
@@ -1054,7 +1514,21 @@ impl ir::StructDatum {
             fallback_clause: false,
         };
 
-        vec![wf]
+        let mut clauses = vec![wf];
+        let from_env = ir::FromEnv::Ty(self.binders.value.self_ty.clone().cast());
+        for wc in &self.binders.value.where_clauses {
+            clauses.push(ir::ProgramClause {
+                implication: self.binders.map_ref(|_| {
+                    ir::ProgramClauseImplication {
+                        consequence: wc.clone().into_from_env_goal(),
+                        conditions: vec![from_env.clone().cast()],
+                    }
+                }),
+                fallback_clause: false,
+            });
+        }
+
+        clauses
     }
 }
 
@@ -1073,16 +1547,24 @@ impl ir::TraitDatum {
         //        // where clauses declared on the trait are met:
         //        (?Self: Eq<?T>), WF(?Self: Eq<?T>)
         //
-        //    for<?Self, ?T> (?Self: Eq<T>) :- WF(?Self: Ord<T>)
-        //    for<?Self, ?T> WF(?Self: Ord<?T>) :- WF(?Self: Ord<T>)
+        //    // the trait holds when it is assumed:
+        //    for<?Self, ?T> (?Self: Ord<?T>) :- FromEnv(?Self: Ord<?T>)
+        //
+        //    // and so do its where clauses, which are its implied bounds:
+        //    for<?Self, ?T> FromEnv(?Self: Eq<?T>) :- FromEnv(?Self: Ord<?T>)
+        //
+        // Assuming `FromEnv` rather than `WF` in the last two clauses means
+        // that proving `WF(?Self: Ord<?T>)` cannot rely on itself.
 
         let where_clauses = self.binders.value.where_clauses
             .iter()
             .cloned()
             .flat_map(|wc| wc.expanded(program))
             .collect::<Vec<_>>();
-        
-        let wf = ir::WellFormed::TraitRef(self.binders.value.trait_ref.clone());
+
+        let trait_ref = self.binders.value.trait_ref.clone();
+        let wf = ir::WellFormed::TraitRef(trait_ref.clone());
+        let from_env = ir::FromEnv::TraitRef(trait_ref.clone());
 
         let clauses = ir::ProgramClause {
             implication: self.binders.map_ref(|bound| {
@@ -1104,13 +1586,23 @@ impl ir::TraitDatum {
             fallback_clause: false,
         };
 
-        let mut clauses = vec![clauses];
-        for wc in where_clauses {
+        let implemented = ir::ProgramClause {
+            implication: self.binders.map_ref(|_| {
+                ir::ProgramClauseImplication {
+                    consequence: trait_ref.clone().cast(),
+                    conditions: vec![from_env.clone().cast()],
+                }
+            }),
+            fallback_clause: false,
+        };
+
+        let mut clauses = vec![clauses, implemented];
+        for wc in &self.binders.value.where_clauses {
             clauses.push(ir::ProgramClause {
                 implication: self.binders.map_ref(|_| {
                     ir::ProgramClauseImplication {
-                        consequence: wc,
-                        conditions: vec![wf.clone().cast()]
+                        consequence: wc.clone().into_from_env_goal(),
+                        conditions: vec![from_env.clone().cast()]
                     }
                 }),
                 fallback_clause: false,
@@ -1137,6 +1629,12 @@ impl ir::AssociatedTyDatum {
         //
         //    <?T as Foo>::Assoc ==> (Foo::Assoc)<?T> :- (?T: Foo)
         //    forall<U> { (?T: Foo) :- <?T as Foo>::Assoc ==> U }
+        //
+        // If the associated type has bounds, as in `type Assoc: Bar;`, these
+        // are implied by the trait reference, like the where clauses of the
+        // trait:
+        //
+        //    FromEnv(<?T as Foo>::Assoc: Bar) :- FromEnv(?T: Foo)
 
         let binders: Vec<_> = self.parameter_kinds.iter().map(|pk| pk.map(|_| ())).collect();
         let parameters: Vec<_> = binders.iter().zip(0..).map(|p| p.to_parameter()).collect();
@@ -1172,6 +1670,20 @@ impl ir::AssociatedTyDatum {
             }
         };
 
+        let implied_bounds = self.where_clauses
+            .iter()
+            .map(|wc| ir::ProgramClause {
+                implication: ir::Binders {
+                    binders: binders.clone(),
+                    value: ir::ProgramClauseImplication {
+                        consequence: wc.clone().into_from_env_goal(),
+                        conditions: vec![ir::FromEnv::TraitRef(trait_ref.clone()).cast()],
+                    }
+                },
+                fallback_clause: false,
+            })
+            .collect::<Vec<_>>();
+
         let elaborate = {
             // add new type parameter U
             let mut binders = binders;
@@ -1190,6 +1702,8 @@ impl ir::AssociatedTyDatum {
             }
         };
 
-        vec![fallback, elaborate]
+        let mut clauses = vec![fallback, elaborate];
+        clauses.extend(implied_bounds);
+        clauses
     }
 }
@@ -1,10 +1,13 @@
 #![cfg(test)]
 
 use chalk_parse;
+use lalrpop_intern::intern;
 use errors::*;
 use ir::*;
+use solve::solver::SolverConfig;
 use std::sync::Arc;
 use super::{LowerProgram, LowerGoal};
+use super::wf::IllFormedItem;
 
 macro_rules! lowering_success {
     (program $program:tt) => {
@@ -30,8 +33,85 @@ macro_rules! lowering_error {
 }
 
 
+macro_rules! lowering_overlap {
+    (program $program:tt overlapping_impls { $($trait_id:expr => $witness:expr),* }) => {
+        let program_text = stringify!($program);
+        assert!(program_text.starts_with("{"));
+        assert!(program_text.ends_with("}"));
+        let text = &program_text[1..program_text.len()-1];
+        let error = parse_and_lower(text).unwrap_err();
+        match *error.kind() {
+            ErrorKind::OverlappingImpls(ref overlaps) => {
+                for overlap in overlaps {
+                    assert!(text[overlap.lhs.lo..overlap.lhs.hi].starts_with("impl"));
+                    assert!(text[overlap.rhs.lo..overlap.rhs.hi].starts_with("impl"));
+                    assert!(overlap.lhs.hi <= overlap.rhs.lo);
+                }
+                let actual: Vec<_> = overlaps.iter()
+                                             .map(|o| (o.trait_id.to_string(), o.witness.clone()))
+                                             .collect();
+                let expected: Vec<(String, String)> = vec![$(($trait_id.to_string(), $witness.to_string())),*];
+                assert_eq!(actual, expected);
+            }
+            ref kind => panic!("expected overlapping impls, found `{}`", kind),
+        }
+    }
+}
+
+macro_rules! lowering_orphan {
+    (program $program:tt orphan_impls_of { $($trait_id:expr),* }) => {
+        let program_text = stringify!($program);
+        assert!(program_text.starts_with("{"));
+        assert!(program_text.ends_with("}"));
+        let text = &program_text[1..program_text.len()-1];
+        let error = parse_and_lower(text).unwrap_err();
+        match *error.kind() {
+            ErrorKind::FailedOrphanCheck(ref orphans) => {
+                for orphan in orphans {
+                    assert!(text[orphan.span.lo..orphan.span.hi].starts_with("impl"));
+                }
+                let actual: Vec<_> = orphans.iter().map(|o| o.trait_id.to_string()).collect();
+                let expected: Vec<&str> = vec![$($trait_id),*];
+                assert_eq!(actual, expected);
+            }
+            ref kind => panic!("expected orphan check failure, found `{}`", kind),
+        }
+    }
+}
+
+macro_rules! lowering_wf {
+    (program $program:tt ill_formed { $($item:expr),* }) => {
+        let program_text = stringify!($program);
+        assert!(program_text.starts_with("{"));
+        assert!(program_text.ends_with("}"));
+        let text = &program_text[1..program_text.len()-1];
+        let program = parse_and_lower(text).unwrap();
+        let expected: Vec<&str> = vec![$($item),*];
+        match program.verify_well_formedness(&Arc::new(program.environment()), SolverConfig::default()) {
+            Ok(()) => assert!(expected.is_empty(), "expected ill-formed items {:?}", expected),
+            Err(error) => match *error.kind() {
+                ErrorKind::IllFormedItems(ref items) => {
+                    let actual: Vec<_> = items.iter()
+                                              .map(|item| {
+                                                  let span = item.span();
+                                                  let text = &text[span.lo..span.hi];
+                                                  assert!(text.starts_with("struct") || text.starts_with("impl"));
+                                                  match *item {
+                                                      IllFormedItem::Struct { name, .. } => name.to_string(),
+                                                      IllFormedItem::Impl { trait_id, .. } => trait_id.to_string(),
+                                                  }
+                                              })
+                                              .collect();
+                    assert_eq!(actual, expected);
+                }
+                ref kind => panic!("expected ill-formed items, found `{}`", kind),
+            },
+        }
+    }
+}
+
 fn parse_and_lower(text: &str) -> Result<Program> {
-    chalk_parse::parse_program(text)?.lower()
+    chalk_parse::parse_program(text)?.lower(SolverConfig::default())
 }
 
 fn parse_and_lower_goal(program: &Program, text: &str) -> Result<Box<Goal>> {
@@ -186,6 +266,52 @@ fn goal_quantifiers() {
     });
 }
 
+#[test]
+fn render_with_program() {
+    let program = Arc::new(parse_and_lower("trait Foo<A> { }").unwrap());
+    let other = Arc::new(parse_and_lower("trait Bar<A> { }").unwrap());
+    let goal = parse_and_lower_goal(&program, "forall<X> { X: Foo<X> }").unwrap();
+    assert_eq!(format!("{:?}", with_program(&program, &goal)), "ForAll<type> { ?0: Foo<?0> }");
+
+    // The program given explicitly takes precedence over the current one,
+    // which is restored afterwards.
+    set_current_program(&other, || {
+        assert_eq!(format!("{:?}", with_program(&program, &goal)), "ForAll<type> { ?0: Foo<?0> }");
+        assert_eq!(format!("{:?}", goal), "ForAll<type> { ?0: Bar<?0> }");
+    });
+}
+
+#[test]
+fn clause_index() {
+    let program = parse_and_lower("
+            trait Foo { }
+            trait Bar { }
+            struct A { }
+            struct B<T> { }
+            impl Foo for A { }
+            impl<T> Foo for B<T> { }
+            impl<T> Foo for T where T: Bar { }
+    ").unwrap();
+    let env = program.environment();
+    let foo = program.type_ids[&intern("Foo")];
+    let a = program.type_ids[&intern("A")];
+
+    let goal = match *parse_and_lower_goal(&program, "A: Foo").unwrap() {
+        Goal::Leaf(LeafGoal::DomainGoal(ref goal)) => goal.clone(),
+        _ => panic!("expected a domain goal"),
+    };
+
+    // Only the impl for `A`, the blanket impl and the clause proving
+    // `?T: Foo` from `FromEnv(?T: Foo)` may apply.
+    let keys: Vec<_> = env.clauses_for(&goal)
+                          .iter()
+                          .map(|clause| clause.implication.value.consequence.clause_key())
+                          .collect();
+    assert_eq!(keys.len(), 3);
+    assert!(keys.contains(&(ClauseKey::Implemented(foo), Some(a))));
+    assert!(keys.contains(&(ClauseKey::Implemented(foo), None)));
+}
+
 #[test]
 fn atc_accounting() {
     let program = Arc::new(parse_and_lower("
@@ -216,10 +342,13 @@ fn atc_accounting() {
                 value: for<lifetime> AssociatedTyValueBound {
                     ty: Iter<'?0, ?1>,
                     where_clauses: []
-                }
+                },
+                default: false
             }
         ],
-        specialization_priority: 0
+        specialization_priority: 0,
+        upstream: false,
+        default: false
     }
 }"#);
         let goal = parse_and_lower_goal(&program, "forall<X> { forall<'a> { forall<Y> { \
@@ -304,15 +433,38 @@ fn check_parameter_kinds() {
 
 #[test]
 fn two_impls_for_same_type() {
-    lowering_error! {
+    lowering_overlap! {
         program {
             trait Foo { }
             struct Bar { }
             impl Foo for Bar { }
             impl Foo for Bar { }
         }
-        error_msg {
-            "overlapping impls of trait \"Foo\""
+        overlapping_impls {
+            "Foo" => "Bar: Foo"
+        }
+    }
+}
+
+#[test]
+fn all_overlapping_pairs() {
+    lowering_overlap! {
+        program {
+            trait Foo { }
+            trait Bar<T> { }
+            struct Baz { }
+            struct Vec<T> { }
+            impl Foo for Baz { }
+            impl Foo for Baz { }
+            impl<T> Bar<T> for Vec<Baz> { }
+            impl<T> Bar<Baz> for Vec<T> { }
+            impl Foo for Baz { }
+        }
+        overlapping_impls {
+            "Foo" => "Baz: Foo",
+            "Foo" => "Baz: Foo",
+            "Foo" => "Baz: Foo",
+            "Bar" => "Vec<Baz>: Bar<Baz>"
         }
     }
 }
@@ -344,7 +496,7 @@ fn concrete_impl_and_blanket_impl() {
 
 #[test]
 fn two_blanket_impls() {
-    lowering_error! {
+    lowering_overlap! {
         program {
             trait Foo { }
             trait Bar { }
@@ -355,21 +507,17 @@ fn two_blanket_impls() {
             impl Bar for Quux { }
             impl Baz for Quux { }
         }
-        error_msg {
-            "overlapping impls of trait \"Foo\""
+        overlapping_impls {
+            "Foo" => "Quux: Foo"
         }
     }
 }
 
 #[test]
-// FIXME This should be an error
-// We currently assume a closed universe always, but overlaps checking should
-// assume an open universe - what if a client implemented both Bar and Baz
-//
-// In other words, this should have the same behavior as the two_blanket_impls
-// test.
+// Overlap checking assumes an open universe: a downstream crate could
+// implement both Bar and Baz for one of its types.
 fn two_blanket_impls_open_ended() {
-    lowering_success! {
+    lowering_overlap! {
         program {
             trait Foo { }
             trait Bar { }
@@ -377,6 +525,52 @@ fn two_blanket_impls_open_ended() {
             impl<T> Foo for T where T: Bar { }
             impl<T> Foo for T where T: Baz { }
         }
+        overlapping_impls {
+            "Foo" => "?0: Foo"
+        }
+    }
+}
+
+#[test]
+fn upstream_negative_reasoning_in_coherence() {
+    // `Vec<T>: Clone` does not hold today, but the upstream crate could add
+    // such an impl without breaking anyone.
+    lowering_overlap! {
+        program {
+            #[upstream] trait Clone { }
+            #[upstream] struct Vec<T> { }
+            trait Foo { }
+            impl<T> Foo for T where T: Clone { }
+            impl<T> Foo for Vec<T> { }
+        }
+        overlapping_impls {
+            "Foo" => "Vec<?0>: Foo"
+        }
+    }
+}
+
+#[test]
+fn fundamental_negative_reasoning_in_coherence() {
+    // Adding impls of a fundamental trait is a breaking change, and an
+    // upstream crate can never add impls for a local type.
+    lowering_success! {
+        program {
+            #[upstream] #[fundamental] trait Sized { }
+            #[upstream] struct Vec<T> { }
+            trait Foo { }
+            impl<T> Foo for T where T: Sized { }
+            impl<T> Foo for Vec<T> { }
+        }
+    }
+
+    lowering_success! {
+        program {
+            #[upstream] trait Clone { }
+            trait Foo { }
+            struct Bar { }
+            impl<T> Foo for T where T: Clone { }
+            impl Foo for Bar { }
+        }
     }
 }
 
@@ -408,15 +602,15 @@ fn local_negative_reasoning_in_coherence() {
 
 #[test]
 fn multiple_parameters() {
-    lowering_error! {
+    lowering_overlap! {
         program {
             trait Foo<T> { }
             struct Baz { }
 
             impl<T> Foo<Baz> for T { }
             impl<T> Foo<T> for Baz { }
-        } error_msg {
-            "overlapping impls of trait \"Foo\""
+        } overlapping_impls {
+            "Foo" => "Baz: Foo<Baz>"
         }
     }
 }
@@ -446,7 +640,7 @@ fn nonoverlapping_assoc_types() {
 
 #[test]
 fn overlapping_assoc_types() {
-    lowering_error! {
+    lowering_success! {
         program {
             trait Foo<T> { }
 
@@ -456,26 +650,88 @@ fn overlapping_assoc_types() {
             struct Vec<T> { }
             impl<T> Iterator for Vec<T> { type Item = T; }
 
+            // This impl overlaps with the one below, but specializes it.
             impl<T> Foo<<T as Iterator>::Item> for T where T: Iterator { }
 
             impl<A, B> Foo<A> for B { }
-        } error_msg {
-            "overlapping impls of trait \"Foo\""
         }
     }
 }
 
+#[test]
+fn overlapping_assoc_types_error() {
+    lowering_overlap! {
+        program {
+            trait Foo<T> { }
+            trait Bar { }
+
+            trait Iterator { type Item; }
+
+
+            struct Vec<T> { }
+            impl<T> Iterator for Vec<T> { type Item = T; }
+            impl<T> Bar for Vec<T> { }
+
+            impl<T> Foo<<T as Iterator>::Item> for T where T: Iterator { }
+
+            impl<A, B> Foo<A> for B where B: Bar { }
+        } overlapping_impls {
+            "Foo" => "Vec<?0>: Foo<<Vec<?0> as Iterator>::Item>"
+        }
+    }
+}
+
+#[test]
+fn specialize_default_value() {
+    lowering_success! {
+        program {
+            trait Foo { type Assoc; }
+            struct Bar { }
+            impl<T> Foo for T { default type Assoc = Bar; }
+            impl Foo for Bar { type Assoc = Bar; }
+        }
+    }
+}
+
+#[test]
+fn specialize_non_default_value() {
+    let text = "
+        trait Foo { type Assoc; type Other; }
+        struct Bar { }
+        struct Vec<T> { }
+        impl<T> Foo for T { type Assoc = Bar; type Other = Bar; }
+        impl Foo for Bar { type Assoc = Bar; }
+        impl<T> Foo for Vec<T> { type Assoc = Bar; type Other = Bar; }
+    ";
+    let error = parse_and_lower(text).unwrap_err();
+    match *error.kind() {
+        ErrorKind::OverrideNonDefault(ref overrides) => {
+            let actual: Vec<_> = overrides.iter()
+                                          .map(|o| {
+                                              assert!(text[o.span.lo..o.span.hi].starts_with("impl"));
+                                              (o.span.line, o.name.to_string())
+                                          })
+                                          .collect();
+            assert_eq!(actual, vec![(6, "Assoc".to_string()), (7, "Assoc".to_string()), (7, "Other".to_string())]);
+        }
+        ref kind => panic!("expected non-default overrides, found `{}`", kind),
+    }
+    assert!(error.to_string().starts_with(
+        "impl at line 6, column 9 cannot specialize associated type \"Assoc\" of trait \"Foo\": \
+         it is not marked `default`\n"));
+}
+
 #[test]
 fn overlapping_negative_positive_impls() {
-    lowering_error! {
+    lowering_overlap! {
         program {
             trait Send { }
             struct i32 { }
 
             impl Send for i32 { }
             impl !Send for i32 { }
-        } error_msg {
-            "overlapping impls of trait \"Send\""
+        } overlapping_impls {
+            "Send" => "i32: Send"
         }
     }
 }
@@ -499,3 +755,290 @@ fn overlapping_negative_impls() {
         }
     }
 }
+
+#[test]
+fn orphan_check_success() {
+    lowering_success! {
+        program {
+            #[upstream] trait Clone { }
+            #[upstream] trait Into<T> { }
+            #[upstream] struct Vec<T> { }
+            #[upstream] #[fundamental] struct Box<T> { }
+            trait Local { }
+            struct Foo { }
+
+            impl<T> Local for Vec<T> { }
+            impl Clone for Foo { }
+            impl Clone for Box<Foo> { }
+            impl<T> Into<Foo> for Vec<T> { }
+            impl<T> Into<T> for Foo { }
+            #[upstream] impl<T> Clone for Vec<T> { }
+            #[upstream] impl<T> Into<Vec<T>> for Vec<T> { }
+        }
+    }
+}
+
+#[test]
+fn orphan_check_blanket_impl() {
+    lowering_orphan! {
+        program {
+            #[upstream] trait Clone { }
+            impl<T> Clone for T { }
+        }
+        orphan_impls_of { "Clone" }
+    }
+}
+
+#[test]
+fn orphan_check_upstream_type() {
+    lowering_orphan! {
+        program {
+            #[upstream] trait Clone { }
+            #[upstream] struct Vec<T> { }
+            struct Foo { }
+            impl Clone for Vec<Foo> { }
+        }
+        orphan_impls_of { "Clone" }
+    }
+}
+
+#[test]
+fn orphan_check_uncovered_parameter() {
+    lowering_orphan! {
+        program {
+            #[upstream] trait Into<T> { }
+            struct Foo { }
+            impl<T> Into<Foo> for T { }
+        }
+        orphan_impls_of { "Into" }
+    }
+}
+
+#[test]
+fn orphan_check_fundamental_type() {
+    lowering_orphan! {
+        program {
+            #[upstream] trait Into<T> { }
+            #[upstream] #[fundamental] struct Box<T> { }
+            struct Foo { }
+            impl<T> Into<Foo> for Box<T> { }
+        }
+        orphan_impls_of { "Into" }
+    }
+}
+
+#[test]
+fn orphan_check_reports_every_impl() {
+    lowering_orphan! {
+        program {
+            #[upstream] trait Clone { }
+            #[upstream] trait Into<T> { }
+            #[upstream] struct Vec<T> { }
+            struct Foo { }
+            impl<T> Clone for T { }
+            impl Into<Foo> for Foo { }
+            impl<T> Into<Foo> for Vec<T> { }
+            impl<T> Into<Foo> for T { }
+        }
+        orphan_impls_of { "Clone", "Into" }
+    }
+}
+
+#[test]
+fn orphan_check_error_message() {
+    let error = parse_and_lower("
+        #[upstream] trait Clone { }
+        #[upstream] struct Vec<T> { }
+        struct Foo { }
+        impl Clone for Vec<Foo> { }
+    ").unwrap_err();
+    assert_eq!(error.to_string(), "impl of trait \"Clone\" at line 5, column 9 violates the orphan rules");
+}
+
+#[test]
+fn orphan_check_builtin_types() {
+    // References are fundamental, the other built-in types are foreign.
+    lowering_success! {
+        program {
+            #[upstream] trait Clone { }
+            struct Foo { }
+            impl<'a> Clone for &'a Foo { }
+            impl<'a> Clone for &'a mut Foo { }
+        }
+    }
+
+    lowering_orphan! {
+        program {
+            #[upstream] trait Clone { }
+            struct Foo { }
+            impl Clone for [Foo] { }
+        }
+        orphan_impls_of { "Clone" }
+    }
+
+    lowering_orphan! {
+        program {
+            #[upstream] trait Clone { }
+            struct Foo { }
+            impl Clone for (Foo, Foo) { }
+        }
+        orphan_impls_of { "Clone" }
+    }
+}
+
+#[test]
+fn trait_objects() {
+    lowering_success! {
+        program {
+            trait Bar { }
+            trait Foo where Self: Bar { }
+            #[auto] trait Send { }
+            trait Iterator { type Item; }
+            struct u32 { }
+            impl<'a> Bar for dyn Foo + Send + 'a { }
+            impl Bar for dyn Iterator<Item = u32> { }
+        }
+    }
+
+    lowering_error! {
+        program {
+            trait Eq<T> { }
+            trait Foo where Self: Eq<Self> { }
+            trait Bar { }
+            impl Bar for dyn Foo { }
+        }
+        error_msg {
+            "the trait `Foo` cannot be made into an object: \
+             its where clauses refer to `Self` other than as the type being bounded"
+        }
+    }
+
+    lowering_error! {
+        program {
+            trait Foo { type Item<T>; }
+            trait Bar { }
+            impl Bar for dyn Foo { }
+        }
+        error_msg {
+            "the trait `Foo` cannot be made into an object: its associated type `Item` has parameters"
+        }
+    }
+
+    lowering_error! {
+        program {
+            trait Foo { }
+            trait Bar { }
+            impl Bar for dyn Foo + Bar { }
+        }
+        error_msg {
+            "only auto traits can be added to a trait object, but `Foo` and `Bar` are not"
+        }
+    }
+
+    lowering_error! {
+        program {
+            trait Iterator { type Item; }
+            trait Bar { }
+            impl Bar for dyn Iterator { }
+        }
+        error_msg {
+            "the value of the associated type `Item` in trait `Iterator` must be specified"
+        }
+    }
+
+    // Goals are checked against the lowered traits.
+    let program = parse_and_lower("
+        trait Eq<T> { }
+        trait Foo: Eq<Self> { }
+        trait Bar { }
+    ").unwrap();
+    assert_eq!(
+        parse_and_lower_goal(&program, "dyn Foo: Bar").unwrap_err().to_string(),
+        "the trait `Foo` cannot be made into an object: \
+         its where clauses refer to `Self` other than as the type being bounded"
+    );
+    assert!(parse_and_lower_goal(&program, "dyn Bar: Bar").is_ok());
+}
+
+#[test]
+fn supertraits_and_assoc_ty_bounds() {
+    lowering_success! {
+        program {
+            trait Clone { }
+            trait Iterator { type Item; }
+            trait Foo<T>: Clone + Iterator<Item = T> where T: Clone {
+                type Assoc: Clone + Iterator<Item = T>;
+            }
+        }
+    }
+
+    lowering_error! {
+        program {
+            trait Clone { }
+            #[auto] trait Send: Clone { }
+        }
+        error_msg {
+            "auto trait cannot have where clauses"
+        }
+    }
+
+    lowering_error! {
+        program {
+            trait Iterator { type Item; }
+            trait Foo: Iterator<Elem = Self> { }
+        }
+        error_msg {
+            "no associated type `Elem` defined in trait"
+        }
+    }
+}
+
+#[test]
+fn well_formed_items() {
+    lowering_wf! {
+        program {
+            trait Clone { }
+            trait Eq { }
+            struct Set<T> where T: Eq { }
+            struct Wrapper<T> where T: Eq { set: Set<T> }
+            impl<T> Clone for Set<T> where T: Eq { }
+
+            trait Iterator { type Item: Clone; }
+            struct Foo { }
+            impl Clone for Foo { }
+            impl Iterator for Set<Foo> where Foo: Eq { type Item = Foo; }
+        }
+        ill_formed { }
+    }
+}
+
+#[test]
+fn ill_formed_struct() {
+    lowering_wf! {
+        program {
+            trait Eq { }
+            struct Set<T> where T: Eq { }
+            struct Wrapper<T> { set: Set<T> }
+        }
+        ill_formed { "Wrapper" }
+    }
+}
+
+#[test]
+fn ill_formed_impls() {
+    lowering_wf! {
+        program {
+            trait Clone { }
+            trait Copy where Self: Clone { }
+            struct Foo { }
+            struct Bar { }
+            impl Clone for Foo { }
+            impl Copy for Foo { }
+            impl Copy for Bar { }
+
+            trait Iterator { type Item: Clone; }
+            impl Iterator for Foo { type Item = Bar; }
+        }
+        ill_formed { "Copy", "Iterator" }
+    }
+}
@@ -0,0 +1,178 @@
+use std::fmt;
+use std::sync::Arc;
+
+use chalk_parse::ast::Span;
+use cast::*;
+use errors::*;
+use fold::Subst;
+use ir::*;
+use itertools::Itertools;
+use solve::solver::{Solver, SolverConfig};
+
+use super::ToParameter;
+
+/// A struct or impl of the current crate which is not well-formed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum IllFormedItem {
+    /// Some field type of the struct is not well-formed under its where
+    /// clauses.
+    Struct { name: Identifier, span: Span },
+
+    /// The trait reference of the impl, or one of its associated type values,
+    /// is not well-formed under its where clauses; `trait_id` is the name of
+    /// the trait.
+    Impl { trait_id: Identifier, span: Span },
+}
+
+impl IllFormedItem {
+    pub fn span(&self) -> Span {
+        match *self {
+            IllFormedItem::Struct { span, .. } | IllFormedItem::Impl { span, .. } => span,
+        }
+    }
+}
+
+impl fmt::Display for IllFormedItem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            IllFormedItem::Struct { name, span } => {
+                write!(f, "struct {:?} at {} is not well-formed", name, span)
+            }
+            IllFormedItem::Impl { trait_id, span } => {
+                write!(f, "impl of trait {:?} at {} is not well-formed", trait_id, span)
+            }
+        }
+    }
+}
+
+impl Program {
+    /// Check that every struct and impl declared in the current crate is
+    /// well-formed, assuming its where clauses (and that its type parameters
+    /// are well-formed). Given:
+    ///
+    /// ```notrust
+    /// struct Set<T> where T: Hash { tree: Tree<T> }
+    /// impl<T> Clone for Set<T> where T: Clone { }
+    /// ```
+    ///
+    /// we pose the goals:
+    ///
+    /// ```notrust
+    /// forall<T> { if (WF(T), T: Hash) { WF(Tree<T>) } }
+    /// forall<T> { if (WF(T), T: Clone) { WF(Set<T>: Clone) } }
+    /// ```
+    ///
+    /// For an impl, the value of each associated type must moreover be
+    /// well-formed and meet the bounds declared in the trait. Items marked
+    /// `#[upstream]` are assumed to have been checked in their own crate, and
+    /// negative impls and `default impl`s have nothing to check: the latter
+    /// do not implement their trait.
+    ///
+    /// The goals are solved against `env`, the environment of this program.
+    pub fn verify_well_formedness(&self, env: &Arc<ProgramEnvironment>, config: SolverConfig) -> Result<()> {
+        let mut solver = Solver::new(env, config);
+
+        let mut ill_formed = vec![];
+        for (&id, struct_datum) in &self.struct_data {
+            if struct_datum.binders.value.upstream {
+                continue;
+            }
+            if !self.is_well_formed(&mut solver, self.struct_wf_goal(struct_datum))? {
+                let name = self.type_kinds[&id].name;
+                ill_formed.push(IllFormedItem::Struct { name, span: self.item_spans[&id] });
+            }
+        }
+
+        for (&id, impl_datum) in &self.impl_data {
+            let bound = &impl_datum.binders.value;
+            if bound.upstream || bound.default || !bound.trait_ref.is_positive() {
+                continue;
+            }
+            if !self.is_well_formed(&mut solver, self.impl_wf_goal(impl_datum))? {
+                let trait_id = self.type_kinds[&bound.trait_ref.trait_ref().trait_id].name;
+                ill_formed.push(IllFormedItem::Impl { trait_id, span: self.item_spans[&id] });
+            }
+        }
+
+        if ill_formed.is_empty() {
+            Ok(())
+        } else {
+            ill_formed.sort_by_key(|item| item.span().lo);
+            Err(Error::from_kind(ErrorKind::IllFormedItems(ill_formed)))
+        }
+    }
+
+    fn is_well_formed(&self, solver: &mut Solver, goal: Option<Goal>) -> Result<bool> {
+        let goal = match goal {
+            Some(goal) => goal,
+            None => return Ok(true),
+        };
+        match solver.solve_closed_goal(InEnvironment::empty(goal)) {
+            Ok(solution) => Ok(solution.is_unique()),
+            Err(e) => if e.is_overflow() { Err(e) } else { Ok(false) },
+        }
+    }
+
+    fn struct_wf_goal(&self, struct_datum: &StructDatum) -> Option<Goal> {
+        let bound = &struct_datum.binders.value;
+        let goals = bound.fields.iter().cloned().map(|ty| WellFormed::Ty(ty).cast());
+        let assumptions = self.assumptions(&struct_datum.binders.binders, &bound.where_clauses);
+        conjunction(goals).map(|goal| {
+            goal.implied_by(assumptions)
+                .quantify(QuantifierKind::ForAll, struct_datum.binders.binders.clone())
+        })
+    }
+
+    fn impl_wf_goal(&self, impl_datum: &ImplDatum) -> Option<Goal> {
+        let bound = &impl_datum.binders.value;
+        let trait_ref = bound.trait_ref.trait_ref();
+
+        let values = bound.associated_ty_values.iter().filter_map(|value| {
+            // The bounds of the associated type, where `Self` and the other
+            // parameters of the trait are those of the impl, and the other
+            // parameters are bound by the value, as in
+            // `forall<'a> { <Vec<T> as Iterable>::Iter<'a>: Iterator }`.
+            let datum = &self.associated_ty_data[&value.associated_ty_id];
+            let num_binders = value.value.binders.len();
+            let parameters: Vec<_> = value.value.binders
+                                                .iter()
+                                                .zip(0..)
+                                                .map(|p| p.to_parameter())
+                                                .chain(trait_ref.up_shift(num_binders).parameters)
+                                                .collect();
+            let bounds = Subst::apply(&parameters, &datum.where_clauses);
+
+            let ty = WellFormed::Ty(value.value.value.ty.clone()).cast();
+            let goals = Some(ty).into_iter().chain(bounds.into_iter().casted());
+            let assumptions = self.assumptions(&value.value.binders, &value.value.value.where_clauses);
+            conjunction(goals).map(|goal| {
+                goal.implied_by(assumptions)
+                    .quantify(QuantifierKind::ForAll, value.value.binders.clone())
+            })
+        });
+
+        let goals = Some(WellFormed::TraitRef(trait_ref.clone()).cast()).into_iter().chain(values);
+        let assumptions = self.assumptions(&impl_datum.binders.binders, &bound.where_clauses);
+        conjunction(goals).map(|goal| {
+            goal.implied_by(assumptions)
+                .quantify(QuantifierKind::ForAll, impl_datum.binders.binders.clone())
+        })
+    }
+
+    /// What an item with the given parameters and where clauses may assume:
+    /// that its type parameters are well-formed, and its where clauses.
+    fn assumptions(&self, binders: &[ParameterKind<()>], where_clauses: &[DomainGoal]) -> Vec<DomainGoal> {
+        let tys = binders.iter()
+                         .zip(0..)
+                         .filter_map(|p| p.to_parameter().ty())
+                         .map(|ty| WellFormed::Ty(ty).cast());
+        let where_clauses = where_clauses.iter()
+                                         .cloned()
+                                         .flat_map(|wc| wc.assumed(self));
+        tys.chain(where_clauses).collect()
+    }
+}
+
+fn conjunction<I: IntoIterator<Item = Goal>>(goals: I) -> Option<Goal> {
+    goals.into_iter().fold1(|goal, leaf| Goal::And(Box::new(goal), Box::new(leaf)))
+}
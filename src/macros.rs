@@ -1,70 +1,46 @@
-use std::cell::Cell;
+//! Macros emitting trace events, see the `trace` module. The message is only
+//! formatted if tracing is enabled on the current thread.
 
-lazy_static! {
-    pub static ref DEBUG_ENABLED: bool = {
-        use std::env;
-        env::var("CHALK_DEBUG").is_ok()
-    };
+macro_rules! event {
+    ($level:ident, $($t:tt)*) => {
+        if ::trace::enabled() {
+            ::trace::event(::trace::Level::$level, module_path!(), format!($($t)*));
+        }
+    }
 }
 
-thread_local! {
-    pub static INDENT: Cell<usize> = Cell::new(0);
+macro_rules! info {
+    ($($t:tt)*) => { event!(Info, $($t)*) }
 }
 
 macro_rules! debug {
-    ($($t:tt)*) => {
-        if *::macros::DEBUG_ENABLED {
-            ::macros::dump(format!($($t)*));
-        }
-    }
+    ($($t:tt)*) => { event!(Debug, $($t)*) }
 }
 
-macro_rules! debug_heading {
-    ($($t:tt)*) => {
-        debug!($($t)*);
-        let _ = &::macros::Indent::new();
-    }
+macro_rules! trace {
+    ($($t:tt)*) => { event!(Trace, $($t)*) }
 }
 
-pub fn dump(string: String) {
-    if !*DEBUG_ENABLED {
-        return;
-    }
-
-    let indent = ::macros::INDENT.with(|i| i.get());
-    let mut first = true;
-    for line in string.lines() {
-        if first {
-            for _ in 0..indent {
-                print!("| ");
-            }
+/// Emit a heading: the events which follow, up to the end of the enclosing
+/// block, are nested in it.
+macro_rules! heading {
+    ($level:ident, $($t:tt)*) => {
+        let _ = &if ::trace::enabled() {
+            ::trace::heading(::trace::Level::$level, module_path!(), format!($($t)*))
         } else {
-            for _ in 0..indent {
-                print!("  ");
-            }
-        }
-        println!("{}", line);
-        first = false;
+            ::trace::Heading::inactive()
+        };
     }
 }
 
-pub struct Indent {
-    dummy: ()
+macro_rules! info_heading {
+    ($($t:tt)*) => { heading!(Info, $($t)*) }
 }
 
-impl Indent {
-    pub fn new() -> Self {
-        if *DEBUG_ENABLED {
-            INDENT.with(|i| i.set(i.get() + 1));
-        }
-        Indent { dummy: () }
-    }
+macro_rules! debug_heading {
+    ($($t:tt)*) => { heading!(Debug, $($t)*) }
 }
 
-impl Drop for Indent {
-    fn drop(&mut self) {
-        if *DEBUG_ENABLED {
-            INDENT.with(|i| i.set(i.get() - 1));
-        }
-    }
+macro_rules! trace_heading {
+    ($($t:tt)*) => { heading!(Trace, $($t)*) }
 }
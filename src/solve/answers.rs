@@ -0,0 +1,182 @@
+use std::collections::VecDeque;
+use std::fmt;
+
+use ir::*;
+use solve::fulfill::{AnswerStep, Fulfill, FulfillState};
+use solve::solver::Solver;
+
+/// An iterator over the distinct answers to a goal, see `Solver::answers`.
+///
+/// Where the solver would merge the solutions obtained from several clauses
+/// into an ambiguous one, the search tries each clause separately. The
+/// search proceeds breadth-first, so that every answer is eventually found
+/// even if there are infinitely many of them.
+pub struct Answers<'s> {
+    solver: &'s mut Solver,
+
+    /// The substitution whose values are the answers; its variables are the
+    /// same in every search state.
+    subst: Substitution,
+
+    /// Search states remaining to explore, with the number of clauses that
+    /// were tried one at a time to reach them.
+    queue: VecDeque<(FulfillState, usize)>,
+
+    found: Vec<Canonical<ConstrainedSubst>>,
+
+    /// Whether the search was cut short, in some state, by the overflow depth
+    /// of the solver.
+    overflowed: bool,
+
+    /// Whether some state had a goal which built-in clauses that cannot be
+    /// listed may prove, see `DomainGoal::has_unlisted_builtin_candidates`.
+    unlisted: bool,
+}
+
+/// The answers found by `Answers::take_answers`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AnswerSet {
+    pub answers: Vec<Canonical<ConstrainedSubst>>,
+    pub status: AnswerSetStatus,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AnswerSetStatus {
+    /// These are all the answers.
+    Complete,
+
+    /// There are more answers than the limit that was given.
+    Truncated,
+
+    /// The search was cut short by the overflow depth, as happens when there
+    /// are infinitely many answers; those that were found are listed.
+    Infinite,
+
+    /// Some answers could not be enumerated, e.g. those where a type of
+    /// unknown shape is a tuple of some arity; those that were found are
+    /// listed.
+    Incomplete,
+}
+
+impl Solver {
+    /// Enumerate the distinct answers to a closed goal. Each answer is a
+    /// substitution for the existential variables of the goal, as in the
+    /// unique solutions of `solve_closed_goal`.
+    ///
+    /// States of the search where only goals that can be neither solved nor
+    /// split according to clauses remain (e.g. a negative goal involving
+    /// unresolved variables) yield no answer.
+    pub fn answers(&mut self, goal: InEnvironment<Goal>) -> Answers {
+        let (subst, state) = {
+            let mut fulfill = Fulfill::new(self);
+            fulfill.push_goal(&goal.environment, goal.goal);
+            (fulfill.initial_subst(), fulfill.into_state())
+        };
+
+        Answers {
+            solver: self,
+            subst,
+            queue: Some((state, 0)).into_iter().collect(),
+            found: vec![],
+            overflowed: false,
+            unlisted: false,
+        }
+    }
+}
+
+impl<'s> Answers<'s> {
+    /// Collect up to `limit` answers.
+    pub fn take_answers(mut self, limit: usize) -> AnswerSet {
+        let answers: Vec<_> = self.by_ref().take(limit).collect();
+        let status = if answers.len() == limit && self.next().is_some() {
+            AnswerSetStatus::Truncated
+        } else if self.overflowed {
+            AnswerSetStatus::Infinite
+        } else if self.unlisted {
+            AnswerSetStatus::Incomplete
+        } else {
+            AnswerSetStatus::Complete
+        };
+        AnswerSet { answers, status }
+    }
+
+    /// Queue a state for each clause that could prove `goal`, in which the
+    /// clause was applied.
+    fn branch(&mut self, state: FulfillState, depth: usize, goal: InEnvironment<DomainGoal>) {
+        if !self.unlisted {
+            let canonical = Fulfill::from_state(self.solver, state.clone()).canonicalize(&goal.goal);
+            self.unlisted = canonical.value.has_unlisted_builtin_candidates(&*self.solver.program);
+        }
+
+        let env_clauses = goal.environment.clauses.iter()
+            .cloned()
+            .map(DomainGoal::into_program_clause);
+        let mut prog_clauses = self.solver.program.program_clauses_for(&goal.goal);
+        prog_clauses.extend(goal.goal.builtin_clauses(&*self.solver.program));
+        let clauses: Vec<_> = env_clauses.chain(prog_clauses).collect();
+
+        for ProgramClause { implication, .. } in clauses {
+            let mut fulfill = Fulfill::from_state(self.solver, state.clone());
+            let ProgramClauseImplication { consequence, conditions } =
+                fulfill.instantiate_in(goal.environment.universe, implication.binders, &implication.value);
+
+            if fulfill.unify(&goal.environment, &goal.goal, &consequence).is_err() {
+                continue;
+            }
+
+            for condition in conditions {
+                fulfill.push_goal(&goal.environment, condition);
+            }
+            self.queue.push_back((fulfill.into_state(), depth + 1));
+        }
+    }
+}
+
+impl<'s> Iterator for Answers<'s> {
+    type Item = Canonical<ConstrainedSubst>;
+
+    fn next(&mut self) -> Option<Canonical<ConstrainedSubst>> {
+        while let Some((state, depth)) = self.queue.pop_front() {
+            if depth > self.solver.config.overflow_depth {
+                self.overflowed = true;
+                continue;
+            }
+
+            let mut fulfill = Fulfill::from_state(self.solver, state);
+            let step = fulfill.answer_step(&self.subst);
+            let state = fulfill.into_state();
+
+            match step {
+                Ok(AnswerStep::Answer(answer)) => {
+                    if !self.found.contains(&answer) {
+                        self.found.push(answer.clone());
+                        return Some(answer);
+                    }
+                }
+                Ok(AnswerStep::Branch(goal)) => self.branch(state, depth, goal),
+                Ok(AnswerStep::Stuck) => (),
+                Err(e) => self.overflowed = self.overflowed || e.is_overflow(),
+            }
+        }
+
+        None
+    }
+}
+
+impl fmt::Display for AnswerSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        for answer in &self.answers {
+            writeln!(f, "substitution [{}], lifetime constraints {:?}",
+                     &answer.value.subst,
+                     &answer.value.constraints)?;
+        }
+        let count = self.answers.len();
+        let noun = if count == 1 { "answer" } else { "answers" };
+        match self.status {
+            AnswerSetStatus::Complete => write!(f, "{} {}", count, noun),
+            AnswerSetStatus::Truncated => write!(f, "first {} {}; there are more", count, noun),
+            AnswerSetStatus::Infinite => write!(f, "{} {}; there may be infinitely many", count, noun),
+            AnswerSetStatus::Incomplete => write!(f, "{} {}; there may be others", count, noun),
+        }
+    }
+}
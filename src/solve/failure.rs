@@ -0,0 +1,174 @@
+use std::fmt;
+
+use errors::*;
+use ir::*;
+use super::proof::{ClauseSource, ProofFrame, fmt_clause, fmt_goal};
+
+/// An explanation of why the solver could not prove a goal. Explanations are
+/// only built when asked for, see `Solver::explain_failure`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Failure {
+    /// `goal` could not be proven, because of `reason`.
+    NotProved {
+        goal: FullyReducedGoal,
+        reason: FailureReason,
+    },
+
+    /// `not { goal }` does not hold, since `goal` could be proven.
+    NotRefuted {
+        goal: InEnvironment<Goal>,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FailureReason {
+    /// Unification failed.
+    Mismatch(Mismatch),
+
+    /// One of the goals the failed goal depends upon failed.
+    Premise(Box<Failure>),
+
+    /// None of the clauses which could prove the goal applied (there may be
+    /// no such clause at all).
+    NoCandidates(Vec<Candidate>),
+
+    /// The goal was already being proven further up the stack, and no
+    /// answer was found for it yet.
+    Cycle,
+
+    /// The failure was found in the cache of the solver, which had no
+    /// explanation for it.
+    Cached,
+
+    /// The explanation is deeper than what was asked for.
+    Pruned,
+}
+
+/// A clause that could have proven a goal, but did not apply.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Candidate {
+    pub source: ClauseSource,
+    pub clause: Binders<ProgramClauseImplication>,
+
+    /// Either a `Mismatch` between the goal and the consequence of the
+    /// clause, or the failed `Premise`.
+    pub reason: FailureReason,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Mismatch {
+    /// Two types with different type constructors had to be equated.
+    Types(Ty, Ty),
+
+    /// Any other unification failure, e.g. an occurs check.
+    Other(String),
+}
+
+impl Mismatch {
+    fn from_error(error: &Error) -> Mismatch {
+        match *error.kind() {
+            ErrorKind::CannotEquate(ref a, ref b) => Mismatch::Types(a.clone(), b.clone()),
+            _ => Mismatch::Other(error.to_string()),
+        }
+    }
+}
+
+impl Failure {
+    /// Replace the parts of the explanation nested more than `depth` goals
+    /// deep by `FailureReason::Pruned`.
+    pub fn prune(&mut self, depth: usize) {
+        if let Failure::NotProved { ref mut reason, .. } = *self {
+            reason.prune(depth);
+        }
+    }
+
+    fn fmt_indented(&self, fmt: &mut fmt::Formatter, indent: usize) -> fmt::Result {
+        match *self {
+            Failure::NotProved { ref goal, ref reason } => {
+                write!(fmt, "{:indent$}`", "", indent = indent)?;
+                match *goal {
+                    FullyReducedGoal::EqGoal(ref goal) => fmt_goal(fmt, &goal.value)?,
+                    FullyReducedGoal::DomainGoal(ref goal) => fmt_goal(fmt, &goal.value)?,
+                }
+                writeln!(fmt, "` does not hold")?;
+                reason.fmt_indented(fmt, indent + 2)
+            }
+            Failure::NotRefuted { ref goal } => {
+                write!(fmt, "{:indent$}`not {{ ", "", indent = indent)?;
+                fmt_goal(fmt, goal)?;
+                writeln!(fmt, " }}` does not hold")?;
+                writeln!(fmt, "{:indent$}  the negated goal can be proven", "", indent = indent)
+            }
+        }
+    }
+}
+
+impl FailureReason {
+    /// The reason why the goal (or clause) of `frame` failed with `error`:
+    /// either one of the goals solved in the frame failed, or unification did.
+    pub(super) fn from_frame(frame: ProofFrame, error: &Error) -> FailureReason {
+        match frame.failure {
+            Some(failure) => FailureReason::Premise(Box::new(failure)),
+            None => FailureReason::Mismatch(Mismatch::from_error(error)),
+        }
+    }
+
+    fn prune(&mut self, depth: usize) {
+        match *self {
+            FailureReason::Premise(ref mut failure) if depth > 0 => failure.prune(depth - 1),
+            FailureReason::NoCandidates(ref mut candidates) if depth > 0 => {
+                for candidate in candidates {
+                    candidate.reason.prune(depth);
+                }
+            }
+            FailureReason::Premise(_) | FailureReason::NoCandidates(_) => *self = FailureReason::Pruned,
+            FailureReason::Mismatch(_) |
+            FailureReason::Cycle |
+            FailureReason::Cached |
+            FailureReason::Pruned => (),
+        }
+    }
+
+    fn fmt_indented(&self, fmt: &mut fmt::Formatter, indent: usize) -> fmt::Result {
+        match *self {
+            FailureReason::Mismatch(ref mismatch) => {
+                writeln!(fmt, "{:indent$}{}", "", mismatch, indent = indent)
+            }
+            FailureReason::Premise(ref failure) => failure.fmt_indented(fmt, indent),
+            FailureReason::NoCandidates(ref candidates) if candidates.is_empty() => {
+                writeln!(fmt, "{:indent$}no clause can prove it", "", indent = indent)
+            }
+            FailureReason::NoCandidates(ref candidates) => {
+                for candidate in candidates {
+                    write!(fmt, "{:indent$}{} `", "", candidate.source, indent = indent)?;
+                    fmt_clause(fmt, &candidate.clause)?;
+                    writeln!(fmt, "` does not apply")?;
+                    candidate.reason.fmt_indented(fmt, indent + 2)?;
+                }
+                Ok(())
+            }
+            FailureReason::Cycle => {
+                writeln!(fmt, "{:indent$}it depends on itself", "", indent = indent)
+            }
+            FailureReason::Cached => {
+                writeln!(fmt, "{:indent$}the failure was cached without an explanation", "", indent = indent)
+            }
+            FailureReason::Pruned => writeln!(fmt, "{:indent$}...", "", indent = indent),
+        }
+    }
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Mismatch::Types(ref a, ref b) => write!(fmt, "cannot equate `{:?}` and `{:?}`", a, b),
+            Mismatch::Other(ref message) => write!(fmt, "{}", message),
+        }
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_indented(fmt, 0)
+    }
+}
@@ -3,6 +3,8 @@ use errors::*;
 use fold::Fold;
 use solve::infer::{InferenceTable, UnificationResult, ParameterInferenceVariable};
 use solve::infer::{TyInferenceVariable, LifetimeInferenceVariable};
+use solve::failure::Failure;
+use solve::proof::ProofTree;
 use solve::solver::Solver;
 use std::collections::HashSet;
 use std::fmt::Debug;
@@ -52,6 +54,29 @@ enum NegativeSolution {
     CannotProve,
 }
 
+/// The state of a `Fulfill`, detached from its solver so that it can be
+/// cloned. This is used to explore several ways of solving the same goals.
+#[derive(Clone)]
+pub struct FulfillState {
+    infer: InferenceTable,
+    obligations: Vec<Obligation>,
+    constraints: HashSet<InEnvironment<Constraint>>,
+    cannot_prove: bool,
+}
+
+/// What `Fulfill::answer_step` learned about the pending obligations.
+pub enum AnswerStep {
+    /// All obligations were fulfilled, yielding an answer.
+    Answer(Canonical<ConstrainedSubst>),
+
+    /// This domain goal, which was removed from the obligations, is
+    /// ambiguous; each clause that could prove it may lead to other answers.
+    Branch(InEnvironment<DomainGoal>),
+
+    /// The remaining obligations can neither be solved nor branched upon.
+    Stuck,
+}
+
 /// A `Fulfill` is where we actually break down complex goals, instantiate
 /// variables, and perform inference. It's highly stateful. It's generally used
 /// in Chalk to try to solve a goal, and then package up what was learned in a
@@ -90,12 +115,22 @@ impl<'s> Fulfill<'s> {
         }
     }
 
+    pub fn from_state(solver: &'s mut Solver, state: FulfillState) -> Self {
+        let FulfillState { infer, obligations, constraints, cannot_prove } = state;
+        Fulfill { solver, infer, obligations, constraints, cannot_prove }
+    }
+
+    pub fn into_state(self) -> FulfillState {
+        let Fulfill { infer, obligations, constraints, cannot_prove, .. } = self;
+        FulfillState { infer, obligations, constraints, cannot_prove }
+    }
+
     /// Wraps `InferenceTable::instantiate`
     pub fn instantiate<U, T>(&mut self, universes: U, arg: &T) -> T::Result
         where T: Fold,
               U: IntoIterator<Item = ParameterKind<UniverseIndex>>
     {
-        self.infer.instantiate(universes, arg)
+        self.counting_variables(|infer| infer.instantiate(universes, arg))
     }
 
     /// Wraps `InferenceTable::instantiate_in`
@@ -106,7 +141,30 @@ impl<'s> Fulfill<'s> {
         where T: Fold,
               U: IntoIterator<Item = ParameterKind<()>>
     {
-        self.infer.instantiate_in(universe, binders, arg)
+        self.counting_variables(|infer| infer.instantiate_in(universe, binders, arg))
+    }
+
+    /// Run `op` on the inference table, adding the variables it creates to the
+    /// statistics of the solver.
+    fn counting_variables<F, R>(&mut self, op: F) -> R
+        where F: FnOnce(&mut InferenceTable) -> R
+    {
+        let before = self.infer.variables_created();
+        let result = op(&mut self.infer);
+        self.solver.stats.inference_variables += self.infer.variables_created() - before;
+        result
+    }
+
+    /// Record that the consequence of a clause unified with the goal it was
+    /// tried on, see `SolverStats`.
+    pub fn record_clause_unified(&mut self) {
+        self.solver.stats.clauses_unified += 1;
+    }
+
+    /// Wraps `InferenceTable::canonicalize`; the variables of `value` which
+    /// are unresolved become those of the result.
+    pub fn canonicalize<T: Fold>(&mut self, value: &T) -> Canonical<T::Result> {
+        self.infer.canonicalize(value).quantified
     }
 
     /// Unifies `a` and `b` in the given environment.
@@ -117,7 +175,7 @@ impl<'s> Fulfill<'s> {
         where T: ?Sized + Zip + Debug
     {
         let UnificationResult { goals, constraints, cannot_prove } =
-            self.infer.unify(environment, a, b)?;
+            self.counting_variables(|infer| infer.unify(environment, a, b))?;
         debug!("unify({:?}, {:?}) succeeded", a, b);
         debug!("unify: goals={:?}", goals);
         debug!("unify: constraints={:?}", constraints);
@@ -174,6 +232,10 @@ impl<'s> Fulfill<'s> {
                 let in_env = InEnvironment::new(environment, *subgoal);
                 self.obligations.push(Obligation::Refute(in_env));
             }
+            Goal::Leaf(LeafGoal::DomainGoal(DomainGoal::Outlives(Outlives { a, b }))) => {
+                // Outlives goals are left to region checking.
+                self.constraints.insert(InEnvironment::new(environment, Constraint::Outlives(a, b)));
+            }
             Goal::Leaf(wc) => {
                 self.obligations.push(Obligation::Prove(InEnvironment::new(environment, wc)));
             }
@@ -216,15 +278,24 @@ impl<'s> Fulfill<'s> {
             return Ok(NegativeSolution::Ambiguous);
         }
 
-        // Negate the result
-        if let Ok(solution) = self.solver.solve_closed_goal(canonicalized.quantified.value) {
-            match solution {
-                Solution::Unique(_) => Err("refutation failed")?,
-                Solution::Ambig(_) => Ok(NegativeSolution::Ambiguous),
-                Solution::CannotProve => Ok(NegativeSolution::CannotProve),
+        // Negate the result. The proof of the negated goal, if any, is not
+        // part of the proof tree under construction.
+        let goal = canonicalized.quantified.value;
+        match self.solver.without_proof(|solver| solver.solve_closed_goal(goal.clone())) {
+            Ok(Solution::Unique(_)) => {
+                self.solver.record_failure(Failure::NotRefuted { goal });
+                Err("refutation failed")?
             }
-        } else {
-            Ok(NegativeSolution::Refuted)
+            Ok(Solution::Ambig(_)) => Ok(NegativeSolution::Ambiguous),
+            Ok(Solution::CannotProve) => Ok(NegativeSolution::CannotProve),
+
+            // Failing because of overflow does not mean the goal is false.
+            Err(e) => if e.is_overflow() {
+                Err(e)
+            } else {
+                self.solver.record_proof(ProofTree::Refuted { goal });
+                Ok(NegativeSolution::Refuted)
+            },
         }
     }
 
@@ -238,6 +309,22 @@ impl<'s> Fulfill<'s> {
         self.infer.lifetime_vars()
     }
 
+    /// A substitution mapping each of the variables created so far to itself.
+    /// Applying the solution of a goal to it recovers the values of the
+    /// existential variables of the goal.
+    pub fn initial_subst(&self) -> Substitution {
+        Substitution {
+            tys: self.ty_vars()
+                .iter()
+                .map(|t| (*t, t.to_ty()))
+                .collect(),
+            lifetimes: self.lifetime_vars()
+                .iter()
+                .map(|lt| (*lt, lt.to_lifetime()))
+                .collect(),
+        }
+    }
+
     /// Apply the subsitution `subst` to all the variables of `free_vars`
     /// (understood in deBruijn style), and add any lifetime constraints.
     fn apply_solution(&mut self,
@@ -345,6 +432,35 @@ impl<'s> Fulfill<'s> {
         }
     }
 
+    /// Used to enumerate answers: fulfill the obligations that can be, then
+    /// either produce the answer for `subst`, or pick an ambiguous domain goal
+    /// whose candidate clauses should be tried one at a time.
+    pub fn answer_step(&mut self, subst: &Substitution) -> Result<AnswerStep> {
+        let outcome = self.fulfill()?;
+
+        if self.cannot_prove {
+            return Ok(AnswerStep::Stuck);
+        }
+
+        if outcome.is_complete() {
+            let constraints = self.constraints.iter().cloned().collect();
+            let constrained = self.infer.canonicalize(&ConstrainedSubst { subst: subst.clone(), constraints });
+            return Ok(AnswerStep::Answer(constrained.quantified));
+        }
+
+        let position = self.obligations.iter().position(|obligation| match *obligation {
+            Obligation::Prove(InEnvironment { goal: LeafGoal::DomainGoal(_), .. }) => true,
+            _ => false,
+        });
+
+        match position.map(|i| self.obligations.remove(i)) {
+            Some(Obligation::Prove(InEnvironment { environment, goal: LeafGoal::DomainGoal(goal) })) => {
+                Ok(AnswerStep::Branch(InEnvironment { environment, goal }))
+            }
+            _ => Ok(AnswerStep::Stuck),
+        }
+    }
+
     /// Try to fulfill all pending obligations and build the resulting
     /// solution. The returned solution will transform `subst` substitution with
     /// the outcome of type inference by updating the replacements it provides.
@@ -379,7 +495,7 @@ impl<'s> Fulfill<'s> {
 
             while let Some(obligation) = self.obligations.pop() {
                 if let Obligation::Prove(goal) = obligation {
-                    let PositiveSolution { free_vars, solution } = self.prove(&goal).unwrap();
+                    let PositiveSolution { free_vars, solution } = self.prove(&goal)?;
                     if let Some(constrained_subst) = solution.constrained_subst() {
                         self.apply_solution(free_vars, constrained_subst);
                         let subst = self.infer.canonicalize(&subst);
@@ -0,0 +1,286 @@
+use std::fmt;
+use std::sync::Arc;
+
+use chalk_parse;
+use errors::*;
+use ir;
+use lower::*;
+use solve::solver::{Engine, Solver, SolverConfig};
+
+/// A program together with goals to solve against it and their expected
+/// results, as read from a `.chalk` test file:
+///
+/// ```notrust
+/// program {
+///     struct Foo { }
+///     struct Bar { }
+///     trait Clone { }
+///     impl Clone for Foo { }
+/// }
+///
+/// goal {
+///     Foo: Clone
+/// } yields {
+///     "Unique; substitution []"
+/// }
+///
+/// goal {
+///     Bar: Clone
+/// } yields {
+///     "No possible solution: no applicable candidates"
+/// } yields[SLG] {
+///     "No possible solution: no solution found"
+/// }
+/// ```
+///
+/// Every goal is solved with each engine; a `yields[SLG]` block gives the
+/// expected result of the SLG engine where it differs from that of the
+/// recursive one. Expected results are compared with actual ones as in the
+/// `test!` macro of `solve::test`: ignoring whitespace and quotes, they must be
+/// a prefix of the actual result. A goal that cannot be solved yields `No
+/// possible solution: ..`, and one that cannot be lowered `error: ..`. Lines
+/// starting with `//` outside of blocks are comments.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TestFile {
+    pub program: String,
+    pub goals: Vec<GoalTest>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GoalTest {
+    /// The line of the `goal` keyword, starting from 1; `None` for goals
+    /// that were not read from a file.
+    pub line: Option<usize>,
+    pub goal: String,
+    pub expected: String,
+    pub slg_expected: Option<String>,
+}
+
+/// A goal whose result was not the expected one.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TestFailure {
+    /// The position of the goal in the file, starting from 1.
+    pub index: usize,
+    pub line: Option<usize>,
+    pub goal: String,
+    pub engine: Engine,
+    pub expected: String,
+    pub actual: String,
+}
+
+impl TestFile {
+    pub fn parse(text: &str) -> Result<TestFile> {
+        let mut scanner = Scanner { text, pos: 0 };
+
+        scanner.expect_keyword("program")?;
+        let program = scanner.block()?;
+
+        let mut goals = vec![];
+        while !scanner.at_end() {
+            scanner.expect_keyword("goal")?;
+            let line = Some(scanner.line());
+            let goal = scanner.block()?;
+            scanner.expect_keyword("yields")?;
+            let expected = scanner.block()?;
+            let slg_expected = if scanner.keyword("yields[SLG]") {
+                Some(scanner.block()?)
+            } else {
+                None
+            };
+            goals.push(GoalTest { line, goal, expected, slg_expected });
+        }
+
+        Ok(TestFile { program, goals })
+    }
+
+    /// Solve every goal with each engine, returning those whose result was not
+    /// the expected one. Fails if the program itself cannot be lowered, or is
+    /// not well-formed. The program is lowered and checked with `config`, and
+    /// goals are solved with it too, but for its `engine`.
+    ///
+    /// Each goal is solved by a fresh solver, so that its result does not
+    /// depend on what was cached while solving the goals before it.
+    pub fn run(&self, config: SolverConfig) -> Result<Vec<TestFailure>> {
+        self.run_goals(config, true)
+    }
+
+    /// Like `run`, but for programs that are ill-formed on purpose, e.g. to
+    /// test the well-formedness goals themselves.
+    pub fn run_unchecked(&self, config: SolverConfig) -> Result<Vec<TestFailure>> {
+        self.run_goals(config, false)
+    }
+
+    fn run_goals(&self, config: SolverConfig, check_well_formedness: bool) -> Result<Vec<TestFailure>> {
+        let program = Arc::new(chalk_parse::parse_program(&self.program)?.lower(config)?);
+        let env = Arc::new(program.environment());
+        if check_well_formedness {
+            program.verify_well_formedness(&env, config)?;
+        }
+
+        let mut failures = vec![];
+        for &engine in &[Engine::Recursive, Engine::Slg] {
+            let config = SolverConfig { engine, ..config };
+
+            // The program is made current so that trace events show item
+            // names; results are rendered with it explicitly.
+            ir::set_current_program(&program, || {
+                for (test, index) in self.goals.iter().zip(1..) {
+                    let expected = match engine {
+                        Engine::Slg => test.slg_expected.as_ref().unwrap_or(&test.expected),
+                        Engine::Recursive => &test.expected,
+                    };
+                    let mut solver = Solver::new(&env, config);
+                    let actual = solve(&program, &mut solver, &test.goal);
+                    if !matches(expected, &actual) {
+                        failures.push(TestFailure {
+                            index,
+                            line: test.line,
+                            goal: test.goal.clone(),
+                            engine,
+                            expected: expected.clone(),
+                            actual,
+                        });
+                    }
+                }
+            });
+        }
+
+        failures.sort_by_key(|failure| failure.index);
+        Ok(failures)
+    }
+}
+
+fn solve(program: &Arc<ir::Program>, solver: &mut Solver, text: &str) -> String {
+    let goal = match lower_goal(program, text) {
+        Ok(goal) => goal,
+        Err(e) => return format!("error: {}", e),
+    };
+    let goal = ir::InEnvironment::new(&ir::Environment::new(), *goal);
+    match solver.solve_closed_goal(goal) {
+        Ok(v) => format!("{}", ir::with_program(program, &v)),
+        Err(e) => format!("No possible solution: {}", ir::with_program(program, &e)),
+    }
+}
+
+fn lower_goal(program: &ir::Program, text: &str) -> Result<Box<ir::Goal>> {
+    chalk_parse::parse_goal(text)?.lower(program)
+}
+
+fn matches(expected: &str, actual: &str) -> bool {
+    let normalize = |text: &str| -> String { text.chars().filter(|&c| !c.is_whitespace() && c != '"').collect() };
+    let expected = normalize(expected);
+    !expected.is_empty() && normalize(actual).starts_with(&expected)
+}
+
+/// Reads the top-level keywords and blocks of a test file; the contents of
+/// blocks are left to the chalk parser.
+struct Scanner<'t> {
+    text: &'t str,
+    pos: usize,
+}
+
+impl<'t> Scanner<'t> {
+    fn rest(&self) -> &'t str {
+        &self.text[self.pos..]
+    }
+
+    fn line(&self) -> usize {
+        self.text[..self.pos].matches('\n').count() + 1
+    }
+
+    fn error<T>(&self, message: String) -> Result<T> {
+        Err(ErrorKind::MalformedTestFile(self.line(), message).into())
+    }
+
+    fn skip_whitespace_and_comments(&mut self) {
+        loop {
+            let rest = self.rest();
+            let trimmed = rest.trim_left();
+            self.pos += rest.len() - trimmed.len();
+            if !trimmed.starts_with("//") {
+                return;
+            }
+            self.pos += trimmed.find('\n').unwrap_or(trimmed.len());
+        }
+    }
+
+    fn at_end(&mut self) -> bool {
+        self.skip_whitespace_and_comments();
+        self.rest().is_empty()
+    }
+
+    /// Consumes `keyword` if it comes next.
+    fn keyword(&mut self, keyword: &str) -> bool {
+        self.skip_whitespace_and_comments();
+        let rest = self.rest();
+        if !rest.starts_with(keyword) {
+            return false;
+        }
+        let followed_by_ident = rest[keyword.len()..]
+            .chars()
+            .next()
+            .map_or(false, |c| c.is_alphanumeric() || c == '_' || c == '[');
+        if !followed_by_ident {
+            self.pos += keyword.len();
+        }
+        !followed_by_ident
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<()> {
+        if self.keyword(keyword) {
+            Ok(())
+        } else {
+            let found = self.rest().split_whitespace().next().unwrap_or("end of file");
+            self.error(format!("expected `{}`, found `{}`", keyword, found))
+        }
+    }
+
+    /// Consumes a block delimited by balanced braces, returning its contents.
+    fn block(&mut self) -> Result<String> {
+        self.skip_whitespace_and_comments();
+        if !self.rest().starts_with('{') {
+            return self.error("expected `{`".to_string());
+        }
+
+        let start = self.pos + 1;
+        let mut depth = 0;
+        let mut in_comment = false;
+        for (i, c) in self.rest().char_indices() {
+            match c {
+                '\n' => in_comment = false,
+                _ if in_comment => (),
+                '/' if self.rest()[i..].starts_with("//") => in_comment = true,
+                '{' => depth += 1,
+                '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        let end = self.pos + i;
+                        self.pos = end + 1;
+                        return Ok(self.text[start..end].to_string());
+                    }
+                }
+                _ => (),
+            }
+        }
+        self.error("unclosed block".to_string())
+    }
+}
+
+impl fmt::Display for TestFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.line {
+            Some(line) => writeln!(f, "goal at line {} ({:?} engine):", line, self.engine)?,
+            None => writeln!(f, "goal #{} ({:?} engine):", self.index, self.engine)?,
+        }
+        for line in self.goal.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            writeln!(f, "    {}", line)?;
+        }
+        for line in self.expected.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            writeln!(f, "-   {}", line)?;
+        }
+        for line in self.actual.lines() {
+            writeln!(f, "+   {}", line)?;
+        }
+        Ok(())
+    }
+}
@@ -26,7 +26,7 @@ impl InferenceTable {
     /// A substitution mapping from the free variables to their re-bound form is
     /// also returned.
     pub fn canonicalize<T: Fold>(&mut self, value: &T) -> Canonicalized<T> {
-        debug!("canonicalize({:#?})", value);
+        trace!("canonicalize({:#?})", value);
         let mut q = Canonicalizer {
             table: self,
             free_vars: Vec::new(),
@@ -111,16 +111,16 @@ impl<'q> Folder for Canonicalizer<'q> {
     }
 
     fn fold_free_lifetime_var(&mut self, depth: usize, binders: usize) -> Result<Lifetime> {
-        debug!("fold_free_lifetime_var(depth={:?}, binders={:?})", depth, binders);
+        trace!("fold_free_lifetime_var(depth={:?}, binders={:?})", depth, binders);
         let var = LifetimeInferenceVariable::from_depth(depth);
         match self.table.probe_lifetime_var(var) {
             Some(l) => {
-                debug!("fold_free_lifetime_var: {:?} mapped to {:?}", var, l);
+                trace!("fold_free_lifetime_var: {:?} mapped to {:?}", var, l);
                 let mut folder = (self, Shifter::new(binders));
                 l.fold_with(&mut folder, 0)
             }
             None => {
-                debug!("fold_free_lifetime_var: {:?} not unified", var);
+                trace!("fold_free_lifetime_var: {:?} not unified", var);
                 let free_var = ParameterKind::Lifetime(self.table.lifetime_unify.find(var));
                 let position = self.add(free_var) + binders;
                 Ok(LifetimeInferenceVariable::from_depth(position).to_lifetime())
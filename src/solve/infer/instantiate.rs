@@ -10,11 +10,11 @@ impl InferenceTable {
         where T: Fold + Debug,
               U: IntoIterator<Item = ParameterKind<UniverseIndex>>
     {
-        debug!("instantiate(arg={:?})", arg);
+        trace!("instantiate(arg={:?})", arg);
         let vars: Vec<_> = universes.into_iter()
             .map(|u| self.new_parameter_variable(u))
             .collect();
-        debug!("instantiate: vars={:?}", vars);
+        trace!("instantiate: vars={:?}", vars);
         let mut instantiator = Instantiator { vars };
         arg.fold_with(&mut instantiator, 0).expect("")
     }
@@ -18,6 +18,9 @@ pub struct InferenceTable {
     ty_vars: Vec<TyInferenceVariable>,
     lifetime_unify: ena::UnificationTable<LifetimeInferenceVariable>,
     lifetime_vars: Vec<LifetimeInferenceVariable>,
+
+    /// Number of variables created, including those which were rolled back.
+    variables_created: usize,
 }
 
 pub struct InferenceSnapshot {
@@ -36,18 +39,21 @@ impl InferenceTable {
             ty_vars: vec![],
             lifetime_unify: ena::UnificationTable::new(),
             lifetime_vars: vec![],
+            variables_created: 0,
         }
     }
 
     pub fn new_variable(&mut self, ui: UniverseIndex) -> TyInferenceVariable {
         let var = self.ty_unify.new_key(InferenceValue::Unbound(ui));
         self.ty_vars.push(var);
+        self.variables_created += 1;
         var
     }
 
     pub fn new_lifetime_variable(&mut self, ui: UniverseIndex) -> LifetimeInferenceVariable {
         let var = self.lifetime_unify.new_key(InferenceValue::Unbound(ui));
         self.lifetime_vars.push(var);
+        self.variables_created += 1;
         var
     }
 
@@ -59,6 +65,10 @@ impl InferenceTable {
         }
     }
 
+    pub fn variables_created(&self) -> usize {
+        self.variables_created
+    }
+
     pub fn ty_vars(&self) -> &[TyInferenceVariable] {
         &self.ty_vars
     }
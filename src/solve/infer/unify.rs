@@ -15,7 +15,7 @@ impl InferenceTable {
                     -> Result<UnificationResult>
         where T: ?Sized + Zip + Debug,
     {
-        debug_heading!("unify(a={:?}\
+        trace_heading!("unify(a={:?}\
                      ,\n      b={:?})", a, b);
         let mut unifier = Unifier::new(self, environment);
         match Zip::zip_with(&mut unifier, a, b) {
@@ -83,14 +83,14 @@ impl<'t> Unifier<'t> {
             return self.unify_ty_ty(a, &n_b);
         }
 
-        debug_heading!("unify_ty_ty(a={:?}\
+        trace_heading!("unify_ty_ty(a={:?}\
                      ,\n            b={:?})", a, b);
 
         match (a, b) {
             (&Ty::Var(depth1), &Ty::Var(depth2)) => {
                 let var1 = TyInferenceVariable::from_depth(depth1);
                 let var2 = TyInferenceVariable::from_depth(depth2);
-                debug!("unify_ty_ty: unify_var_var({:?}, {:?})", var1, var2);
+                trace!("unify_ty_ty: unify_var_var({:?}, {:?})", var1, var2);
                 Ok(self.table
                     .ty_unify
                     .unify_var_var(var1, var2)
@@ -100,7 +100,9 @@ impl<'t> Unifier<'t> {
             (&Ty::Var(depth), ty @ &Ty::Apply(_)) |
             (ty @ &Ty::Apply(_), &Ty::Var(depth)) |
             (&Ty::Var(depth), ty @ &Ty::ForAll(_)) |
-            (ty @ &Ty::ForAll(_), &Ty::Var(depth)) => {
+            (ty @ &Ty::ForAll(_), &Ty::Var(depth)) |
+            (&Ty::Var(depth), ty @ &Ty::Dyn(_)) |
+            (ty @ &Ty::Dyn(_), &Ty::Var(depth)) => {
                 self.unify_var_ty(TyInferenceVariable::from_depth(depth), ty)
             }
 
@@ -109,10 +111,26 @@ impl<'t> Unifier<'t> {
             }
 
             (&Ty::ForAll(ref quantified_ty), apply_ty @ &Ty::Apply(_)) |
-            (apply_ty @ &Ty::Apply(_), &Ty::ForAll(ref quantified_ty)) => {
+            (apply_ty @ &Ty::Apply(_), &Ty::ForAll(ref quantified_ty)) |
+            (&Ty::ForAll(ref quantified_ty), apply_ty @ &Ty::Dyn(_)) |
+            (apply_ty @ &Ty::Dyn(_), &Ty::ForAll(ref quantified_ty)) => {
                 self.unify_forall_apply(quantified_ty, apply_ty)
             }
 
+            (&Ty::Dyn(ref bounds1), &Ty::Dyn(ref bounds2)) => {
+                self.unify_dyn_tys(bounds1, bounds2)
+            }
+
+            (&Ty::Dyn(_), &Ty::Apply(ref apply)) |
+            (&Ty::Apply(ref apply), &Ty::Dyn(_)) => {
+                if apply.name.is_for_all() {
+                    // As for two different type names above.
+                    self.cannot_prove = true;
+                    return Ok(())
+                }
+                bail!(ErrorKind::CannotEquate(a.clone(), b.clone()))
+            }
+
             (&Ty::Apply(ref apply1), &Ty::Apply(ref apply2)) => {
                 if apply1.name != apply2.name {
                     if apply1.name.is_for_all() || apply2.name.is_for_all() {
@@ -127,7 +145,7 @@ impl<'t> Unifier<'t> {
                         self.cannot_prove = true;
                         return Ok(())
                     } else {
-                        bail!("cannot equate `{:?}` and `{:?}`", apply1.name, apply2.name);
+                        bail!(ErrorKind::CannotEquate(a.clone(), b.clone()));
                     }
                 }
 
@@ -141,9 +159,11 @@ impl<'t> Unifier<'t> {
             (ty @ &Ty::Apply(_), &Ty::Projection(ref proj)) |
             (ty @ &Ty::ForAll(_), &Ty::Projection(ref proj)) |
             (ty @ &Ty::Var(_), &Ty::Projection(ref proj)) |
+            (ty @ &Ty::Dyn(_), &Ty::Projection(ref proj)) |
             (&Ty::Projection(ref proj), ty @ &Ty::Apply(_)) |
             (&Ty::Projection(ref proj), ty @ &Ty::ForAll(_)) |
-            (&Ty::Projection(ref proj), ty @ &Ty::Var(_)) => {
+            (&Ty::Projection(ref proj), ty @ &Ty::Var(_)) |
+            (&Ty::Projection(ref proj), ty @ &Ty::Dyn(_)) => {
                 self.unify_projection_ty(proj, ty)
             }
         }
@@ -157,7 +177,7 @@ impl<'t> Unifier<'t> {
         // for<'a...> exists<'b...> T == U &&
         // for<'b...> exists<'a...> T == U
 
-        debug!("unify_forall_tys({:?}, {:?})", ty1, ty2);
+        trace!("unify_forall_tys({:?}, {:?})", ty1, ty2);
 
         let mut environment = self.environment.clone();
         let lifetimes1: Vec<_> = (0..ty1.num_binders)
@@ -173,18 +193,38 @@ impl<'t> Unifier<'t> {
 
         let ty1 = ty1.subst(&lifetimes1);
         let ty2 = ty2.subst(&lifetimes2);
-        debug!("unify_forall_tys: ty1 = {:?}", ty1);
-        debug!("unify_forall_tys: ty2 = {:?}", ty2);
+        trace!("unify_forall_tys: ty1 = {:?}", ty1);
+        trace!("unify_forall_tys: ty2 = {:?}", ty2);
 
         let eq_goal = EqGoal { a: ParameterKind::Ty(ty1), b: ParameterKind::Ty(ty2) };
         let goal = InEnvironment::new(&environment, eq_goal).cast();
-        debug!("unify_forall_tys: goal = {:?}", goal);
+        trace!("unify_forall_tys: goal = {:?}", goal);
 
         self.goals.push(goal);
 
         Ok(())
     }
 
+    fn unify_dyn_tys(&mut self,
+                     bounds1: &Binders<Vec<DomainGoal>>,
+                     bounds2: &Binders<Vec<DomainGoal>>)
+                     -> Result<()> {
+        // dyn A == dyn B if their bounds agree for an arbitrary self type,
+        // which we skolemize. Since the bounds are in a canonical order, we
+        // can compare them pairwise.
+        trace!("unify_dyn_tys({:?}, {:?})", bounds1, bounds2);
+
+        let environment = self.environment.new_universe();
+        let self_ty: Parameter = Ty::Apply(ApplicationTy {
+            name: TypeName::ForAll(environment.universe),
+            parameters: vec![],
+        }).cast();
+
+        let bounds1 = bounds1.subst(&[self_ty.clone()]);
+        let bounds2 = bounds2.subst(&[self_ty]);
+        Zip::zip_with(self, &bounds1, &bounds2)
+    }
+
     fn unify_projection_tys(&mut self, proj1: &ProjectionTy, proj2: &ProjectionTy) -> Result<()> {
         let var = self.table.new_variable(self.environment.universe).to_ty();
         self.unify_projection_ty(proj1, &var)?;
@@ -220,7 +260,7 @@ impl<'t> Unifier<'t> {
     }
 
     fn unify_var_ty(&mut self, var: TyInferenceVariable, ty: &Ty) -> Result<()> {
-        debug!("unify_var_ty(var={:?}, ty={:?})", var, ty);
+        trace!("unify_var_ty(var={:?}, ty={:?})", var, ty);
 
         // Determine the universe index associated with this
         // variable. This is basically a count of the number of
@@ -235,7 +275,7 @@ impl<'t> Unifier<'t> {
         let ty1 = OccursCheck::new(self, var, universe_index).check_ty(ty)?;
 
         self.table.ty_unify.unify_var_value(var, InferenceValue::Bound(ty1.clone())).unwrap();
-        debug!("unify_var_ty: var {:?} set to {:?}", var, ty1);
+        trace!("unify_var_ty: var {:?} set to {:?}", var, ty1);
 
         Ok(())
     }
@@ -247,13 +287,13 @@ impl<'t> Unifier<'t> {
             return self.unify_lifetime_lifetime(a, &n_b);
         }
 
-        debug!("unify_lifetime_lifetime({:?}, {:?})", a, b);
+        trace!("unify_lifetime_lifetime({:?}, {:?})", a, b);
 
         match (a, b) {
             (&Lifetime::Var(depth_a), &Lifetime::Var(depth_b)) => {
                 let var_a = LifetimeInferenceVariable::from_depth(depth_a);
                 let var_b = LifetimeInferenceVariable::from_depth(depth_b);
-                debug!("unify_lifetime_lifetime: var_a={:?} var_b={:?}", var_a, var_b);
+                trace!("unify_lifetime_lifetime: var_a={:?} var_b={:?}", var_a, var_b);
                 self.table.lifetime_unify.unify_var_var(var_a, var_b).unwrap();
                 Ok(())
             }
@@ -310,7 +350,11 @@ impl TypeName {
     fn universe_index(&self) -> UniverseIndex {
         match *self {
             TypeName::ItemId(_) |
-            TypeName::AssociatedType(_) => UniverseIndex::root(),
+            TypeName::AssociatedType(_) |
+            TypeName::Ref(_) |
+            TypeName::Tuple(_) |
+            TypeName::Slice |
+            TypeName::FnPtr(_) => UniverseIndex::root(),
             TypeName::ForAll(universe) => {
                 assert!(universe.counter > 0);
                 universe
@@ -420,8 +464,14 @@ impl<'u, 't> OccursCheck<'u, 't> {
     }
 
     fn check_ty(&mut self, parameter: &Ty) -> Result<Ty> {
-        if let Some(n_parameter) = self.unifier.table.normalize_shallow(parameter) {
-            return self.check_ty(&n_parameter);
+        // Only variables bound outside of `parameter` are inference
+        // variables; see `Ty::Var(_)` below.
+        let free_var = match *parameter {
+            Ty::Var(depth) if depth >= self.binders => Some(Ty::Var(depth - self.binders)),
+            _ => None,
+        };
+        if let Some(n_parameter) = free_var.and_then(|v| self.unifier.table.normalize_shallow(&v)) {
+            return self.check_ty(&n_parameter.up_shift(self.binders));
         }
 
         match *parameter {
@@ -433,6 +483,20 @@ impl<'u, 't> OccursCheck<'u, 't> {
                 Ok(Ty::ForAll(Box::new(self.check_quantified(quantified_ty)?)))
             }
 
+            Ty::Dyn(ref bounds) => {
+                self.binders += bounds.len();
+                let value = bounds.value.iter()
+                                        .map(|goal| self.check_domain_goal(goal))
+                                        .collect::<Result<Vec<_>>>()?;
+                self.binders -= bounds.len();
+                Ok(Ty::Dyn(Binders { binders: bounds.binders.clone(), value }))
+            }
+
+            Ty::Var(depth) if depth < self.binders => {
+                // the self type of an enclosing `dyn` type
+                Ok(Ty::Var(depth))
+            }
+
             Ty::Var(depth) => {
                 let v = TyInferenceVariable::from_depth(depth - self.binders);
                 let ui = self.unifier.table.ty_unify.probe_value(v).unbound().unwrap();
@@ -458,15 +522,58 @@ impl<'u, 't> OccursCheck<'u, 't> {
                 Ok(Ty::Var(depth))
             }
 
-            Ty::Projection(ProjectionTy { associated_ty_id, ref parameters }) => {
+            Ty::Projection(ref projection) => {
                 // FIXME(#6) -- this rejects constraints like
                 // `exists(A -> A = Item0<<A as Item1>::foo>)`, which
                 // is probably too conservative.
-                let parameters =
-                    parameters.iter()
-                              .map(|p| self.check_parameter(p))
-                              .collect::<Result<Vec<_>>>()?;
-                Ok(Ty::Projection(ProjectionTy { associated_ty_id, parameters }))
+                Ok(Ty::Projection(self.check_projection(projection)?))
+            }
+        }
+    }
+
+    fn check_projection(&mut self, projection: &ProjectionTy) -> Result<ProjectionTy> {
+        let ProjectionTy { associated_ty_id, ref parameters } = *projection;
+        let parameters = parameters.iter()
+                                   .map(|p| self.check_parameter(p))
+                                   .collect::<Result<Vec<_>>>()?;
+        Ok(ProjectionTy { associated_ty_id, parameters })
+    }
+
+    fn check_trait_ref(&mut self, trait_ref: &TraitRef) -> Result<TraitRef> {
+        let TraitRef { trait_id, ref parameters } = *trait_ref;
+        let parameters = parameters.iter()
+                                   .map(|p| self.check_parameter(p))
+                                   .collect::<Result<Vec<_>>>()?;
+        Ok(TraitRef { trait_id, parameters })
+    }
+
+    /// Checks the bounds of a `dyn` type.
+    fn check_domain_goal(&mut self, goal: &DomainGoal) -> Result<DomainGoal> {
+        match *goal {
+            DomainGoal::Implemented(ref trait_ref) => {
+                Ok(DomainGoal::Implemented(self.check_trait_ref(trait_ref)?))
+            }
+            DomainGoal::Normalize(Normalize { ref projection, ref ty }) => {
+                let projection = self.check_projection(projection)?;
+                let ty = self.check_ty(ty)?;
+                Ok(DomainGoal::Normalize(Normalize { projection, ty }))
+            }
+            DomainGoal::WellFormed(WellFormed::Ty(ref ty)) => {
+                Ok(DomainGoal::WellFormed(WellFormed::Ty(self.check_ty(ty)?)))
+            }
+            DomainGoal::WellFormed(WellFormed::TraitRef(ref trait_ref)) => {
+                Ok(DomainGoal::WellFormed(WellFormed::TraitRef(self.check_trait_ref(trait_ref)?)))
+            }
+            DomainGoal::FromEnv(FromEnv::Ty(ref ty)) => {
+                Ok(DomainGoal::FromEnv(FromEnv::Ty(self.check_ty(ty)?)))
+            }
+            DomainGoal::FromEnv(FromEnv::TraitRef(ref trait_ref)) => {
+                Ok(DomainGoal::FromEnv(FromEnv::TraitRef(self.check_trait_ref(trait_ref)?)))
+            }
+            DomainGoal::Outlives(Outlives { ref a, ref b }) => {
+                let a = self.check_parameter(a)?;
+                let b = self.check_lifetime(b)?;
+                Ok(DomainGoal::Outlives(Outlives { a, b }))
             }
         }
     }
@@ -474,7 +581,7 @@ impl<'u, 't> OccursCheck<'u, 't> {
     fn universe_check(&mut self,
                       application_universe_index: UniverseIndex)
                       -> Result<()> {
-        debug!("universe_check({:?}, {:?})",
+        trace!("universe_check({:?}, {:?})",
                self.universe_index,
                application_universe_index);
         if self.universe_index < application_universe_index {
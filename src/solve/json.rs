@@ -0,0 +1,191 @@
+use std::fmt;
+use std::sync::Arc;
+
+use errors::*;
+use ir::*;
+use solve::{Guidance, Solution};
+
+/// A JSON value, as built by `ToJson` and printed by its `Display` impl.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Json {
+    Number(usize),
+    String(String),
+    Array(Vec<Json>),
+
+    /// An object, whose members are printed in the given order.
+    Object(Vec<(String, Json)>),
+}
+
+/// Conversion of solver results to JSON. Types and lifetimes are rendered as
+/// strings, with the item names of the program (e.g. `"Vec<u32>"`), as in the
+/// output of the solver; the structure of the results is:
+///
+/// ```notrust
+/// solution    = { "kind": "unique", "binders": [binder], "substitution": subst,
+///                 "constraints": [constraint] }
+///             | { "kind": "ambiguous", "guidance": "definite" | "suggested",
+///                 "binders": [binder], "substitution": subst }
+///             | { "kind": "ambiguous", "guidance": "unknown" }
+///             | { "kind": "cannot_prove" }
+///             | { "kind": "no_solution", "error": string }
+/// binder      = { "kind": "type" | "lifetime", "universe": number }
+/// subst       = { "?0": "Vec<u32>", "'?1": "'!1", ... }
+/// constraint  = { "kind": "lifetime_eq", "a": lifetime, "b": lifetime,
+///                 "environment": [string] }
+///             | { "kind": "outlives", "parameter": string, "lifetime": string,
+///                 "environment": [string] }
+/// ```
+///
+/// The `no_solution` form is that of a `Result<Solution>` which is an error.
+pub trait ToJson {
+    fn to_json(&self, program: &Arc<Program>) -> Json;
+}
+
+impl ToJson for Result<Solution> {
+    fn to_json(&self, program: &Arc<Program>) -> Json {
+        match *self {
+            Ok(ref solution) => solution.to_json(program),
+            Err(ref e) => object(vec![
+                ("kind", string("no_solution")),
+                ("error", Json::String(e.to_string())),
+            ]),
+        }
+    }
+}
+
+impl ToJson for Solution {
+    fn to_json(&self, program: &Arc<Program>) -> Json {
+        match *self {
+            Solution::Unique(ref constrained) => {
+                let constraints = constrained.value.constraints.iter().map(|c| c.to_json(program)).collect();
+                object(vec![
+                    ("kind", string("unique")),
+                    ("binders", binders_to_json(&constrained.binders)),
+                    ("substitution", constrained.value.subst.to_json(program)),
+                    ("constraints", Json::Array(constraints)),
+                ])
+            }
+            Solution::Ambig(ref guidance) => {
+                let mut members = vec![("kind".to_string(), string("ambiguous"))];
+                if let Json::Object(guidance) = guidance.to_json(program) {
+                    members.extend(guidance);
+                }
+                Json::Object(members)
+            }
+            Solution::CannotProve => object(vec![("kind", string("cannot_prove"))]),
+        }
+    }
+}
+
+impl ToJson for Guidance {
+    fn to_json(&self, program: &Arc<Program>) -> Json {
+        let (guidance, subst) = match *self {
+            Guidance::Definite(ref subst) => ("definite", subst),
+            Guidance::Suggested(ref subst) => ("suggested", subst),
+            Guidance::Unknown => return object(vec![("guidance", string("unknown"))]),
+        };
+        object(vec![
+            ("guidance", string(guidance)),
+            ("binders", binders_to_json(&subst.binders)),
+            ("substitution", subst.value.to_json(program)),
+        ])
+    }
+}
+
+impl ToJson for Substitution {
+    fn to_json(&self, program: &Arc<Program>) -> Json {
+        let tys = self.tys.iter().map(|(var, ty)| (format!("{:?}", var), render(program, ty)));
+        let lifetimes = self.lifetimes.iter().map(|(var, lifetime)| (format!("{:?}", var), render(program, lifetime)));
+        Json::Object(tys.chain(lifetimes).collect())
+    }
+}
+
+impl ToJson for InEnvironment<Constraint> {
+    fn to_json(&self, program: &Arc<Program>) -> Json {
+        let mut members = match self.goal {
+            Constraint::LifetimeEq(ref a, ref b) => vec![
+                ("kind", string("lifetime_eq")),
+                ("a", render(program, a)),
+                ("b", render(program, b)),
+            ],
+            Constraint::Outlives(ref parameter, ref lifetime) => vec![
+                ("kind", string("outlives")),
+                ("parameter", render(program, parameter)),
+                ("lifetime", render(program, lifetime)),
+            ],
+        };
+        let environment = self.environment.clauses.iter().map(|clause| render(program, clause)).collect();
+        members.push(("environment", Json::Array(environment)));
+        object(members)
+    }
+}
+
+fn binders_to_json(binders: &[ParameterKind<UniverseIndex>]) -> Json {
+    Json::Array(binders.iter().map(|binder| {
+        let (kind, universe) = match *binder {
+            ParameterKind::Ty(universe) => ("type", universe),
+            ParameterKind::Lifetime(universe) => ("lifetime", universe),
+        };
+        object(vec![
+            ("kind", string(kind)),
+            ("universe", Json::Number(universe.counter)),
+        ])
+    }).collect())
+}
+
+fn object(members: Vec<(&str, Json)>) -> Json {
+    Json::Object(members.into_iter().map(|(key, value)| (key.to_string(), value)).collect())
+}
+
+fn string(value: &str) -> Json {
+    Json::String(value.to_string())
+}
+
+fn render<T: fmt::Debug>(program: &Arc<Program>, value: &T) -> Json {
+    Json::String(format!("{:?}", with_program(program, value)))
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Json::Number(n) => write!(f, "{}", n),
+            Json::String(ref s) => write_string(f, s),
+            Json::Array(ref elements) => {
+                write!(f, "[")?;
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", element)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(ref members) => {
+                write!(f, "{{")?;
+                for (i, &(ref key, ref value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
@@ -2,7 +2,13 @@ use std::fmt;
 use ir::*;
 
 pub mod fulfill;
+pub mod answers;
+pub mod failure;
+pub mod harness;
 pub mod infer;
+pub mod json;
+pub mod proof;
+pub mod slg;
 pub mod solver;
 
 #[cfg(test)] mod test;
@@ -0,0 +1,214 @@
+use std::fmt;
+
+use ir::*;
+use super::Solution;
+use super::failure::{Candidate, Failure};
+
+/// A record of how the solver established (or refuted) a goal. Proof trees
+/// are only built when asked for, see `Solver::solve_closed_goal_with_proof`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProofTree {
+    /// `goal` holds, with the given solution, because of `step`.
+    Proved {
+        goal: FullyReducedGoal,
+        solution: Solution,
+        step: ProofStep,
+    },
+
+    /// `goal` was refuted: it could not be proven (negation as failure).
+    Refuted {
+        goal: InEnvironment<Goal>,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProofStep {
+    /// An equality goal, solved by unification. Unifying projections may
+    /// require proving normalization goals, which are the premises.
+    Unification {
+        premises: Vec<ProofTree>,
+    },
+
+    /// A clause was applied; its conditions were proven by `premises`.
+    Clause {
+        source: ClauseSource,
+        clause: Binders<ProgramClauseImplication>,
+        premises: Vec<ProofTree>,
+    },
+
+    /// The goal was already being proven further up the stack, and the cycle
+    /// is accepted as an infinite proof since it is coinductive.
+    Coinduction,
+
+    /// The goal was already being proven further up the stack, and the
+    /// answer found so far (tabling) was used.
+    Cycle,
+
+    /// The answer was found in the cache of the solver, which had no proof
+    /// for it.
+    Cached,
+
+    /// Several clauses applied with different results, which were combined
+    /// into the (ambiguous) solution.
+    Combined {
+        steps: Vec<ProofStep>,
+    },
+}
+
+/// Where a clause applied in a proof comes from.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ClauseSource {
+    /// A where clause of the environment.
+    Environment,
+
+    /// A clause of the lowered program.
+    Program,
+
+    /// A fallback clause of the lowered program.
+    Fallback,
+}
+
+/// The part of a proof tree (or failure explanation) currently being built
+/// by the solver.
+#[derive(Default)]
+pub(super) struct ProofFrame {
+    /// Proofs of the goals solved so far in this frame.
+    pub(super) premises: Vec<ProofTree>,
+
+    /// The clauses by which the solution of the goal of this frame was
+    /// obtained; several if it combines their results.
+    pub(super) steps: Vec<ProofStep>,
+
+    /// The last goal of this frame that could not be solved. Since failures
+    /// are propagated immediately, this is what made the frame fail.
+    pub(super) failure: Option<Failure>,
+
+    /// Clauses which could not be applied to the goal of this frame.
+    pub(super) candidates: Vec<Candidate>,
+}
+
+impl ProofFrame {
+    /// Record the proof of a premise. A goal may be solved several times
+    /// (e.g. when it was ambiguous at first); only the last proof is kept.
+    pub(super) fn add_premise(&mut self, proof: ProofTree) {
+        self.premises.retain(|p| !p.same_goal(&proof));
+        self.premises.push(proof);
+    }
+}
+
+impl ProofTree {
+    fn same_goal(&self, other: &ProofTree) -> bool {
+        match (self, other) {
+            (&ProofTree::Proved { goal: ref g1, .. }, &ProofTree::Proved { goal: ref g2, .. }) => g1 == g2,
+            (&ProofTree::Refuted { goal: ref g1 }, &ProofTree::Refuted { goal: ref g2 }) => g1 == g2,
+            _ => false,
+        }
+    }
+
+    fn fmt_indented(&self, fmt: &mut fmt::Formatter, indent: usize) -> fmt::Result {
+        match *self {
+            ProofTree::Proved { ref goal, ref solution, ref step } => {
+                write!(fmt, "{:indent$}", "", indent = indent)?;
+                match *goal {
+                    FullyReducedGoal::EqGoal(ref goal) => fmt_goal(fmt, &goal.value)?,
+                    FullyReducedGoal::DomainGoal(ref goal) => fmt_goal(fmt, &goal.value)?,
+                }
+                match *solution {
+                    Solution::Unique(ref constrained) if !constrained.value.subst.tys.is_empty() ||
+                                                         !constrained.value.subst.lifetimes.is_empty() =>
+                        writeln!(fmt, " with [{}]", constrained.value.subst)?,
+                    _ => writeln!(fmt)?,
+                }
+
+                step.fmt_indented(fmt, indent + 2)
+            }
+            ProofTree::Refuted { ref goal } => {
+                write!(fmt, "{:indent$}not {{ ", "", indent = indent)?;
+                fmt_goal(fmt, goal)?;
+                writeln!(fmt, " }}")?;
+                writeln!(fmt, "{:indent$}  by failing to prove it", "", indent = indent)
+            }
+        }
+    }
+}
+
+impl ProofStep {
+    fn fmt_indented(&self, fmt: &mut fmt::Formatter, indent: usize) -> fmt::Result {
+        write!(fmt, "{:indent$}", "", indent = indent)?;
+        match *self {
+            ProofStep::Unification { ref premises } => {
+                writeln!(fmt, "by unification")?;
+                fmt_premises(fmt, premises, indent + 2)
+            }
+            ProofStep::Clause { source, ref clause, ref premises } => {
+                write!(fmt, "by {} `", source)?;
+                fmt_clause(fmt, clause)?;
+                writeln!(fmt, "`")?;
+                fmt_premises(fmt, premises, indent + 2)
+            }
+            ProofStep::Coinduction => writeln!(fmt, "by coinduction"),
+            ProofStep::Cycle => writeln!(fmt, "by the answer found so far for this cycle"),
+            ProofStep::Cached => writeln!(fmt, "by a cached answer"),
+            ProofStep::Combined { ref steps } => {
+                writeln!(fmt, "by combining the results of")?;
+                for step in steps {
+                    step.fmt_indented(fmt, indent + 2)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+pub(super) fn fmt_goal<G: fmt::Debug>(fmt: &mut fmt::Formatter, goal: &InEnvironment<G>) -> fmt::Result {
+    if goal.environment.clauses.is_empty() {
+        write!(fmt, "{:?}", goal.goal)
+    } else {
+        write!(fmt, "if ({:?}) {{ {:?} }}", goal.environment.clauses, goal.goal)
+    }
+}
+
+pub(super) fn fmt_clause(fmt: &mut fmt::Formatter, clause: &Binders<ProgramClauseImplication>) -> fmt::Result {
+    let ProgramClauseImplication { ref consequence, ref conditions } = clause.value;
+    if !clause.binders.is_empty() {
+        write!(fmt, "forall<")?;
+        for (index, binder) in clause.binders.iter().enumerate() {
+            if index > 0 {
+                write!(fmt, ", ")?;
+            }
+            match *binder {
+                ParameterKind::Ty(()) => write!(fmt, "type")?,
+                ParameterKind::Lifetime(()) => write!(fmt, "lifetime")?,
+            }
+        }
+        write!(fmt, "> ")?;
+    }
+    write!(fmt, "{:?}", consequence)?;
+    for (index, condition) in conditions.iter().enumerate() {
+        write!(fmt, "{}{:?}", if index == 0 { " :- " } else { ", " }, condition)?;
+    }
+    Ok(())
+}
+
+fn fmt_premises(fmt: &mut fmt::Formatter, premises: &[ProofTree], indent: usize) -> fmt::Result {
+    for premise in premises {
+        premise.fmt_indented(fmt, indent)?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for ClauseSource {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ClauseSource::Environment => write!(fmt, "where clause"),
+            ClauseSource::Program => write!(fmt, "program clause"),
+            ClauseSource::Fallback => write!(fmt, "fallback clause"),
+        }
+    }
+}
+
+impl fmt::Display for ProofTree {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_indented(fmt, 0)
+    }
+}
@@ -0,0 +1,728 @@
+//! An alternative engine based on SLG resolution.
+//!
+//! Each (canonical) goal that is encountered gets a *table*, which records
+//! the answers found for the goal so far. A table is filled by *strands*:
+//! each strand is an attempt at proving the goal, consisting of an
+//! inference context and the literals which remain to be proven (an
+//! "ex-clause"). When a strand needs the answers of another goal, it
+//! consumes those in the table of that goal. If that table is still being
+//! filled (because of a cycle), the strand is suspended and resumed whenever
+//! a new answer is added.
+//!
+//! Tables are completed one strongly connected component at a time, using
+//! a stack in the style of Tarjan's algorithm: a table that depends on a
+//! table below it on the stack is completed together with it.
+//!
+//! Negative literals are only selected once they no longer have free
+//! variables. If the negated goal is part of the component being solved, the
+//! literal is *delayed*: answers which depend on it are conditional, and are
+//! simplified once the component is complete.
+//!
+//! To guarantee termination, goals and answers involving types nested more
+//! deeply than the overflow depth are not explored further; the affected
+//! answers are ambiguous instead.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use errors::*;
+use ir::*;
+use solve::Solution;
+use solve::Guidance;
+use solve::infer::{InferenceTable, ParameterInferenceVariable, TyInferenceVariable,
+                   LifetimeInferenceVariable, UnificationResult};
+use solve::solver::SolverConfig;
+
+type CanonicalGoal = Canonical<InEnvironment<Goal>>;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct TableIndex(usize);
+
+/// The tables for all the goals solved so far. Completed tables are kept
+/// across queries.
+pub struct Forest {
+    program: Arc<ProgramDatabase>,
+    config: SolverConfig,
+    compatible_modality: bool,
+    tables: Vec<Table>,
+    table_indices: HashMap<CanonicalGoal, TableIndex>,
+
+    /// The tables of the closed goals given to `solve_closed_goal`. These
+    /// are kept apart from the other tables, since their answers are for
+    /// the existential variables of the goal.
+    queries: HashMap<InEnvironment<Goal>, TableIndex>,
+
+    /// The tables which are not complete yet.
+    stack: Vec<TableIndex>,
+}
+
+struct Table {
+    goal: CanonicalGoal,
+
+    /// The position of the table on the stack, if it is not complete.
+    depth: Option<usize>,
+
+    answers: Vec<Answer>,
+
+    /// Strands which remain to be run.
+    strands: Vec<Strand>,
+
+    /// Strands waiting for the answers of this table.
+    consumers: Vec<Consumer>,
+
+    /// Whether some answer was too large, and replaced by an ambiguous one.
+    truncated: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Answer {
+    subst: Canonical<ConstrainedSubst>,
+
+    /// The negated goals this answer depends upon, which were not complete
+    /// when the answer was found.
+    delayed: Vec<TableIndex>,
+
+    ambiguous: bool,
+    cannot_prove: bool,
+    fallback: bool,
+}
+
+#[derive(Clone)]
+struct Strand {
+    infer: InferenceTable,
+    ex_clause: ExClause,
+}
+
+#[derive(Clone)]
+struct ExClause {
+    /// The values of the variables of the table goal.
+    subst: Substitution,
+    constraints: Vec<InEnvironment<Constraint>>,
+    subgoals: Vec<Literal>,
+    delayed: Vec<TableIndex>,
+    ambiguous: bool,
+    cannot_prove: bool,
+
+    /// Whether the strand started from a fallback clause.
+    fallback: bool,
+}
+
+#[derive(Clone, Debug)]
+enum Literal {
+    Positive(InEnvironment<LeafGoal>),
+    Negative(InEnvironment<Goal>),
+}
+
+struct Consumer {
+    /// The table the strand belongs to.
+    table: TableIndex,
+    strand: Strand,
+
+    /// The variables of the strand corresponding to those of the consumed
+    /// (canonical) goal.
+    free_vars: Vec<ParameterInferenceVariable>,
+}
+
+/// What became of a literal selected in a strand.
+enum Selected {
+    /// The strand goes on with its other literals.
+    Continue,
+
+    /// The strand was consumed (its continuations, if any, were queued).
+    Done,
+}
+
+impl Forest {
+    pub fn new(program: &Arc<ProgramDatabase>, config: SolverConfig) -> Self {
+        Forest {
+            program: program.clone(),
+            config,
+            compatible_modality: false,
+            tables: vec![],
+            table_indices: HashMap::new(),
+            queries: HashMap::new(),
+            stack: vec![],
+        }
+    }
+
+    /// See `Solver::in_compatible_modality`.
+    pub fn in_compatible_modality(mut self) -> Self {
+        self.compatible_modality = true;
+        self
+    }
+
+    /// Solve a closed goal; the substitution of a unique solution is for the
+    /// existential variables of the goal, as with the recursive solver.
+    pub fn solve_closed_goal(&mut self, goal: InEnvironment<Goal>) -> Result<Solution> {
+        let table = match self.queries.get(&goal).cloned() {
+            Some(table) => table,
+            None => {
+                let mut infer = InferenceTable::new();
+                let mut subgoals = vec![];
+                push_goal(&mut infer, &goal.environment, goal.goal.clone(), &mut subgoals);
+                let subst = identity_subst(&infer);
+                let strand = Strand { infer, ex_clause: ExClause::new(subst, subgoals) };
+                let table = self.push_table(Canonical { value: goal.clone(), binders: vec![] }, vec![strand]);
+                self.queries.insert(goal, table);
+                self.solve_table(table);
+                table
+            }
+        };
+
+        self.solution(table)
+    }
+
+    fn solution(&self, table: TableIndex) -> Result<Solution> {
+        let table = &self.tables[table.0];
+        let (cannot_prove, answers): (Vec<_>, Vec<_>) = table.answers.iter().partition(|a| a.cannot_prove);
+
+        match answers.len() {
+            0 if cannot_prove.is_empty() => Err("no solution found".into()),
+            0 => Ok(Solution::CannotProve),
+            1 if answers[0].is_unconditional() => Ok(Solution::Unique(answers[0].subst.clone())),
+            1 if !table.truncated && !is_trivial(&answers[0].subst.value.subst) => {
+                let Canonical { value, binders } = answers[0].subst.clone();
+                Ok(Solution::Ambig(Guidance::Definite(Canonical { value: value.subst, binders })))
+            }
+            _ => Ok(Solution::Ambig(Guidance::Unknown)),
+        }
+    }
+
+    fn push_table(&mut self, goal: CanonicalGoal, strands: Vec<Strand>) -> TableIndex {
+        let index = TableIndex(self.tables.len());
+        self.tables.push(Table {
+            goal,
+            depth: None,
+            answers: vec![],
+            strands,
+            consumers: vec![],
+            truncated: false,
+        });
+        index
+    }
+
+    /// The table for `goal`, which is solved first if it is new, along with
+    /// the lowest stack depth that solving it depends upon (if any).
+    fn table_for(&mut self, goal: CanonicalGoal) -> (TableIndex, Option<usize>) {
+        if let Some(&table) = self.table_indices.get(&goal) {
+            return (table, self.tables[table.0].depth);
+        }
+
+        let strands = self.initial_strands(&goal);
+        let table = self.push_table(goal.clone(), strands);
+        self.table_indices.insert(goal, table);
+        (table, self.solve_table(table))
+    }
+
+    fn initial_strands(&self, canonical_goal: &CanonicalGoal) -> Vec<Strand> {
+        let Canonical { ref value, ref binders } = *canonical_goal;
+        let mut infer = InferenceTable::new();
+        let InEnvironment { environment, goal } = infer.instantiate(binders.iter().cloned(), value);
+        let subst = binders_subst(&infer, binders);
+
+        match goal {
+            Goal::Leaf(LeafGoal::DomainGoal(goal)) => {
+                let env_clauses = environment.clauses.iter()
+                    .cloned()
+                    .map(DomainGoal::into_program_clause);
+                let mut prog_clauses = self.program.program_clauses_for(&goal);
+                prog_clauses.extend(goal.builtin_clauses(&*self.program));
+
+                env_clauses.chain(prog_clauses).filter_map(|clause| {
+                    let mut infer = infer.clone();
+                    let ProgramClauseImplication { consequence, conditions } =
+                        infer.instantiate_in(environment.universe,
+                                             clause.implication.binders.iter().cloned(),
+                                             &clause.implication.value);
+                    let unified = match infer.unify(&environment, &goal, &consequence) {
+                        Ok(unified) => unified,
+                        Err(_) => return None,
+                    };
+
+                    let mut subgoals = vec![];
+                    for condition in conditions {
+                        push_goal(&mut infer, &environment, condition, &mut subgoals);
+                    }
+                    let mut ex_clause = ExClause::new(subst.clone(), subgoals);
+                    ex_clause.fallback = clause.fallback_clause;
+                    ex_clause.add_unification_result(unified);
+                    Some(Strand { infer, ex_clause })
+                }).collect()
+            }
+            goal => {
+                let mut subgoals = vec![];
+                push_goal(&mut infer, &environment, goal, &mut subgoals);
+                vec![Strand { infer, ex_clause: ExClause::new(subst, subgoals) }]
+            }
+        }
+    }
+
+    /// Push `table` on the stack and run its strands, as well as those of
+    /// the tables above it on the stack. If none of them depends on a table
+    /// below, they are all complete; otherwise, the lowest stack depth they
+    /// depend upon is returned.
+    fn solve_table(&mut self, table: TableIndex) -> Option<usize> {
+        let depth = self.stack.len();
+        self.stack.push(table);
+        self.tables[table.0].depth = Some(depth);
+
+        let mut min_depth = depth;
+        loop {
+            let next = self.stack[depth..].iter().cloned().find(|t| !self.tables[t.0].strands.is_empty());
+            let table = match next {
+                Some(table) => table,
+                None => break,
+            };
+            let strand = self.tables[table.0].strands.pop().unwrap();
+            if let Some(d) = self.run_strand(table, strand) {
+                min_depth = ::std::cmp::min(min_depth, d);
+            }
+        }
+
+        if min_depth < depth {
+            Some(min_depth)
+        } else {
+            self.complete(depth);
+            None
+        }
+    }
+
+    /// Run a strand of `table` until it produces an answer, fails, or has
+    /// to wait for the answers of another table. Returns the lowest depth of
+    /// the tables on the stack that the strand depended upon.
+    fn run_strand(&mut self, table: TableIndex, mut strand: Strand) -> Option<usize> {
+        let mut min_depth = None;
+        loop {
+            let literal = match strand.select(&*self.program) {
+                Some(literal) => literal,
+                None => {
+                    self.add_answer(table, strand);
+                    return min_depth;
+                }
+            };
+
+            let (selected, depth) = match literal {
+                Literal::Positive(goal) => self.select_positive(table, &mut strand, goal),
+                Literal::Negative(goal) => self.select_negative(&mut strand, goal),
+            };
+
+            min_depth = match (min_depth, depth) {
+                (Some(a), Some(b)) => Some(::std::cmp::min(a, b)),
+                (a, b) => a.or(b),
+            };
+
+            if let Selected::Done = selected {
+                return min_depth;
+            }
+        }
+    }
+
+    fn select_positive(&mut self, table: TableIndex, strand: &mut Strand, goal: InEnvironment<LeafGoal>)
+                       -> (Selected, Option<usize>)
+    {
+        let InEnvironment { environment, goal } = goal;
+        let goal = match goal {
+            LeafGoal::EqGoal(EqGoal { a, b }) => {
+                return match strand.infer.unify(&environment, &a, &b) {
+                    Ok(result) => {
+                        strand.ex_clause.add_unification_result(result);
+                        (Selected::Continue, None)
+                    }
+                    Err(_) => (Selected::Done, None),
+                };
+            }
+            LeafGoal::DomainGoal(DomainGoal::Outlives(Outlives { a, b })) => {
+                // As in `Fulfill::push_goal`, left to region checking.
+                let constraint = InEnvironment::new(&environment, Constraint::Outlives(a, b));
+                strand.ex_clause.constraints.push(constraint);
+                return (Selected::Continue, None);
+            }
+            LeafGoal::DomainGoal(goal) => goal,
+        };
+
+        let canonicalized = strand.infer.canonicalize(&InEnvironment::new(&environment, goal));
+        if domain_goal_depth(&canonicalized.quantified.value.goal) > self.config.overflow_depth {
+            debug!("slg: goal too large, answering ambiguously");
+            strand.ex_clause.ambiguous = true;
+            return (Selected::Continue, None);
+        }
+
+        let free_vars = canonicalized.free_vars;
+        let key = canonicalized.quantified.map(|goal| goal.map(|goal| Goal::Leaf(LeafGoal::DomainGoal(goal))));
+        let (subgoal_table, depth) = self.table_for(key);
+
+        // Coinductive cycles hold without further ado.
+        if let Some(depth) = self.tables[subgoal_table.0].depth {
+            let coinductive = self.stack[depth..].iter()
+                .all(|t| self.tables[t.0].is_coinductive(&*self.program));
+            if coinductive {
+                debug!("slg: coinductive cycle");
+                return (Selected::Continue, Some(depth));
+            }
+        }
+
+        for answer in self.tables[subgoal_table.0].answers.clone() {
+            let mut continuation = strand.clone();
+            if continuation.apply_answer(&free_vars, &answer) {
+                self.tables[table.0].strands.push(continuation);
+            }
+        }
+
+        if self.tables[subgoal_table.0].depth.is_some() {
+            let strand = strand.clone();
+            self.tables[subgoal_table.0].consumers.push(Consumer { table, strand, free_vars });
+        }
+
+        (Selected::Done, depth)
+    }
+
+    fn select_negative(&mut self, strand: &mut Strand, goal: InEnvironment<Goal>) -> (Selected, Option<usize>) {
+        let canonicalized = strand.infer.canonicalize(&goal);
+        let (negated_table, depth) = self.table_for(canonicalized.quantified);
+        if self.tables[negated_table.0].depth.is_some() {
+            debug!("slg: delaying negative literal");
+            strand.ex_clause.delayed.push(negated_table);
+            return (Selected::Continue, depth);
+        }
+
+        let answers = &self.tables[negated_table.0].answers;
+        if answers.iter().any(|a| a.is_unconditional()) {
+            (Selected::Done, None)
+        } else {
+            if answers.iter().all(|a| a.cannot_prove) {
+                strand.ex_clause.cannot_prove = strand.ex_clause.cannot_prove || !answers.is_empty();
+            } else {
+                strand.ex_clause.ambiguous = true;
+            }
+            (Selected::Continue, None)
+        }
+    }
+
+    fn add_answer(&mut self, table: TableIndex, strand: Strand) {
+        let Strand { mut infer, ex_clause } = strand;
+        let ExClause { subst, constraints, delayed, ambiguous, cannot_prove, fallback, .. } = ex_clause;
+
+        let subst = infer.canonicalize(&ConstrainedSubst { subst, constraints }).quantified;
+        let answer = if subst_depth(&subst.value.subst) > self.config.overflow_depth {
+            if self.tables[table.0].truncated {
+                return;
+            }
+            debug!("slg: answer too large, answering ambiguously");
+            self.tables[table.0].truncated = true;
+            let mut answer = self.tables[table.0].trivial_answer();
+            answer.ambiguous = true;
+            answer
+        } else {
+            Answer { subst, delayed, ambiguous, cannot_prove, fallback }
+        };
+
+        {
+            let answers = &self.tables[table.0].answers;
+            if answers.iter().any(|a| a.subst == answer.subst && (a.is_unconditional() || *a == answer)) {
+                return;
+            }
+        }
+
+        debug!("slg: new answer {:?} for {:?}", answer.subst, self.tables[table.0].goal);
+        let mut continuations = vec![];
+        for consumer in &self.tables[table.0].consumers {
+            let mut strand = consumer.strand.clone();
+            if strand.apply_answer(&consumer.free_vars, &answer) {
+                continuations.push((consumer.table, strand));
+            }
+        }
+        for (consumer_table, strand) in continuations {
+            self.tables[consumer_table.0].strands.push(strand);
+        }
+
+        self.tables[table.0].answers.push(answer);
+    }
+
+    /// Mark the tables from `depth` upwards on the stack as complete, and
+    /// simplify their answers.
+    fn complete(&mut self, depth: usize) {
+        let completed = self.stack.split_off(depth);
+        for &table in &completed {
+            let table = &mut self.tables[table.0];
+            table.depth = None;
+            table.consumers.clear();
+        }
+
+        // Now that the negated goals are complete, delayed literals can be
+        // resolved: an answer depending on a negated goal which holds is
+        // removed, and a delayed literal whose goal has no answer is dropped.
+        // Answers depending on negated goals with conditional answers stay
+        // conditional.
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &table in &completed {
+                for i in (0..self.tables[table.0].answers.len()).rev() {
+                    let delayed = self.tables[table.0].answers[i].delayed.clone();
+                    let holds = |t: &TableIndex| self.tables[t.0].answers.iter().any(|a| a.is_unconditional());
+                    let fails = |t: &TableIndex| self.tables[t.0].answers.is_empty();
+
+                    if delayed.iter().any(&holds) {
+                        self.tables[table.0].answers.remove(i);
+                        changed = true;
+                    } else if delayed.iter().any(&fails) {
+                        let delayed = delayed.into_iter().filter(|t| !fails(t)).collect();
+                        self.tables[table.0].answers[i].delayed = delayed;
+                        changed = true;
+                    }
+                }
+            }
+        }
+
+        for &table in &completed {
+            let program = self.program.clone();
+            let table = &mut self.tables[table.0];
+
+            // Fallback clauses only apply when nothing else does.
+            if table.answers.iter().any(|a| !a.fallback && !a.cannot_prove) {
+                table.answers.retain(|a| !a.fallback);
+            }
+
+            if self.compatible_modality && table.answers.is_empty() {
+                if let Goal::Leaf(LeafGoal::DomainGoal(DomainGoal::Implemented(ref trait_ref))) =
+                    table.goal.value.goal
+                {
+                    if !program.is_knowable(trait_ref) {
+                        debug!("slg: not knowable: answering ambiguously");
+                        let mut answer = table.trivial_answer();
+                        answer.ambiguous = true;
+                        table.answers.push(answer);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Table {
+    fn is_coinductive(&self, program: &ProgramDatabase) -> bool {
+        match self.goal.value.goal {
+            Goal::Leaf(LeafGoal::DomainGoal(DomainGoal::Implemented(ref trait_ref))) =>
+                program.trait_datum(trait_ref.trait_id).binders.value.auto,
+            _ => false,
+        }
+    }
+
+    /// An answer which does not constrain the variables of the goal.
+    fn trivial_answer(&self) -> Answer {
+        let binders = self.goal.binders.clone();
+        let value = ConstrainedSubst { subst: Substitution::from_binders(&binders), constraints: vec![] };
+        let subst = Canonical { value, binders };
+        Answer { subst, delayed: vec![], ambiguous: false, cannot_prove: false, fallback: false }
+    }
+}
+
+impl Answer {
+    fn is_unconditional(&self) -> bool {
+        !self.ambiguous && !self.cannot_prove && self.delayed.is_empty()
+    }
+}
+
+impl ExClause {
+    fn new(subst: Substitution, subgoals: Vec<Literal>) -> Self {
+        ExClause {
+            subst,
+            constraints: vec![],
+            subgoals,
+            delayed: vec![],
+            ambiguous: false,
+            cannot_prove: false,
+            fallback: false,
+        }
+    }
+
+    fn add_unification_result(&mut self, result: UnificationResult) {
+        let UnificationResult { goals, constraints, cannot_prove } = result;
+        self.subgoals.extend(goals.into_iter().map(Literal::Positive));
+        self.constraints.extend(constraints);
+        self.cannot_prove = self.cannot_prove || cannot_prove;
+    }
+}
+
+impl Literal {
+    fn flounders(&self, infer: &mut InferenceTable, program: &ProgramDatabase) -> bool {
+        match *self {
+            Literal::Positive(InEnvironment { goal: LeafGoal::DomainGoal(ref goal), .. }) =>
+                infer.canonicalize(goal).quantified.value.has_unlisted_builtin_candidates(program),
+            Literal::Positive(_) => false,
+            Literal::Negative(ref goal) => !infer.canonicalize(goal).free_vars.is_empty(),
+        }
+    }
+}
+
+impl Strand {
+    /// Remove the next literal to work on, if any.
+    ///
+    /// Negative literals are not solved as long as they involve unresolved
+    /// variables (see `Fulfill::refute`). When only such literals are left,
+    /// the strand *flounders*: they are dropped, and the strand is ambiguous.
+    /// Positive literals do not flounder, even if their `Self` type is
+    /// unknown: their table enumerates the answers, which are truncated past
+    /// the overflow depth. The exception are those which built-in clauses
+    /// that cannot be listed may prove, since their answers could not all be
+    /// enumerated (see `DomainGoal::has_unlisted_builtin_candidates`).
+    fn select(&mut self, program: &ProgramDatabase) -> Option<Literal> {
+        let position = {
+            let infer = &mut self.infer;
+            self.ex_clause.subgoals.iter().rposition(|literal| !literal.flounders(infer, program))
+        };
+        match position {
+            Some(i) => Some(self.ex_clause.subgoals.remove(i)),
+            None => {
+                if !self.ex_clause.subgoals.is_empty() {
+                    debug!("slg: floundering on {:?}", self.ex_clause.subgoals);
+                    self.ex_clause.subgoals.clear();
+                    self.ex_clause.ambiguous = true;
+                }
+                None
+            }
+        }
+    }
+
+    /// Unify the `free_vars` of the strand with the values given by an
+    /// answer to the goal they were canonicalized from.
+    fn apply_answer(&mut self, free_vars: &[ParameterInferenceVariable], answer: &Answer) -> bool {
+        let Canonical { ref value, ref binders } = answer.subst;
+        let ConstrainedSubst { subst, constraints } = self.infer.instantiate(binders.iter().cloned(), value);
+        let empty_env = &Environment::new();
+
+        for (i, var) in free_vars.iter().enumerate() {
+            let unified = match *var {
+                ParameterKind::Ty(ty) => match subst.tys.get(&TyInferenceVariable::from_depth(i)) {
+                    Some(new_ty) => self.infer.unify(empty_env, &ty.to_ty(), new_ty),
+                    None => continue,
+                },
+                ParameterKind::Lifetime(lt) => match subst.lifetimes.get(&LifetimeInferenceVariable::from_depth(i)) {
+                    Some(new_lt) => self.infer.unify(empty_env, &lt.to_lifetime(), new_lt),
+                    None => continue,
+                },
+            };
+            match unified {
+                Ok(result) => self.ex_clause.add_unification_result(result),
+                Err(_) => return false,
+            }
+        }
+
+        let ex_clause = &mut self.ex_clause;
+        ex_clause.constraints.extend(constraints);
+        ex_clause.ambiguous = ex_clause.ambiguous || !answer.is_unconditional() && !answer.cannot_prove;
+        ex_clause.cannot_prove = ex_clause.cannot_prove || answer.cannot_prove;
+        true
+    }
+}
+
+/// Break `goal` down into literals, as `Fulfill::push_goal` does.
+fn push_goal(infer: &mut InferenceTable, environment: &Arc<Environment>, goal: Goal, subgoals: &mut Vec<Literal>) {
+    match goal {
+        Goal::Quantified(QuantifierKind::ForAll, subgoal) => {
+            let mut new_environment = environment.clone();
+            let parameters: Vec<_> = subgoal.binders.iter().map(|pk| {
+                new_environment = new_environment.new_universe();
+                match *pk {
+                    ParameterKind::Lifetime(()) =>
+                        ParameterKind::Lifetime(Lifetime::ForAll(new_environment.universe)),
+                    ParameterKind::Ty(()) => ParameterKind::Ty(Ty::Apply(ApplicationTy {
+                        name: TypeName::ForAll(new_environment.universe),
+                        parameters: vec![],
+                    })),
+                }
+            }).collect();
+            let subgoal = subgoal.value.subst(&parameters);
+            push_goal(infer, &new_environment, subgoal, subgoals);
+        }
+        Goal::Quantified(QuantifierKind::Exists, subgoal) => {
+            let subgoal = infer.instantiate_in(environment.universe, subgoal.binders.iter().cloned(), &subgoal.value);
+            push_goal(infer, environment, *subgoal, subgoals);
+        }
+        Goal::Implies(wc, subgoal) => {
+            let new_environment = &environment.add_clauses(wc);
+            push_goal(infer, new_environment, *subgoal, subgoals);
+        }
+        Goal::And(subgoal1, subgoal2) => {
+            push_goal(infer, environment, *subgoal1, subgoals);
+            push_goal(infer, environment, *subgoal2, subgoals);
+        }
+        Goal::Not(subgoal) => subgoals.push(Literal::Negative(InEnvironment::new(environment, *subgoal))),
+        Goal::Leaf(goal) => subgoals.push(Literal::Positive(InEnvironment::new(environment, goal))),
+    }
+}
+
+/// The substitution giving the values of the variables of a canonical goal,
+/// keyed by their position in `binders`, as in the solutions of the
+/// recursive solver; `infer` must be the table the goal was instantiated in.
+fn binders_subst(infer: &InferenceTable, binders: &[ParameterKind<UniverseIndex>]) -> Substitution {
+    let mut ty_vars = infer.ty_vars().iter();
+    let mut lifetime_vars = infer.lifetime_vars().iter();
+    let mut subst = Substitution::empty();
+    for (i, kind) in binders.iter().enumerate() {
+        match *kind {
+            ParameterKind::Ty(_) => {
+                let var = ty_vars.next().unwrap();
+                subst.tys.insert(TyInferenceVariable::from_depth(i), var.to_ty());
+            }
+            ParameterKind::Lifetime(_) => {
+                let var = lifetime_vars.next().unwrap();
+                subst.lifetimes.insert(LifetimeInferenceVariable::from_depth(i), var.to_lifetime());
+            }
+        }
+    }
+    subst
+}
+
+fn identity_subst(infer: &InferenceTable) -> Substitution {
+    Substitution {
+        tys: infer.ty_vars().iter().map(|t| (*t, t.to_ty())).collect(),
+        lifetimes: infer.lifetime_vars().iter().map(|lt| (*lt, lt.to_lifetime())).collect(),
+    }
+}
+
+/// Whether `subst` leaves all the variables unconstrained.
+fn is_trivial(subst: &Substitution) -> bool {
+    let mut vars: Vec<_> = subst.tys.values().map(|ty| match *ty {
+        Ty::Var(depth) => Some(depth),
+        _ => None,
+    }).chain(subst.lifetimes.values().map(|lt| match *lt {
+        Lifetime::Var(depth) => Some(depth),
+        _ => None,
+    })).collect();
+    let count = vars.len();
+    vars.sort();
+    vars.dedup();
+    vars.len() == count && vars.iter().all(|v| v.is_some())
+}
+
+fn ty_depth(ty: &Ty) -> usize {
+    match *ty {
+        Ty::Var(_) => 1,
+        Ty::Apply(ApplicationTy { ref parameters, .. }) |
+        Ty::Projection(ProjectionTy { ref parameters, .. }) => 1 + parameters_depth(parameters),
+        Ty::ForAll(ref quantified_ty) => 1 + ty_depth(&quantified_ty.ty),
+        Ty::Dyn(ref bounds) => 1 + bounds.value.iter().map(domain_goal_depth).max().unwrap_or(0),
+    }
+}
+
+fn parameters_depth(parameters: &[Parameter]) -> usize {
+    parameters.iter().filter_map(|p| p.as_ref().ty()).map(ty_depth).max().unwrap_or(0)
+}
+
+fn domain_goal_depth(goal: &DomainGoal) -> usize {
+    match *goal {
+        DomainGoal::Implemented(ref trait_ref) |
+        DomainGoal::WellFormed(WellFormed::TraitRef(ref trait_ref)) |
+        DomainGoal::FromEnv(FromEnv::TraitRef(ref trait_ref)) => parameters_depth(&trait_ref.parameters),
+        DomainGoal::Normalize(Normalize { ref projection, ref ty }) =>
+            ::std::cmp::max(parameters_depth(&projection.parameters), ty_depth(ty)),
+        DomainGoal::WellFormed(WellFormed::Ty(ref ty)) |
+        DomainGoal::FromEnv(FromEnv::Ty(ref ty)) => ty_depth(ty),
+        DomainGoal::Outlives(Outlives { ref a, .. }) => a.as_ref().ty().map_or(0, ty_depth),
+    }
+}
+
+fn subst_depth(subst: &Substitution) -> usize {
+    subst.tys.values().map(ty_depth).max().unwrap_or(0)
+}
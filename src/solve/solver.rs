@@ -3,49 +3,167 @@ use std::sync::Arc;
 
 use super::*;
 use solve::fulfill::Fulfill;
-use std::cell::Cell;
-
-thread_local! {
-    // Default overflow depth which will be used in tests
-    static OVERFLOW_DEPTH: Cell<usize> = Cell::new(10);
-}
-
-pub fn set_overflow_depth(overflow_depth: usize) {
-    OVERFLOW_DEPTH.with(|depth| depth.set(overflow_depth));
-}
-
-pub fn get_overflow_depth() -> usize {
-    OVERFLOW_DEPTH.with(|depth| depth.get())
-}
+use solve::failure::{Candidate, Failure, FailureReason};
+use solve::proof::{ClauseSource, ProofFrame, ProofStep, ProofTree};
+use solve::slg::Forest;
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, Instant};
 
 /// We use a stack for detecting cycles. Each stack slot contains:
 /// - a goal which is being processed
 /// - a flag indicating the presence of a cycle during the processing of this goal
 /// - in case a cycle has been found, the latest previous answer to the same goal
+/// - the lowest stack index of a goal whose provisional answer (or coinductive
+///   assumption) was used while processing this goal, if any
 #[derive(Debug)]
 struct StackSlot {
     goal: FullyReducedGoal,
     cycle: bool,
     answer: Option<Solution>,
+    depends_on: Option<usize>,
+
+    /// Whether solving the goal reached the overflow depth somewhere, in
+    /// which case its answer depends on the depth it was asked at.
+    overflowed: bool,
+}
+
+/// Statistics about the answer cache of a `Solver`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Number of goals answered from the cache.
+    pub hits: usize,
+
+    /// Number of goals which had to be solved.
+    pub misses: usize,
+}
+
+/// Counters of the work done by a `Solver`, to spot pathological programs.
+/// Apart from the time per goal, they are only collected by the recursive
+/// engine.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SolverStats {
+    /// The misses of the cache are the fully reduced goals which were solved.
+    pub cache: CacheStats,
+
+    /// Number of program clauses (or assumptions) tried on a goal.
+    pub clauses_tried: usize,
+
+    /// Number of the clauses tried whose consequence unified with the goal.
+    pub clauses_unified: usize,
+
+    /// Number of times a goal was solved again because of a cycle, until its
+    /// answer reached a fixed point.
+    pub fixed_point_iterations: usize,
+
+    pub max_stack_depth: usize,
+
+    /// Number of inference variables created while solving.
+    pub inference_variables: usize,
+
+    /// Number of closed goals solved.
+    pub closed_goals: usize,
+
+    /// The time taken by all the closed goals.
+    pub total_goal_time: Duration,
+
+    /// The time taken by the last closed goal.
+    pub last_goal_time: Option<Duration>,
 }
 
 /// For debugging purpose only: choose whether to apply a tabling strategy for cycles or
 /// treat them as hard errors (the latter can possibly reduce debug output)
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum CycleStrategy {
     Tabling,
     Error,
 }
 
+/// The algorithm used to solve goals.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Engine {
+    /// The recursive solver of this module, which handles cycles by solving
+    /// goals again until a fixed point is reached.
+    Recursive,
+
+    /// The tabled solver of `solve::slg`. Proof trees and explanations of
+    /// failures are only available with the recursive engine: asking for
+    /// them fails with `ErrorKind::UnsupportedWithSlg`. Answer enumeration
+    /// always uses the recursive engine.
+    Slg,
+}
+
+/// The parameters of a `Solver`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SolverConfig {
+    /// Goals nested more deeply than this are reported as an `Overflow` error.
+    pub overflow_depth: usize,
+    pub cycle_strategy: CycleStrategy,
+    pub engine: Engine,
+}
+
+impl Default for SolverConfig {
+    fn default() -> Self {
+        SolverConfig {
+            overflow_depth: 10,
+            cycle_strategy: CycleStrategy::Tabling,
+            engine: Engine::Recursive,
+        }
+    }
+}
+
 /// A Solver is the basic context in which you can propose goals for a given
 /// program. **All questions posed to the solver are in canonical, closed form,
 /// so that each question is answered with effectively a "clean slate"**. This
 /// allows for better caching, and simplifies management of the inference
 /// context.
+///
+/// Answers to fully reduced goals are cached for the lifetime of the solver,
+/// so a single solver should be reused for all the queries about a program.
 pub struct Solver {
-    pub(super) program: Arc<ProgramEnvironment>,
+    pub(super) program: Arc<ProgramDatabase>,
     stack: Vec<StackSlot>,
-    cycle_strategy: CycleStrategy,
-    overflow_depth: usize,
+    cache: HashMap<FullyReducedGoal, ::std::result::Result<Solution, CachedError>>,
+    pub(super) stats: SolverStats,
+    pub(super) config: SolverConfig,
+    compatible_modality: bool,
+
+    /// Proof trees under construction; empty unless a proof was asked for.
+    proof_frames: Vec<ProofFrame>,
+
+    /// Proofs of the cached answers, recorded when they were first computed.
+    proofs: HashMap<FullyReducedGoal, ProofTree>,
+
+    /// Explanations of the cached failures, likewise.
+    failures: HashMap<FullyReducedGoal, Failure>,
+
+    /// The answer tables of the SLG engine, if it is used.
+    forest: Forest,
+}
+
+/// An error cached as the answer to a goal. An `Error` cannot be cloned, so
+/// the kinds the solver matches on are kept as such, and the others as their
+/// message; a cache hit then fails with the same kind of error.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum CachedError {
+    CannotEquate(Ty, Ty),
+    Other(String),
+}
+
+impl CachedError {
+    fn new(error: &Error) -> Self {
+        match *error.kind() {
+            ErrorKind::CannotEquate(ref a, ref b) => CachedError::CannotEquate(a.clone(), b.clone()),
+            _ => CachedError::Other(error.to_string()),
+        }
+    }
+
+    fn to_error(&self) -> Error {
+        match *self {
+            CachedError::CannotEquate(ref a, ref b) => ErrorKind::CannotEquate(a.clone(), b.clone()).into(),
+            CachedError::Other(ref message) => message.clone().into(),
+        }
+    }
 }
 
 /// An extension trait for merging `Result`s
@@ -57,6 +175,14 @@ impl<T> MergeWith<T> for Result<T> {
     fn merge_with<F>(self: Result<T>, other: Result<T>, f: F) -> Result<T>
         where F: FnOnce(T, T) -> T
     {
+        // An overflow in either branch makes the merged result meaningless.
+        if is_overflow(&self) {
+            return self;
+        }
+        if is_overflow(&other) {
+            return other;
+        }
+
         match (self, other) {
             (Err(_), Ok(v)) |
             (Ok(v), Err(_)) => Ok(v),
@@ -66,20 +192,95 @@ impl<T> MergeWith<T> for Result<T> {
     }
 }
 
+/// A solution, together with the proof steps of the clauses it was obtained
+/// by if a proof is being recorded (none otherwise).
+type Derivation = (Solution, Vec<ProofStep>);
+
+/// Merge two derivations with `f`, keeping the steps of the one whose
+/// solution is kept, or of both if their solutions are combined.
+fn merge_derivations<F>(a: Derivation, b: Derivation, f: F) -> Derivation
+    where F: FnOnce(Solution, Solution) -> Solution
+{
+    let ((a, a_steps), (b, b_steps)) = (a, b);
+    if a_steps.is_empty() && b_steps.is_empty() {
+        return (f(a, b), vec![]);
+    }
+
+    let merged = f(a.clone(), b.clone());
+    let steps = if merged == a {
+        a_steps
+    } else if merged == b {
+        b_steps
+    } else {
+        a_steps.into_iter().chain(b_steps).collect()
+    };
+    (merged, steps)
+}
+
+fn is_overflow<T>(result: &Result<T>) -> bool {
+    result.as_ref().err().map_or(false, |e| e.is_overflow())
+}
+
+/// Weaken the result of a goal that built-in clauses which were not tried
+/// may prove, see `DomainGoal::has_unlisted_builtin_candidates`.
+fn unlisted_candidates_as_ambiguity(result: Result<Solution>) -> Result<Solution> {
+    match result {
+        Ok(Solution::Unique(constrained)) => {
+            let Canonical { value, binders } = constrained;
+            Ok(Solution::Ambig(Guidance::Suggested(Canonical { value: value.subst, binders })))
+        }
+        Ok(Solution::Ambig(Guidance::Definite(subst))) => Ok(Solution::Ambig(Guidance::Suggested(subst))),
+        Err(ref e) if !e.is_overflow() => Ok(Solution::Ambig(Guidance::Unknown)),
+        result => result,
+    }
+}
+
+/// An overflow, once a solution was found by other means, only means that
+/// there may be other solutions: it is then counted as an ambiguous one.
+fn overflow_as_ambiguity(result: Result<Derivation>) -> Result<Derivation> {
+    if is_overflow(&result) {
+        Ok((Solution::Ambig(Guidance::Unknown), vec![]))
+    } else {
+        result
+    }
+}
+
 impl Solver {
-    pub fn new(
-        program: &Arc<ProgramEnvironment>,
-        cycle_strategy: CycleStrategy,
-        overflow_depth: usize
-    ) -> Self {
+    pub fn new<P: ProgramDatabase + 'static>(program: &Arc<P>, config: SolverConfig) -> Self {
+        let program: Arc<ProgramDatabase> = program.clone();
         Solver {
-            program: program.clone(),
+            forest: Forest::new(&program, config),
+            program,
             stack: vec![],
-            cycle_strategy,
-            overflow_depth,
+            cache: HashMap::new(),
+            stats: SolverStats::default(),
+            config,
+            compatible_modality: false,
+            proof_frames: vec![],
+            proofs: HashMap::new(),
+            failures: HashMap::new(),
         }
     }
 
+    /// Switch to the "compatible modality" used by coherence: a trait goal
+    /// that could be made to hold by an impl in another crate (see
+    /// `ProgramDatabase::is_knowable`) is answered with an ambiguous
+    /// solution rather than an error when no impl applies. This way, impls
+    /// which could be made to overlap by other crates are reported.
+    pub fn in_compatible_modality(mut self) -> Self {
+        self.compatible_modality = true;
+        self.forest = self.forest.in_compatible_modality();
+        self
+    }
+
+    pub fn cache_stats(&self) -> CacheStats {
+        self.stats.cache
+    }
+
+    pub fn stats(&self) -> &SolverStats {
+        &self.stats
+    }
+
     /// Attempt to solve a *closed* goal. The substitution returned in the
     /// solution will be for the fully decomposed goal. For example, given the
     /// program
@@ -95,6 +296,21 @@ impl Solver {
     /// solution is produced with substitution `?0 := u8`. The `?0` is drawn
     /// from the number of the instantiated existential.
     pub fn solve_closed_goal(&mut self, goal: InEnvironment<Goal>) -> Result<Solution> {
+        info_heading!("solve_closed_goal({:?})", goal);
+        let start = Instant::now();
+        let result = match self.config.engine {
+            Engine::Recursive => self.solve_closed_goal_recursively(goal),
+            Engine::Slg => self.forest.solve_closed_goal(goal),
+        };
+        let time = start.elapsed();
+        self.stats.closed_goals += 1;
+        self.stats.total_goal_time += time;
+        self.stats.last_goal_time = Some(time);
+        info!("solution: {:?}", result);
+        result
+    }
+
+    fn solve_closed_goal_recursively(&mut self, goal: InEnvironment<Goal>) -> Result<Solution> {
         let mut fulfill = Fulfill::new(self);
         fulfill.push_goal(&goal.environment, goal.goal);
 
@@ -103,30 +319,84 @@ impl Solver {
         // substitution is only used for REPL/debugging purposes anyway; in
         // rustc, the top-level interaction would happen by manipulating a
         // Fulfill more directly.
-        let subst = Substitution {
-            tys: fulfill
-                .ty_vars()
-                .iter()
-                .map(|t| (*t, t.to_ty()))
-                .collect(),
-            lifetimes: fulfill
-                .lifetime_vars()
-                .iter()
-                .map(|lt| (*lt, lt.to_lifetime()))
-                .collect(),
-        };
+        let subst = fulfill.initial_subst();
 
         fulfill.solve(subst)
     }
 
+    /// Like `solve_closed_goal`, but also returns proof trees for the
+    /// fully reduced goals that were solved, showing the clauses that were
+    /// applied at each step.
+    pub fn solve_closed_goal_with_proof(&mut self, goal: InEnvironment<Goal>)
+                                        -> Result<(Solution, Vec<ProofTree>)>
+    {
+        if self.config.engine == Engine::Slg {
+            bail!(ErrorKind::UnsupportedWithSlg("proofs"));
+        }
+        self.proof_frames.push(ProofFrame::default());
+        let solution = self.solve_closed_goal(goal);
+        let frame = self.proof_frames.pop().unwrap();
+        Ok((solution?, frame.premises))
+    }
+
+    /// Attempt to solve a closed goal, explaining why it could not be proven
+    /// if that is the case. The explanation is pruned to `depth` nested
+    /// goals. Returns `None` if the goal has a solution.
+    pub fn explain_failure(&mut self, goal: InEnvironment<Goal>, depth: usize) -> Result<Option<Failure>> {
+        if self.config.engine == Engine::Slg {
+            bail!(ErrorKind::UnsupportedWithSlg("explanations of failures"));
+        }
+        self.proof_frames.push(ProofFrame::default());
+        let solution = self.solve_closed_goal(goal);
+        let frame = self.proof_frames.pop().unwrap();
+        match solution {
+            Ok(_) => Ok(None),
+            Err(e) => {
+                if e.is_overflow() {
+                    return Err(e);
+                }
+                Ok(frame.failure.map(|mut failure| {
+                    failure.prune(depth);
+                    failure
+                }))
+            }
+        }
+    }
+
     /// Attempt to solve a goal that has been fully broken down into leaf form
     /// and canonicalized. This is where the action really happens, and is the
-    /// place where we would perform caching in rustc (and may eventually do in Chalk).
+    /// place where answers are cached.
     pub fn solve_reduced_goal(&mut self, goal: FullyReducedGoal) -> Result<Solution> {
         debug_heading!("Solver::solve({:?})", goal);
 
-        if self.stack.len() > self.overflow_depth {
-            panic!("overflow depth reached");
+        if let Some(answer) = self.cache.get(&goal).cloned() {
+            debug!("cache hit: {:?}", answer);
+            self.stats.cache.hits += 1;
+            if self.recording() {
+                match answer {
+                    Ok(ref solution) => {
+                        let proof = self.proofs.get(&goal).cloned().unwrap_or_else(|| {
+                            ProofTree::Proved { goal: goal.clone(), solution: solution.clone(), step: ProofStep::Cached }
+                        });
+                        self.record_proof(proof);
+                    }
+                    Err(_) => {
+                        let failure = self.failures.get(&goal).cloned().unwrap_or_else(|| {
+                            Failure::NotProved { goal: goal.clone(), reason: FailureReason::Cached }
+                        });
+                        self.record_failure(failure);
+                    }
+                }
+            }
+            return answer.map_err(|e| e.to_error());
+        }
+
+        if self.stack.len() > self.config.overflow_depth {
+            let stack = self.stack.iter().map(|s| s.goal.clone()).chain(Some(goal)).collect();
+            if let Some(slot) = self.stack.last_mut() {
+                slot.overflowed = true;
+            }
+            bail!(ErrorKind::Overflow(stack));
         }
 
         // The goal was already on the stack: we found a cycle.
@@ -147,18 +417,31 @@ impl Solver {
                     constraints: vec![],
                 };
                 debug!("applying coinductive semantics");
-                return Ok(Solution::Unique(Canonical { value, binders: goal.into_binders() }));
+                self.record_dependency(index);
+                let solution = Solution::Unique(Canonical { value, binders: goal.clone().into_binders() });
+                self.record_leaf(goal, &solution, ProofStep::Coinduction);
+                return Ok(solution);
             }
 
             // Else we indicate that we found a cycle by setting `slot.cycle = true`.
             // If there is no cached answer, we can't make any more progress and return `Err`.
             // If there is one, use this answer.
-            let slot = &mut self.stack[index];
-            slot.cycle = true;
-            debug!("cycle detected: previous solution {:?}", slot.answer);
-            return slot.answer.clone().ok_or("cycle".into());
+            self.record_dependency(index);
+            let answer = {
+                let slot = &mut self.stack[index];
+                slot.cycle = true;
+                debug!("cycle detected: previous solution {:?}", slot.answer);
+                slot.answer.clone()
+            };
+            match answer {
+                Some(ref solution) => self.record_leaf(goal, solution, ProofStep::Cycle),
+                None => self.record_failure(Failure::NotProved { goal, reason: FailureReason::Cycle }),
+            }
+            return answer.ok_or("cycle".into());
         }
 
+        self.stats.cache.misses += 1;
+
         // We start with `answer = None` and try to solve the goal. At the end of the iteration,
         // `answer` will be updated with the result of the solving process. If we detect a cycle
         // during the solving process, we cache `answer` and try to solve the goal again. We repeat
@@ -169,12 +452,16 @@ impl Solver {
         // the function which maps the loop iteration to `answer` is a nondecreasing function
         // so this function will eventually be constant and the loop terminates.
         let mut answer = None;
-        loop {
+        let (result, depends_on, overflowed, frame) = loop {
             self.stack.push(StackSlot {
                 goal: goal.clone(),
                 cycle: false,
                 answer: answer.clone(),
+                depends_on: None,
+                overflowed: false,
             });
+            self.stats.max_stack_depth = ::std::cmp::max(self.stats.max_stack_depth, self.stack.len());
+            self.push_proof_frame();
 
             debug!("Solver::solve: new loop iteration");
             let result = match goal.clone() {
@@ -192,21 +479,18 @@ impl Solver {
                     let env_clauses = value.environment.clauses.iter()
                         .cloned()
                         .map(DomainGoal::into_program_clause);
-                    let env_solution = self.solve_from_clauses(&binders, &value, env_clauses);
-
-                    let prog_clauses: Vec<_> = self.program.program_clauses.iter()
-                        .cloned()
-                        .filter(|clause| !clause.fallback_clause)
-                        .collect();
-                    let prog_solution = self.solve_from_clauses(&binders, &value, prog_clauses);
+                    let env_solution = self.solve_from_clauses(&binders, &value, ClauseSource::Environment, env_clauses);
 
                     // These fallback clauses are used when we're sure we'll never
                     // reach Unique via another route
-                    let fallback: Vec<_> = self.program.program_clauses.iter()
-                        .cloned()
-                        .filter(|clause| clause.fallback_clause)
-                        .collect();
-                    let fallback_solution = self.solve_from_clauses(&binders, &value, fallback);
+                    let mut clauses = self.program.program_clauses_for(&value.goal);
+                    clauses.extend(value.goal.builtin_clauses(&*self.program));
+                    let (fallback, prog_clauses): (Vec<_>, Vec<_>) = clauses
+                        .into_iter()
+                        .partition(|clause| clause.fallback_clause);
+
+                    let prog_solution = self.solve_from_clauses(&binders, &value, ClauseSource::Program, prog_clauses);
+                    let fallback_solution = self.solve_from_clauses(&binders, &value, ClauseSource::Fallback, fallback);
 
                     // Now that we have all the outcomes, we attempt to combine
                     // them. Here, we apply a heuristic (also found in rustc): if we
@@ -216,15 +500,50 @@ impl Solver {
                     // made in a given context are more likely to be relevant than
                     // general `impl`s.
 
-                    env_solution
-                        .merge_with(prog_solution, |env, prog| env.favor_over(prog))
-                        .merge_with(fallback_solution, |merged, fallback| merged.fallback_to(fallback))
+                    // An overflow in one of the sources does not discard the
+                    // solutions found by the others, but makes them ambiguous.
+                    let found = env_solution.is_ok() || prog_solution.is_ok() || fallback_solution.is_ok();
+                    let (env_solution, prog_solution, fallback_solution) = if found {
+                        (overflow_as_ambiguity(env_solution),
+                         overflow_as_ambiguity(prog_solution),
+                         overflow_as_ambiguity(fallback_solution))
+                    } else {
+                        (env_solution, prog_solution, fallback_solution)
+                    };
+
+                    let derivation = env_solution
+                        .merge_with(prog_solution, |env, prog| merge_derivations(env, prog, Solution::favor_over))
+                        .merge_with(fallback_solution, |merged, fallback| {
+                            merge_derivations(merged, fallback, Solution::fallback_to)
+                        });
+                    let solution = derivation.map(|(solution, steps)| {
+                        if let Some(frame) = self.proof_frames.last_mut() {
+                            frame.steps = steps;
+                        }
+                        solution
+                    });
+
+                    let failed = match solution {
+                        Ok(ref solution) => solution.cannot_be_proven(),
+                        Err(ref e) => !e.is_overflow(),
+                    };
+
+                    match value.goal {
+                        DomainGoal::Implemented(ref trait_ref)
+                            if failed && self.compatible_modality && !self.program.is_knowable(trait_ref) =>
+                        {
+                            debug!("not knowable: answering ambiguously");
+                            Ok(Solution::Ambig(Guidance::Unknown))
+                        }
+                        _ => solution,
+                    }
                 }
             };
             debug!("Solver::solve: loop iteration result = {:?}", result);
 
             let slot = self.stack.pop().unwrap();
-            match self.cycle_strategy {
+            let frame = self.pop_proof_frame();
+            match self.config.cycle_strategy {
                 CycleStrategy::Tabling if slot.cycle => {
                     let actual_answer = result.as_ref().ok().map(|s| s.clone());
                     let fixed_point = answer == actual_answer;
@@ -236,14 +555,147 @@ impl Solver {
                     // `multiple_ambiguous_cycles`.
                     match (fixed_point, &actual_answer) {
                         (_, &Some(Solution::Ambig(_))) | (true, _) =>
-                            return result,
+                            break (result, slot.depends_on, slot.overflowed, frame),
                         _ => ()
                     };
 
                     answer = actual_answer;
+                    self.stats.fixed_point_iterations += 1;
                 }
-                _ => return result,
+                _ => break (result, slot.depends_on, slot.overflowed, frame),
             };
+        };
+
+        // Built-in clauses which were not tried may prove the goal as well, so
+        // what was found is only a suggestion. This is only done once the
+        // answer reached a fixed point, which an ambiguous answer would cut
+        // short.
+        let result = match goal {
+            FullyReducedGoal::DomainGoal(ref canonical)
+                if canonical.value.goal.has_unlisted_builtin_candidates(&*self.program) =>
+            {
+                debug!("unlisted built-in candidates: answering ambiguously");
+                unlisted_candidates_as_ambiguity(result)
+            }
+            _ => result,
+        };
+
+        // An answer computed from the provisional answer of a goal lower in
+        // the stack may not be final, so it is not cached; instead, the goal
+        // that is now on top of the stack inherits the dependency. An answer
+        // reached despite an overflow somewhere depends on the depth at which
+        // the goal was asked, so it is not cached either, nor are those of the
+        // goals it was needed for.
+        let (proof, failure) = match (&result, frame) {
+            (&Ok(ref solution), Some(frame)) => {
+                let step = match goal {
+                    FullyReducedGoal::EqGoal(_) => Some(ProofStep::Unification { premises: frame.premises }),
+                    FullyReducedGoal::DomainGoal(_) => {
+                        let mut steps = frame.steps;
+                        match steps.len() {
+                            0 | 1 => steps.pop(),
+                            _ => Some(ProofStep::Combined { steps }),
+                        }
+                    }
+                };
+                let proof = step.map(|step| ProofTree::Proved { goal: goal.clone(), solution: solution.clone(), step });
+                (proof, None)
+            }
+            (&Err(ref e), Some(frame)) if !e.is_overflow() => {
+                let reason = match goal {
+                    FullyReducedGoal::EqGoal(_) => FailureReason::from_frame(frame, e),
+                    FullyReducedGoal::DomainGoal(_) => FailureReason::NoCandidates(frame.candidates),
+                };
+                (None, Some(Failure::NotProved { goal: goal.clone(), reason }))
+            }
+            _ => (None, None),
+        };
+
+        let index = self.stack.len();
+        if overflowed {
+            if let Some(slot) = self.stack.last_mut() {
+                slot.overflowed = true;
+            }
+        }
+        match depends_on {
+            Some(depends_on) if depends_on < index => self.record_dependency(depends_on),
+            _ if overflowed => (),
+            _ => {
+                let answer = result.as_ref().map(|s| s.clone()).map_err(CachedError::new);
+                if let Some(ref proof) = proof {
+                    self.proofs.insert(goal.clone(), proof.clone());
+                }
+                if let Some(ref failure) = failure {
+                    self.failures.insert(goal.clone(), failure.clone());
+                }
+                self.cache.insert(goal, answer);
+            }
+        }
+
+        if let Some(proof) = proof {
+            self.record_proof(proof);
+        }
+        if let Some(failure) = failure {
+            self.record_failure(failure);
+        }
+
+        result
+    }
+
+    fn recording(&self) -> bool {
+        !self.proof_frames.is_empty()
+    }
+
+    fn push_proof_frame(&mut self) {
+        if self.recording() {
+            self.proof_frames.push(ProofFrame::default());
+        }
+    }
+
+    fn pop_proof_frame(&mut self) -> Option<ProofFrame> {
+        if self.proof_frames.len() > 1 {
+            self.proof_frames.pop()
+        } else {
+            None
+        }
+    }
+
+    /// Record the proof of a goal in the proof tree under construction, if any.
+    pub(super) fn record_proof(&mut self, proof: ProofTree) {
+        if let Some(frame) = self.proof_frames.last_mut() {
+            frame.add_premise(proof);
+        }
+    }
+
+    /// Record that a goal could not be proven, if an explanation is under
+    /// construction.
+    pub(super) fn record_failure(&mut self, failure: Failure) {
+        if let Some(frame) = self.proof_frames.last_mut() {
+            frame.failure = Some(failure);
+        }
+    }
+
+    fn record_leaf(&mut self, goal: FullyReducedGoal, solution: &Solution, step: ProofStep) {
+        if self.recording() {
+            self.record_proof(ProofTree::Proved { goal, solution: solution.clone(), step });
+        }
+    }
+
+    /// Run `op` in a fresh proof frame, which is discarded afterwards.
+    pub(super) fn without_proof<F, R>(&mut self, op: F) -> R
+        where F: FnOnce(&mut Solver) -> R
+    {
+        self.push_proof_frame();
+        let result = op(self);
+        self.pop_proof_frame();
+        result
+    }
+
+    /// Record that the goal on top of the stack relies on the provisional
+    /// answer of the goal at stack index `index`.
+    fn record_dependency(&mut self, index: usize) {
+        if let Some(slot) = self.stack.last_mut() {
+            slot.depends_on = Some(slot.depends_on.map_or(index, |d| ::std::cmp::min(d, index)));
         }
     }
 
@@ -268,29 +720,62 @@ impl Solver {
         &mut self,
         binders: &[ParameterKind<UniverseIndex>],
         goal: &InEnvironment<DomainGoal>,
+        source: ClauseSource,
         clauses: C
-    ) -> Result<Solution>
+    ) -> Result<Derivation>
     where
         C: IntoIterator<Item = ProgramClause>,
     {
         let mut cur_solution = None;
+        let mut overflow = None;
         for ProgramClause { implication, .. } in clauses {
             debug_heading!("clause={:?}", implication);
-
-            let res = self.solve_via_implication(binders, goal.clone(), implication);
-            if let Ok(solution) = res {
-                debug!("ok: solution={:?}", solution);
-                cur_solution = Some(
-                    match cur_solution {
-                        None => solution,
-                        Some(cur) => solution.combine(cur),
-                    },
-                );
-            } else {
-                debug!("error");
+            self.stats.clauses_tried += 1;
+
+            let clause = if self.recording() { Some(implication.clone()) } else { None };
+            self.push_proof_frame();
+            let result = self.solve_via_implication(binders, goal.clone(), implication);
+            let frame = self.pop_proof_frame();
+
+            match result {
+                Ok(solution) => {
+                    debug!("ok: solution={:?}", solution);
+                    let steps = match (clause, frame) {
+                        (Some(clause), Some(frame)) => {
+                            vec![ProofStep::Clause { source, clause, premises: frame.premises }]
+                        }
+                        _ => vec![],
+                    };
+                    cur_solution = Some(
+                        match cur_solution {
+                            None => (solution, steps),
+                            Some(cur) => merge_derivations((solution, steps), cur, Solution::combine),
+                        },
+                    );
+                }
+                Err(e) => {
+                    if e.is_overflow() {
+                        debug!("overflow");
+                        overflow = Some(e);
+                        continue;
+                    }
+                    debug!("error");
+                    if let (Some(clause), Some(frame)) = (clause, frame) {
+                        let reason = FailureReason::from_frame(frame, &e);
+                        let candidate = Candidate { source, clause, reason };
+                        self.proof_frames.last_mut().unwrap().candidates.push(candidate);
+                    }
+                }
             }
         }
-        cur_solution.ok_or("no applicable candidates".into())
+        match (cur_solution, overflow) {
+            (Some(cur), Some(e)) => overflow_as_ambiguity(Err(e)).map(|ambiguous| {
+                merge_derivations(cur, ambiguous, Solution::combine)
+            }),
+            (Some(cur), None) => Ok(cur),
+            (None, Some(e)) => Err(e),
+            (None, None) => Err("no applicable candidates".into()),
+        }
     }
 
     /// Modus ponens! That is: try to apply an implication by proving its premises.
@@ -308,6 +793,7 @@ impl Solver {
             fulfill.instantiate_in(goal.environment.universe, clause.binders, &clause.value);
 
         fulfill.unify(&goal.environment, &goal.goal, &consequence)?;
+        fulfill.record_clause_unified();
 
         // if so, toss in all of its premises
         for condition in conditions {
@@ -318,3 +804,27 @@ impl Solver {
         fulfill.solve(subst)
     }
 }
+
+impl fmt::Display for SolverStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "closed goals:           {} in {}", self.closed_goals, Millis(self.total_goal_time))?;
+        if let Some(time) = self.last_goal_time {
+            writeln!(f, "last goal:              {}", Millis(time))?;
+        }
+        writeln!(f, "reduced goals solved:   {} ({} cache hits)", self.cache.misses, self.cache.hits)?;
+        writeln!(f, "clauses tried:          {} ({} unified)", self.clauses_tried, self.clauses_unified)?;
+        writeln!(f, "fixed-point iterations: {}", self.fixed_point_iterations)?;
+        writeln!(f, "max stack depth:        {}", self.max_stack_depth)?;
+        write!(f, "inference variables:    {}", self.inference_variables)
+    }
+}
+
+/// Displays a duration in milliseconds.
+struct Millis(Duration);
+
+impl fmt::Display for Millis {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let millis = self.0.as_secs() as f64 * 1000.0 + self.0.subsec_nanos() as f64 / 1_000_000.0;
+        write!(f, "{:.3}ms", millis)
+    }
+}
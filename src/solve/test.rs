@@ -2,11 +2,19 @@ use chalk_parse;
 use errors::*;
 use ir;
 use lower::*;
-use solve::solver::{self, Solver, CycleStrategy};
+use solve::answers::{AnswerSet, AnswerSetStatus};
+use solve::harness::{GoalTest, TestFile};
+use solve::json::ToJson;
+use solve::solver::{Engine, Solver, SolverConfig};
+use trace::{self, Event, Filter, Level, Subsystem, Tracer, with_tracer};
+use std::cell::RefCell;
+use std::fmt;
+use std::panic;
+use std::rc::Rc;
 use std::sync::Arc;
 
 fn parse_and_lower_program(text: &str) -> Result<ir::Program> {
-    chalk_parse::parse_program(text)?.lower()
+    chalk_parse::parse_program(text)?.lower(SolverConfig::default())
 }
 
 fn parse_and_lower_goal(program: &ir::Program, text: &str) -> Result<Box<ir::Goal>> {
@@ -14,42 +22,101 @@ fn parse_and_lower_goal(program: &ir::Program, text: &str) -> Result<Box<ir::Goa
 }
 
 macro_rules! test {
-    (program $program:tt $(goal $goal:tt yields { $expected:expr })*) => {
-        solve_goal(stringify!($program), vec![$((stringify!($goal), $expected)),*])
-    }
+    (program $program:tt $(goal $goal:tt yields { $expected:expr } $(yields[SLG] { $slg:expr })*)*) => {
+        solve_goal(stringify!($program), vec![$((stringify!($goal), $expected, None $(.or(Some($slg)))*)),*], true)
+    };
+
+    // For programs that are ill-formed on purpose.
+    (ill_formed program $program:tt $(goal $goal:tt yields { $expected:expr } $(yields[SLG] { $slg:expr })*)*) => {
+        solve_goal(stringify!($program), vec![$((stringify!($goal), $expected, None $(.or(Some($slg)))*)),*], false)
+    };
 }
 
+/// Solve each goal with both engines. The SLG engine is expected to give the
+/// same results as the recursive one, except where a `yields[SLG]` is given.
+/// The program must be well-formed unless `check_well_formedness` is false.
 fn solve_goal(program_text: &str,
-              goals: Vec<(&str, &str)>)
+              goals: Vec<(&str, &str, Option<&str>)>,
+              check_well_formedness: bool)
 {
     println!("program {}", program_text);
     assert!(program_text.starts_with("{"));
     assert!(program_text.ends_with("}"));
-    let program = Arc::new(parse_and_lower_program(&program_text[1..program_text.len()-1]).unwrap());
-    let env = Arc::new(program.environment());
-    ir::set_current_program(&program, || {
-        for (goal_text, expected) in goals {
-            println!("----------------------------------------------------------------------");
-            println!("goal {}", goal_text);
-            assert!(goal_text.starts_with("{"));
-            assert!(goal_text.ends_with("}"));
-            let goal = parse_and_lower_goal(&program, &goal_text[1..goal_text.len()-1]).unwrap();
-
-            let mut solver = Solver::new(&env, CycleStrategy::Tabling, solver::get_overflow_depth());
-            let goal = ir::InEnvironment::new(&ir::Environment::new(), *goal);
-            let result = match solver.solve_closed_goal(goal) {
-                Ok(v) => format!("{}", v),
-                Err(e) => format!("No possible solution: {}", e),
-            };
-            println!("expected:\n{}", expected);
-            println!("actual:\n{}", result);
-
-            // remove all whitespace:
-            let expected1: String = expected.chars().filter(|w| !w.is_whitespace()).collect();
-            let result1: String = result.chars().filter(|w| !w.is_whitespace()).collect();
-            assert!(!expected1.is_empty() && result1.starts_with(&expected1));
-        }
-    });
+    let goals = goals.into_iter()
+                     .map(|(goal_text, expected, slg_expected)| {
+                         assert!(goal_text.starts_with("{"));
+                         assert!(goal_text.ends_with("}"));
+                         GoalTest {
+                             line: None,
+                             goal: goal_text[1..goal_text.len()-1].to_string(),
+                             expected: expected.to_string(),
+                             slg_expected: slg_expected.map(|s| s.to_string()),
+                         }
+                     })
+                     .collect();
+    let test_file = TestFile { program: program_text[1..program_text.len()-1].to_string(), goals };
+    let failures = if check_well_formedness {
+        test_file.run(SolverConfig::default())
+    } else {
+        test_file.run_unchecked(SolverConfig::default())
+    };
+    let failures = failures.unwrap();
+    for failure in &failures {
+        println!("{}", failure);
+    }
+    assert!(failures.is_empty());
+}
+
+/// The program of `prove_clone`, for the tests below which use the solver
+/// directly rather than through `test!`.
+const CLONE_PROGRAM: &'static str = "
+    struct Foo { }
+    struct Bar { }
+    struct Vec<T> { }
+    trait Clone { }
+    impl<T> Clone for Vec<T> where T: Clone { }
+    impl Clone for Foo { }
+";
+
+/// A lowered program, with the environment its solvers share.
+struct Fixture {
+    program: Arc<ir::Program>,
+    env: Arc<ir::ProgramEnvironment>,
+}
+
+impl Fixture {
+    fn new(program_text: &str) -> Self {
+        let program = Arc::new(parse_and_lower_program(program_text).unwrap());
+        let env = Arc::new(program.environment());
+        Fixture { program, env }
+    }
+
+    fn clone_program() -> Self {
+        Fixture::new(CLONE_PROGRAM)
+    }
+
+    fn solver(&self) -> Solver {
+        self.solver_with(SolverConfig::default())
+    }
+
+    fn solver_with(&self, config: SolverConfig) -> Solver {
+        Solver::new(&self.env, config)
+    }
+
+    /// A closed goal, in the empty environment.
+    fn goal(&self, text: &str) -> ir::InEnvironment<ir::Goal> {
+        let goal = parse_and_lower_goal(&self.program, text).unwrap();
+        ir::InEnvironment::new(&ir::Environment::new(), *goal)
+    }
+
+    /// Display `value` with the item names of the program.
+    fn render<T: fmt::Display>(&self, value: &T) -> String {
+        ir::with_program(&self.program, value).to_string()
+    }
+
+    fn render_all<T: fmt::Display>(&self, values: &[T]) -> String {
+        values.iter().map(|value| self.render(value)).collect()
+    }
 }
 
 #[test]
@@ -90,6 +157,376 @@ fn prove_clone() {
     }
 }
 
+#[test]
+fn cached_answers() {
+    let fixture = Fixture::clone_program();
+    let mut solver = fixture.solver();
+    let goal = fixture.goal("Vec<Vec<Foo>>: Clone");
+
+    let first = solver.solve_closed_goal(goal.clone()).unwrap();
+    let stats = solver.cache_stats();
+    assert!(stats.misses > 0);
+
+    // Asking again is answered directly from the cache.
+    let second = solver.solve_closed_goal(goal).unwrap();
+    assert_eq!(first, second);
+    assert_eq!(solver.cache_stats().misses, stats.misses);
+    assert_eq!(solver.cache_stats().hits, stats.hits + 1);
+}
+
+#[test]
+fn cached_errors() {
+    let fixture = Fixture::clone_program();
+    let mut solver = fixture.solver();
+    let goal = fixture.goal("Foo = Bar");
+
+    // A cache hit fails with the same kind of error as the first query.
+    for hits in 0..2 {
+        let error = solver.solve_closed_goal(goal.clone()).unwrap_err();
+        match *error.kind() {
+            ErrorKind::CannotEquate(..) => (),
+            ref kind => panic!("expected a type mismatch, found `{}`", kind),
+        }
+        assert_eq!(solver.cache_stats().hits, hits);
+    }
+}
+
+#[test]
+fn solver_stats() {
+    let fixture = Fixture::new("
+        struct Foo { }
+        struct Bar { }
+        struct Vec<T> { }
+        trait Clone { }
+        impl Clone for Vec<Foo> where Foo: Clone { }
+        impl Clone for Vec<Bar> { }
+        impl Clone for Foo { }
+        impl<T> Clone for T where T: Clone { }
+    ");
+    let mut solver = fixture.solver();
+    for text in &["exists<T> { T: Clone }", "Vec<Foo>: Clone"] {
+        solver.solve_closed_goal(fixture.goal(text)).unwrap();
+    }
+
+    let stats = solver.stats();
+    assert_eq!(stats.closed_goals, 2);
+    assert!(stats.last_goal_time.map_or(false, |time| time <= stats.total_goal_time));
+    assert!(stats.cache.misses > 0);
+    assert!(stats.clauses_tried > stats.clauses_unified);
+    assert!(stats.fixed_point_iterations > 0);
+    assert!(stats.max_stack_depth > 1);
+    assert!(stats.inference_variables > 0);
+}
+
+#[test]
+fn trace_events() {
+    let fixture = Fixture::clone_program();
+
+    // Solve a goal with a fresh solver, collecting the events let through
+    // by `filter`.
+    let events = |filter| {
+        let events = Rc::new(RefCell::new(vec![]));
+        let sink = {
+            let events = events.clone();
+            move |event: &Event| events.borrow_mut().push(event.clone())
+        };
+        let goal = fixture.goal("Vec<Vec<Foo>>: Clone");
+        let mut solver = fixture.solver();
+        ir::set_current_program(&fixture.program, || {
+            with_tracer(Tracer::new(filter, sink), || solver.solve_closed_goal(goal).unwrap())
+        });
+        let events = events.borrow().clone();
+        events
+    };
+
+    let all = events(Filter::default());
+    assert!(all.iter().any(|e| e.level == Level::Trace));
+    assert_eq!(all[0].level, Level::Info);
+    assert_eq!(all[0].depth, 0);
+
+    let solver = events(Filter::parse("debug,solver").unwrap());
+    assert!(!solver.is_empty());
+    assert!(solver.iter().all(|e| e.level <= Level::Debug && e.subsystem == Subsystem::Solver));
+
+    let matched = events(Filter::parse("match=Vec<Foo>: Clone").unwrap());
+    assert!(matched.len() < all.len());
+    assert!(matched[0].heading && matched[0].message.contains("Vec<Foo>: Clone"));
+    assert!(matched.iter().all(|e| e.depth >= matched[0].depth));
+    assert!(matched.iter().any(|e| e.message.contains("Foo: Clone") && !e.message.contains("Vec<Foo>")));
+}
+
+#[test]
+fn reentrant_trace_sink() {
+    // A sink which emits events of its own, and panics once.
+    let events = Rc::new(RefCell::new(0));
+    let sink = {
+        let events = events.clone();
+        move |event: &Event| {
+            *events.borrow_mut() += 1;
+            trace::event(Level::Info, module_path!(), format!("seen: {}", event.message));
+            if event.message == "panic" {
+                panic!("sink panicked");
+            }
+        }
+    };
+
+    let enabled = trace::enabled();
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+        with_tracer(Tracer::new(Filter::default(), sink), || {
+            trace::event(Level::Info, module_path!(), "first".to_string());
+            trace::event(Level::Info, module_path!(), "second".to_string());
+            trace::event(Level::Info, module_path!(), "panic".to_string());
+        })
+    }));
+    assert!(result.is_err());
+    assert_eq!(*events.borrow(), 3);
+    assert_eq!(trace::enabled(), enabled);
+}
+
+#[test]
+fn parse_trace_filter() {
+    assert_eq!(Filter::parse("1").unwrap(), Filter::default());
+    assert_eq!(Filter::parse("info, unify,slg,match=Foo<A, B>").unwrap(), Filter {
+        level: Level::Info,
+        subsystems: vec![Subsystem::Unify, Subsystem::Slg],
+        pattern: Some("Foo<A, B>".to_string()),
+    });
+    assert_eq!(Filter::parse("solver,verbose").unwrap_err().to_string(), "unknown trace filter item `verbose`");
+}
+
+/// A database which hands out the clauses of a program one goal at a time,
+/// recording the goals it was asked about.
+struct RecordingDatabase {
+    env: ir::ProgramEnvironment,
+    requests: RefCell<Vec<ir::DomainGoal>>,
+}
+
+impl ir::ProgramDatabase for RecordingDatabase {
+    fn program_clauses_for(&self, goal: &ir::DomainGoal) -> Vec<ir::ProgramClause> {
+        self.requests.borrow_mut().push(goal.clone());
+        self.env.program_clauses_for(goal)
+    }
+
+    fn trait_datum(&self, id: ir::ItemId) -> &ir::TraitDatum {
+        self.env.trait_datum(id)
+    }
+
+    fn associated_ty_datum(&self, id: ir::ItemId) -> &ir::AssociatedTyDatum {
+        self.env.associated_ty_datum(id)
+    }
+
+    fn struct_datum(&self, id: ir::ItemId) -> Option<&ir::StructDatum> {
+        self.env.struct_datum(id)
+    }
+}
+
+#[test]
+fn program_database() {
+    let fixture = Fixture::clone_program();
+    let database = Arc::new(RecordingDatabase {
+        env: fixture.program.environment(),
+        requests: RefCell::new(vec![]),
+    });
+    let mut solver = Solver::new(&database, SolverConfig::default());
+    let domain_goal = |text| match fixture.goal(text).goal {
+        ir::Goal::Leaf(ir::LeafGoal::DomainGoal(goal)) => goal,
+        _ => panic!("not a domain goal: {}", text),
+    };
+
+    let solution = solver.solve_closed_goal(fixture.goal("Vec<Vec<Foo>>: Clone")).unwrap();
+    assert!(solution.is_unique());
+
+    // The clauses were requested for each of the goals in turn.
+    let requests = database.requests.borrow();
+    for text in &["Vec<Vec<Foo>>: Clone", "Vec<Foo>: Clone", "Foo: Clone"] {
+        assert!(requests.contains(&domain_goal(text)));
+    }
+}
+
+#[test]
+fn proof_tree() {
+    let fixture = Fixture::clone_program();
+    let mut solver = fixture.solver();
+    let goal = fixture.goal("exists<T> { Vec<T>: Clone, T = Foo }");
+
+    let (solution, proofs) = solver.solve_closed_goal_with_proof(goal).unwrap();
+    let rendered = fixture.render(&solution) + "\n" + &fixture.render_all(&proofs);
+    assert_eq!(rendered, "\
+Unique; substitution [?0 := Foo], lifetime constraints []
+(?0 = Foo) with [?0 := Foo]
+  by unification
+Vec<Foo>: Clone
+  by program clause `forall<type> Vec<?0>: Clone :- ?0: Clone`
+    Foo: Clone
+      by program clause `Foo: Clone`
+");
+}
+
+#[test]
+fn proof_tree_combined() {
+    let fixture = Fixture::new("
+        struct Foo { }
+        struct Bar { }
+        struct Vec<T> { }
+        trait Clone { }
+        impl Clone for Vec<Foo> { }
+        impl Clone for Vec<Bar> { }
+    ");
+    let mut solver = fixture.solver();
+
+    let (_, proofs) = solver.solve_closed_goal_with_proof(fixture.goal("exists<T> { Vec<T>: Clone }")).unwrap();
+    assert_eq!(fixture.render_all(&proofs), "\
+Vec<?0>: Clone
+  by combining the results of
+    by program clause `Vec<Bar>: Clone`
+    by program clause `Vec<Foo>: Clone`
+");
+}
+
+#[test]
+fn proofs_unsupported_with_slg() {
+    let fixture = Fixture::clone_program();
+    let mut solver = fixture.solver_with(SolverConfig { engine: Engine::Slg, ..SolverConfig::default() });
+    let goal = fixture.goal("Foo: Clone");
+
+    let error = solver.solve_closed_goal_with_proof(goal.clone()).unwrap_err();
+    assert_eq!(error.to_string(), "proofs are unsupported with the SLG engine");
+    let error = solver.explain_failure(goal, 5).unwrap_err();
+    assert_eq!(error.to_string(), "explanations of failures are unsupported with the SLG engine");
+}
+
+#[test]
+fn explain_failure() {
+    let fixture = Fixture::new("
+        struct Foo { }
+        struct Bar { }
+        struct Vec<T> { }
+        trait Clone { }
+        impl<T> Clone for Vec<T> where T: Clone { }
+        impl Clone for Bar { }
+        trait Copy { }
+        impl Copy for Vec<Bar> { }
+    ");
+    let mut solver = fixture.solver();
+    let mut explain = |text: &str, depth: usize| {
+        solver.explain_failure(fixture.goal(text), depth).unwrap().map(|f| fixture.render(&f))
+    };
+
+    assert_eq!(explain("Vec<Foo>: Clone", 5).unwrap(), "\
+`Vec<Foo>: Clone` does not hold
+  program clause `forall<type> ?0: Clone :- FromEnv(?0 as Clone)` does not apply
+    `FromEnv(Vec<Foo> as Clone)` does not hold
+      no clause can prove it
+  program clause `forall<type> Vec<?0>: Clone :- ?0: Clone` does not apply
+    `Foo: Clone` does not hold
+      program clause `forall<type> ?0: Clone :- FromEnv(?0 as Clone)` does not apply
+        `FromEnv(Foo as Clone)` does not hold
+          no clause can prove it
+");
+
+    assert_eq!(explain("Vec<Foo>: Copy", 5).unwrap(), "\
+`Vec<Foo>: Copy` does not hold
+  program clause `forall<type> ?0: Copy :- FromEnv(?0 as Copy)` does not apply
+    `FromEnv(Vec<Foo> as Copy)` does not hold
+      no clause can prove it
+  program clause `Vec<Bar>: Copy` does not apply
+    cannot equate `Foo` and `Bar`
+");
+
+    // Deeper failures are pruned.
+    assert_eq!(explain("Vec<Vec<Foo>>: Clone", 1).unwrap(), "\
+`Vec<Vec<Foo>>: Clone` does not hold
+  program clause `forall<type> ?0: Clone :- FromEnv(?0 as Clone)` does not apply
+    `FromEnv(Vec<Vec<Foo>> as Clone)` does not hold
+      ...
+  program clause `forall<type> Vec<?0>: Clone :- ?0: Clone` does not apply
+    `Vec<Foo>: Clone` does not hold
+      ...
+");
+
+    assert_eq!(explain("exists<T> { Vec<T> = Foo }", 5).unwrap(), "\
+`(Vec<?0> = Foo)` does not hold
+  cannot equate `Vec<?0>` and `Foo`
+");
+
+    assert_eq!(explain("dyn Copy = Foo", 5).unwrap(), "\
+`(dyn Copy = Foo)` does not hold
+  cannot equate `dyn Copy` and `Foo`
+");
+
+    assert_eq!(explain("not { Vec<Bar>: Clone }", 5).unwrap(), "\
+`not { Vec<Bar>: Clone }` does not hold
+  the negated goal can be proven
+");
+
+    assert_eq!(explain("Vec<Bar>: Clone", 5), None);
+}
+
+#[test]
+fn explain_cached_failure() {
+    let fixture = Fixture::clone_program();
+    let mut solver = fixture.solver();
+
+    // Failures cached while no explanation was being built are not explained.
+    assert!(solver.solve_closed_goal(fixture.goal("Bar: Clone")).is_err());
+    let failure = solver.explain_failure(fixture.goal("Bar: Clone"), 5).unwrap().unwrap();
+    assert_eq!(fixture.render(&failure), "\
+`Bar: Clone` does not hold
+  the failure was cached without an explanation
+");
+}
+
+#[test]
+fn proof_of_cached_answer() {
+    let fixture = Fixture::clone_program();
+    let mut solver = fixture.solver();
+
+    solver.solve_closed_goal(fixture.goal("Foo: Clone")).unwrap();
+    let (_, proofs) = solver.solve_closed_goal_with_proof(fixture.goal("Foo: Clone")).unwrap();
+    assert_eq!(fixture.render_all(&proofs), "\
+Foo: Clone
+  by a cached answer
+");
+}
+
+#[test]
+fn enumerate_answers() {
+    let fixture = Fixture::new("
+        struct Foo { }
+        struct Bar { }
+        struct Vec<T> { }
+        trait Clone { }
+        impl<T> Clone for Vec<T> where T: Clone { }
+        impl Clone for Foo { }
+        impl Clone for Bar { }
+        trait Map<T> { }
+        impl Map<Bar> for Foo { }
+        impl Map<Foo> for Bar { }
+    ");
+    let mut solver = fixture.solver();
+    let mut answers = |text: &str, limit: usize| solver.answers(fixture.goal(text)).take_answers(limit);
+
+    assert_eq!(fixture.render(&answers("exists<A, B> { A: Map<B> }", 10)), "\
+substitution [?0 := Foo, ?1 := Bar], lifetime constraints []
+substitution [?0 := Bar, ?1 := Foo], lifetime constraints []
+2 answers; there may be others");
+
+    assert_eq!(fixture.render(&answers("exists<A, B> { A: Map<B>, not { A: Map<Foo> } }", 10)), "\
+substitution [?0 := Foo, ?1 := Bar], lifetime constraints []
+1 answer; there may be others");
+
+    assert_eq!(fixture.render(&answers("exists<T> { T: Clone }", 3)), "\
+substitution [?0 := Foo], lifetime constraints []
+substitution [?0 := Bar], lifetime constraints []
+substitution [?0 := Vec<Foo>], lifetime constraints []
+first 3 answers; there are more");
+
+    // The search gives up at the overflow depth.
+    let infinite = answers("exists<T> { T: Clone }", 100);
+    assert_eq!(infinite.status, AnswerSetStatus::Infinite);
+    assert_eq!(infinite.answers.len(), 20);
+}
+
 #[test]
 fn prove_infer() {
     test! {
@@ -107,10 +544,13 @@ fn prove_infer() {
             "Ambiguous; no inference guidance"
         }
 
+        // `dyn Map<Bar>` implements `Map<Bar>` as well.
         goal {
             exists<A> { A: Map<Bar> }
         } yields {
-            "Unique; substitution [?0 := Foo], lifetime constraints []"
+            "Ambiguous; suggested substitution [?0 := Foo]"
+        } yields[SLG] {
+            "Ambiguous; no inference guidance"
         }
 
         goal {
@@ -183,8 +623,8 @@ fn prove_forall() {
             "Unique; substitution [], lifetime constraints []"
         }
 
-        // This fails because we used `if_raw`, and hence we do not
-        // know that `WF(T: Clone)` holds.
+        // The impl only requires `T: Clone` itself, so this holds even
+        // though `if_raw` does not elaborate the assumption.
         goal {
             forall<T> {
                 if_raw (T: Clone) {
@@ -192,7 +632,19 @@ fn prove_forall() {
                 }
             }
         } yields {
-            "CannotProve"
+            "Unique; substitution [], lifetime constraints []"
+        }
+
+        // But we do not know that `FromEnv(T: Clone)` holds, and hence
+        // cannot rely on the implied bounds of `T: Clone`.
+        goal {
+            forall<T> {
+                if_raw (T: Clone) {
+                    FromEnv(T: Clone)
+                }
+            }
+        } yields {
+            "No possible solution"
         }
     }
 }
@@ -248,13 +700,13 @@ fn cycle_no_solution() {
             impl<T> Foo for S<T> where T: Foo { }
         }
 
-        // only solution: infinite type S<S<S<...
+        // only solutions: infinite type S<S<S<..., and those built on `dyn Foo`
         goal {
             exists<T> {
                 T: Foo
             }
         } yields {
-            "No possible solution: no applicable candidates"
+            "Ambiguous; no inference guidance"
         }
     }
 }
@@ -293,12 +745,15 @@ fn cycle_unique_solution() {
             impl Foo for i32 { }
         }
 
+        // `dyn Foo` is a solution as well.
         goal {
             exists<T> {
                 T: Foo
             }
         } yields {
-            "Unique; substitution [?0 := i32]"
+            "Ambiguous; suggested substitution [?0 := i32]"
+        } yields[SLG] {
+            "Ambiguous; no inference guidance"
         }
     }
 }
@@ -320,133 +775,604 @@ fn multiple_ambiguous_cycles() {
             impl<T> Sized for Vec<T> where T: WF, T: Sized { }
         }
 
-        //          ?T: WF
-        //             |
-        //             |
-        //             |
-        // Int: WF. <-----> (Vec<?T>: WF) :- (?T: Sized)
-        //                              |
-        //                              |
-        //                              |
-        //              Int: Sized. <-------> (Vec<?T>: Sized) :- (?T: Sized), (?T: WF)
-        //                                                            |            |
-        //                                                            |            |
-        //                                                            |            |
-        //                                                          cycle        cycle
-        //
-        // Depending on the evaluation order of the above tree (which cycle we come upon first),
-        // we may fail to reach a fixed point if we loop continuously because `Ambig` does not perform
-        // any unification. We must stop looping as soon as we encounter `Ambig`. In fact without
-        // this strategy, the above program will not even be loaded because of the overlap check which
-        // will loop forever.
+        //          ?T: WF
+        //             |
+        //             |
+        //             |
+        // Int: WF. <-----> (Vec<?T>: WF) :- (?T: Sized)
+        //                              |
+        //                              |
+        //                              |
+        //              Int: Sized. <-------> (Vec<?T>: Sized) :- (?T: Sized), (?T: WF)
+        //                                                            |            |
+        //                                                            |            |
+        //                                                            |            |
+        //                                                          cycle        cycle
+        //
+        // Depending on the evaluation order of the above tree (which cycle we come upon first),
+        // we may fail to reach a fixed point if we loop continuously because `Ambig` does not perform
+        // any unification. We must stop looping as soon as we encounter `Ambig`. In fact without
+        // this strategy, the above program will not even be loaded because of the overlap check which
+        // will loop forever.
+        goal {
+            exists<T> {
+                T: WF
+            }
+        } yields {
+            "Ambig"
+        }
+    }
+}
+
+#[test]
+fn overflow() {
+    test! {
+        program {
+            trait Q { }
+            struct Z { }
+            struct G<X> { }
+            struct S<X> { }
+
+            impl Q for Z { }
+            impl<X> Q for G<X> where X: Q { }
+            impl<X> Q for S<X> where X: Q, S<G<X>>: Q { }
+        }
+
+        // Will try to prove S<G<Z>>: Q then S<G<G<Z>>>: Q etc ad infinitum.
+        // The SLG engine gives up on goals that are too large with an
+        // ambiguous answer instead.
+        goal {
+            S<Z>: Q
+        } yields {
+            "No possible solution: overflow depth reached"
+        } yields[SLG] {
+            "Ambiguous; no inference guidance"
+        }
+
+        goal {
+            G<G<Z>>: Q
+        } yields {
+            "Unique"
+        }
+    }
+}
+
+#[test]
+fn solve_after_overflow() {
+    let fixture = Fixture::new("
+        trait Q { }
+        struct Z { }
+        struct G<X> { }
+        struct S<X> { }
+
+        impl Q for Z { }
+        impl<X> Q for G<X> where X: Q { }
+        impl<X> Q for S<X> where X: Q, S<G<X>>: Q { }
+    ");
+    let mut solver = fixture.solver();
+    let error = solver.solve_closed_goal(fixture.goal("S<Z>: Q")).unwrap_err();
+    assert!(error.is_overflow());
+
+    // The same solver can still be used after an overflow.
+    let solution = solver.solve_closed_goal(fixture.goal("G<G<Z>>: Q")).unwrap();
+    assert!(solution.is_unique());
+}
+
+#[test]
+fn overflow_in_one_clause() {
+    test! {
+        program {
+            trait Q<T> { }
+            struct Z { }
+            struct S<X> { }
+
+            impl Q<Z> for Z { }
+            impl<X> Q<S<X>> for Z where Z: Q<S<S<X>>> { }
+        }
+
+        // The second impl overflows, which does not discard the solution
+        // given by the first one, but makes it ambiguous.
+        goal {
+            exists<T> { Z: Q<T> }
+        } yields {
+            "Ambiguous; no inference guidance"
+        }
+
+        goal {
+            Z: Q<Z>
+        } yields {
+            "Unique"
+        }
+    }
+}
+
+#[test]
+fn normalize_basic() {
+    test! {
+        program {
+            trait Iterator { type Item; }
+            struct Vec<T> { }
+            struct u32 { }
+            impl<T> Iterator for Vec<T> {
+                type Item = T;
+            }
+        }
+
+        goal {
+            forall<T> {
+                exists<U> {
+                    Vec<T>: Iterator<Item = U>
+                }
+            }
+        } yields {
+            "Unique; substitution [?0 := !1], lifetime constraints []"
+        }
+
+        goal {
+            forall<T> {
+                Vec<T>: Iterator<Item = T>
+            }
+        } yields {
+            "Unique; substitution [], lifetime constraints []"
+        }
+
+        goal {
+            forall<T> {
+                if (T: Iterator<Item = u32>) {
+                    exists<U> {
+                        T: Iterator<Item = U>
+                    }
+                }
+            }
+        } yields {
+            "Unique; substitution [?0 := u32]"
+        }
+
+        goal {
+            forall<T> {
+                if (T: Iterator) {
+                    exists<U> {
+                        T: Iterator<Item = U>
+                    }
+                }
+            }
+        } yields {
+            "Unique; substitution [?0 := (Iterator::Item)<!1>]"
+        }
+
+        goal {
+            forall<T> {
+                if (T: Iterator) {
+                    <T as Iterator>::Item = <T as Iterator>::Item
+                }
+            }
+        } yields {
+            "Unique"
+        }
+
+        // `U` may be `T`, `Vec<<T as Iterator>::Item>` or a trait object.
+        goal {
+            forall<T> {
+                if (T: Iterator) {
+                    exists<U> {
+                        <T as Iterator>::Item = <U as Iterator>::Item
+                    }
+                }
+            }
+        } yields {
+            "Ambiguous"
+        }
+    }
+}
+
+#[test]
+fn solution_json() {
+    let fixture = Fixture::new("
+        trait Foo { }
+        struct Unit { }
+        struct Ref<'a, T> { }
+        impl<'a, T> Foo for Ref<'a, T> where T: 'a { }
+    ");
+    let mut solver = fixture.solver();
+    let mut solve = |text| solver.solve_closed_goal(fixture.goal(text)).to_json(&fixture.program).to_string();
+
+    assert_eq!(
+        solve("forall<'a> { Ref<'a, Unit>: Foo }"),
+        r#"{"kind":"unique","binders":[],"substitution":{},"constraints":[{"kind":"outlives","parameter":"Unit","lifetime":"'!1","environment":[]}]}"#);
+    assert_eq!(
+        solve("exists<'a, T> { T = Ref<'a, Unit> }"),
+        r#"{"kind":"unique","binders":[{"kind":"lifetime","universe":0}],"substitution":{"?0":"Ref<'?0, Unit>","'?0":"'?0"},"constraints":[]}"#);
+    assert_eq!(
+        solve("exists<'a, T> { Ref<'a, T>: Foo }"),
+        r#"{"kind":"unique","binders":[{"kind":"type","universe":0},{"kind":"lifetime","universe":0}],"substitution":{"?0":"?0","'?0":"'?1"},"constraints":[{"kind":"outlives","parameter":"?0","lifetime":"'?1","environment":[]}]}"#);
+    assert_eq!(
+        solve("Unit: Foo"),
+        r#"{"kind":"no_solution","error":"no applicable candidates"}"#);
+}
+
+#[test]
+fn region_outlives() {
+    test! {
+        program {
+            trait Foo { }
+
+            struct Unit { }
+            struct Ref<'a, T> { }
+
+            impl<'a, T> Foo for Ref<'a, T> where T: 'a { }
+        }
+
+        goal {
+            forall<'a> {
+                Ref<'a, Unit>: Foo
+            }
+        } yields {
+            "Unique; substitution [],
+                     lifetime constraints [
+                       (Env(U1, []) |- Outlives(Unit, '!1))
+                     ]"
+        }
+
+        goal {
+            forall<'a, 'b> {
+                'a: 'b
+            }
+        } yields {
+            "Unique; substitution [],
+                     lifetime constraints [
+                       (Env(U2, []) |- Outlives('!1, '!2))
+                     ]"
+        }
+
+        goal {
+            forall<'a> {
+                if ('a: 'a) {
+                    Ref<'a, Unit>: Foo
+                }
+            }
+        } yields {
+            "Unique; substitution [],
+                     lifetime constraints [
+                       (Env(U1, ['!1: '!1]) |- Outlives(Unit, '!1))
+                     ]"
+        }
+    }
+}
+
+#[test]
+fn builtin_types() {
+    test! {
+        program {
+            trait Clone { }
+
+            struct i32 { }
+
+            impl Clone for i32 { }
+            impl<'a, T> Clone for &'a T { }
+            impl<A, B> Clone for (A, B) where A: Clone, B: Clone { }
+        }
+
+        goal {
+            forall<'a> {
+                (&'a i32, i32): Clone
+            }
+        } yields {
+            "Unique; substitution [], lifetime constraints []"
+        }
+
+        goal {
+            forall<'a> {
+                (&'a mut i32, i32): Clone
+            }
+        } yields {
+            "No possible solution"
+        }
+
+        goal {
+            exists<T> {
+                fn(T) -> () = fn(i32) -> ()
+            }
+        } yields {
+            "Unique; substitution [?0 := i32]"
+        }
+
+        goal {
+            exists<T> {
+                [T] = (T,)
+            }
+        } yields {
+            "No possible solution"
+        }
+
+        goal {
+            forall<'a> {
+                WellFormed(&'a (i32, [i32]))
+            }
+        } yields {
+            "Unique; substitution [],
+                     lifetime constraints [
+                       (Env(U1, []) |- Outlives((i32, [i32]), '!1))
+                     ]"
+        }
+
+        goal {
+            forall<'a, T> {
+                if (WellFormed(T)) {
+                    WellFormed(&'a mut T)
+                }
+            }
+        } yields {
+            "Unique; substitution [],
+                     lifetime constraints [
+                       (Env(U2, [WellFormed(!2)]) |- Outlives(!2, '!1))
+                     ]"
+        }
+
+        // Tuples and function pointers of any arity are WF, not only the
+        // types whose clauses can be listed.
+        goal {
+            exists<T> {
+                WellFormed(T)
+            }
+        } yields {
+            "Ambiguous; no inference guidance"
+        }
+
+        goal {
+            exists<T> {
+                WellFormed((T, i32)), T = i32
+            }
+        } yields {
+            "Unique; substitution [?0 := i32], lifetime constraints []"
+        }
+    }
+}
+
+#[test]
+fn dyn_trait() {
+    test! {
+        program {
+            trait Foo where Self: Bar { }
+            trait Bar { }
+            #[auto] trait Send { }
+            trait Iterator { type Item; }
+
+            struct u32 { }
+        }
+
+        goal {
+            dyn Foo: Foo
+        } yields {
+            "Unique; substitution []"
+        }
+
+        goal {
+            dyn Foo: Bar
+        } yields {
+            "Unique; substitution []"
+        }
+
+        goal {
+            dyn Foo: Send
+        } yields {
+            "No possible solution"
+        }
+
+        goal {
+            dyn Foo + Send: Send
+        } yields {
+            "Unique; substitution []"
+        }
+
+        goal {
+            exists<T> {
+                dyn Iterator<Item = u32>: Iterator<Item = T>
+            }
+        } yields {
+            "Unique; substitution [?0 := u32]"
+        }
+
+        goal {
+            dyn Foo + Send = dyn Send + Foo
+        } yields {
+            "Unique; substitution []"
+        }
+
+        goal {
+            dyn Foo = dyn Bar
+        } yields {
+            "No possible solution"
+        }
+
+        goal {
+            forall<'a> {
+                WellFormed(dyn Foo + 'a)
+            }
+        } yields {
+            "Unique; substitution []"
+        }
+    }
+}
+
+#[test]
+fn dyn_trait_candidates() {
+    test! {
+        program {
+            trait Foo { }
+            trait Sub where Self: Foo { }
+            trait Generic { type Assoc<T>; }
+            struct A { }
+            impl Foo for A { }
+            impl Generic for A { type Assoc<T> = A; }
+        }
+
+        // `dyn Foo` and `dyn Sub` implement `Foo` as well as `A`.
+        goal {
+            exists<T> {
+                T: Foo
+            }
+        } yields {
+            "Ambiguous; suggested substitution [?0 := A]"
+        } yields[SLG] {
+            "Ambiguous; no inference guidance"
+        }
+
+        goal {
+            dyn Foo: Foo
+        } yields {
+            "Unique; substitution []"
+        }
+
+        goal {
+            exists<T> {
+                T: Sub
+            }
+        } yields {
+            "Ambiguous; no inference guidance"
+        }
+
+        // `Generic` cannot be made into an object.
         goal {
             exists<T> {
-                T: WF
+                T: Generic
             }
         } yields {
-            "Ambig"
+            "Unique; substitution [?0 := A]"
         }
     }
 }
 
 #[test]
-#[should_panic]
-fn overflow() {
+fn supertraits_and_assoc_ty_bounds() {
     test! {
         program {
-            trait Q { }
-            struct Z { }
-            struct G<X>
-            struct S<X>
+            trait Clone { }
+            trait Copy: Clone { }
+            trait Iterator { type Item; }
+            trait IntoIterator {
+                type Item;
+                type IntoIter: Iterator<Item = <Self as IntoIterator>::Item>;
+            }
+        }
 
-            impl Q for Z { }
-            impl<X> Q for G<X> where X: Q { }
-            impl<X> Q for S<X> where X: Q, S<G<X>>: Q { }
+        goal {
+            forall<T> {
+                if (T: Copy) {
+                    T: Clone
+                }
+            }
+        } yields {
+            "Unique; substitution []"
         }
 
-        // Will try to prove S<G<Z>>: Q then S<G<G<Z>>>: Q etc ad infinitum
         goal {
-            S<Z>: Q
+            forall<T> {
+                if (T: Clone) {
+                    T: Copy
+                }
+            }
+        } yields {
+            "No possible solution"
+        }
+
+        goal {
+            forall<T> {
+                if (T: IntoIterator) {
+                    <T as IntoIterator>::IntoIter: Iterator
+                }
+            }
+        } yields {
+            "Unique; substitution []"
+        }
+
+        goal {
+            forall<T> {
+                if (T: IntoIterator) {
+                    exists<U> {
+                        <T as IntoIterator>::IntoIter: Iterator<Item = U>
+                    }
+                }
+            }
         } yields {
-            ""
+            "Unique; substitution [?0 := (IntoIterator::Item)<!1>]"
         }
     }
 }
 
 #[test]
-fn normalize_basic() {
+fn implied_bounds() {
     test! {
         program {
-            trait Iterator { type Item; }
-            struct Vec<T> { }
-            struct u32 { }
-            impl<T> Iterator for Vec<T> {
-                type Item = T;
-            }
+            trait Eq { }
+            trait Ord where Self: Eq { }
+            trait Hash { }
+            struct Set<T> where T: Hash { }
         }
 
         goal {
             forall<T> {
-                exists<U> {
-                    Vec<T>: Iterator<Item = U>
+                if (FromEnv(T: Ord)) {
+                    T: Eq
                 }
             }
         } yields {
-            "Unique; substitution [?0 := !1], lifetime constraints []"
+            "Unique; substitution [], lifetime constraints []"
         }
 
         goal {
             forall<T> {
-                Vec<T>: Iterator<Item = T>
+                if (FromEnv(Set<T>)) {
+                    T: Hash
+                }
             }
         } yields {
             "Unique; substitution [], lifetime constraints []"
         }
 
+        // `if` assumes `FromEnv(T: Ord)` along with `T: Ord`.
         goal {
             forall<T> {
-                if (T: Iterator<Item = u32>) {
-                    exists<U> {
-                        T: Iterator<Item = U>
-                    }
+                if (T: Ord) {
+                    T: Eq
                 }
             }
         } yields {
-            "Unique; substitution [?0 := u32]"
+            "Unique; substitution [], lifetime constraints []"
         }
 
+        // Well-formedness is not an assumption: `WellFormed(T: Ord)` is
+        // what must be proven of `T: Ord`, not what follows from it.
         goal {
             forall<T> {
-                if (T: Iterator) {
-                    exists<U> {
-                        T: Iterator<Item = U>
-                    }
+                if (WellFormed(T: Ord)) {
+                    T: Eq
                 }
             }
         } yields {
-            "Unique; substitution [?0 := (Iterator::Item)<!1>]"
+            "No possible solution"
         }
 
+        // Proving that `T: Ord` is well-formed requires `T: Eq`, which
+        // follows from `FromEnv(T: Ord)` but not from the goal itself, so
+        // it cannot be proven when `if_raw` does not assume the former.
         goal {
             forall<T> {
-                if (T: Iterator) {
-                    <T as Iterator>::Item = <T as Iterator>::Item
+                if_raw (T: Ord, WellFormed(T)) {
+                    WellFormed(T: Ord)
                 }
             }
         } yields {
-            "Unique"
+            "No possible solution"
         }
 
         goal {
             forall<T> {
-                if (T: Iterator) {
-                    exists<U> {
-                        <T as Iterator>::Item = <U as Iterator>::Item
-                    }
+                if (T: Ord, WellFormed(T)) {
+                    WellFormed(T: Ord)
                 }
             }
         } yields {
-            "Unique"
+            "Unique; substitution [], lifetime constraints []"
         }
     }
 }
@@ -464,12 +1390,131 @@ fn normalize_rev_infer() {
             impl Identity for i32 { type Item = i32; }
         }
 
+        // `dyn Identity<Item = u32>` is a solution as well.
         goal {
             exists<T> {
                 T: Identity<Item = u32>
             }
         } yields {
-            "Unique; substitution [?0 := u32]"
+            "Ambiguous; suggested substitution [?0 := u32]"
+        } yields[SLG] {
+            "Ambiguous; no inference guidance"
+        }
+    }
+}
+
+/// Demonstrates that normalization picks the value from the most
+/// specialized impl which applies.
+#[test]
+fn normalize_specialized() {
+    test! {
+        program {
+            trait Foo { type Assoc; }
+            struct A { }
+            struct B { }
+            struct i32 { }
+            struct u32 { }
+            struct Vec<T> { }
+            impl<T> Foo for T { default type Assoc = A; }
+            impl Foo for i32 { type Assoc = B; }
+            impl<T> Foo for Vec<T> { type Assoc = Vec<T>; }
+        }
+
+        goal {
+            exists<U> {
+                i32: Foo<Assoc = U>
+            }
+        } yields {
+            "Unique; substitution [?0 := B]"
+        }
+
+        goal {
+            exists<U> {
+                u32: Foo<Assoc = U>
+            }
+        } yields {
+            "Unique; substitution [?0 := A]"
+        }
+
+        goal {
+            forall<T> {
+                exists<U> {
+                    Vec<T>: Foo<Assoc = U>
+                }
+            }
+        } yields {
+            "Unique; substitution [?0 := Vec<!1>]"
+        }
+
+        // We cannot tell whether `T` is `i32`, so the projection stays opaque.
+        goal {
+            forall<T> {
+                exists<U> {
+                    T: Foo<Assoc = U>
+                }
+            }
+        } yields {
+            "Unique; substitution [?0 := (Foo::Assoc)<!1>]"
+        }
+
+        goal {
+            exists<T, U> {
+                T: Foo<Assoc = U>
+            }
+        } yields {
+            "Ambiguous"
+        }
+    }
+}
+
+/// A `default impl` does not implement its trait by itself: it makes all of
+/// its values overridable, and a value that is not overridden is inherited by
+/// the more specialized impls.
+#[test]
+fn normalize_default_impl_chain() {
+    test! {
+        program {
+            trait Foo { type Assoc; type Other; }
+            struct A { }
+            struct B { }
+            struct C { }
+            struct i32 { }
+            struct Vec<T> { }
+            default impl<T> Foo for T { type Assoc = A; type Other = A; }
+            impl<T> Foo for Vec<T> { default type Assoc = B; type Other = B; }
+            impl Foo for Vec<i32> { type Assoc = C; }
+        }
+
+        goal {
+            exists<U> {
+                i32: Foo<Assoc = U>
+            }
+        } yields {
+            "No possible solution"
+        }
+
+        goal {
+            exists<U> {
+                Vec<A>: Foo<Assoc = U>
+            }
+        } yields {
+            "Unique; substitution [?0 := B]"
+        }
+
+        goal {
+            exists<U> {
+                Vec<i32>: Foo<Assoc = U>
+            }
+        } yields {
+            "Unique; substitution [?0 := C]"
+        }
+
+        goal {
+            exists<U> {
+                Vec<i32>: Foo<Other = U>
+            }
+        } yields {
+            "Unique; substitution [?0 := B]"
         }
     }
 }
@@ -772,7 +1817,7 @@ fn generic_trait() {
 #[test]
 fn trait_wf() {
     test! {
-        program {
+        ill_formed program {
             struct Vec<T> where T: Sized { }
             struct Slice<T> where T: Sized { }
             struct Int { }
@@ -1058,14 +2103,21 @@ fn deep_failure() {
             impl<T> Bar for Foo<T> where T: Baz {}
         }
 
+        // Only `dyn Baz` implements `Baz`.
         goal {
             exists<T> { T: Baz }
         } yields {
-            "No possible solution"
+            "Ambiguous; no inference guidance"
         }
 
         goal {
             exists<T> { Foo<T>: Bar }
+        } yields {
+            "Ambiguous; no inference guidance"
+        }
+
+        goal {
+            forall<T> { Foo<Foo<T>>: Bar }
         } yields {
             "No possible solution"
         }
@@ -1087,10 +2139,13 @@ fn deep_success() {
             impl<T> Bar for Foo<T> where T: Baz {}
         }
 
+        // `dyn Baz` implements `Baz` as well.
         goal {
             exists<T> { Foo<T>: Bar }
         } yields {
-            "Unique; substitution [?0 := ImplsBaz]"
+            "Ambiguous; suggested substitution [?0 := ImplsBaz]"
+        } yields[SLG] {
+            "Ambiguous; no inference guidance"
         }
     }
 }
@@ -1112,12 +2167,17 @@ fn definite_guidance() {
             impl<T> Debug for Foo<T> where T: Display {}
         }
 
+        // `dyn Debug` implements `Debug` as well, so the guidance is only a
+        // suggestion. The SLG engine does not merge distinct answers into
+        // guidance.
         goal {
             exists<T> {
                 T: Debug
             }
         } yields {
-            "Ambiguous; definite substitution [?0 := Foo<?0>]"
+            "Ambiguous; suggested substitution [?0 := Foo<?0>]"
+        } yields[SLG] {
+            "Ambiguous; no inference guidance"
         }
     }
 }
@@ -1174,6 +2234,8 @@ fn suggested_subst() {
             "Unique; substitution [?0 := i32]"
         }
 
+        // The SLG engine gives no preference to the where clauses of the
+        // environment.
         goal {
             exists<T> {
                 if (Foo: SomeTrait<bool>) {
@@ -1182,6 +2244,8 @@ fn suggested_subst() {
             }
         } yields {
             "Ambiguous; suggested substitution [?0 := bool]"
+        } yields[SLG] {
+            "Ambiguous; no inference guidance"
         }
 
         goal {
@@ -1212,6 +2276,8 @@ fn suggested_subst() {
             }
         } yields {
             "Ambiguous; suggested substitution [?0 := bool]"
+        } yields[SLG] {
+            "Ambiguous; no inference guidance"
         }
 
         goal {
@@ -1276,12 +2342,13 @@ fn simple_negation() {
             "Unique"
         }
 
+        // `dyn Foo` implements `Foo`.
         goal {
             not {
                 exists<T> { T: Foo }
             }
         } yields {
-            "Unique"
+            "Ambig"
         }
     }
 }
@@ -1297,18 +2364,27 @@ fn deep_negation() {
             impl<T> Bar for Foo<T> where T: Baz {}
         }
 
+        // Only `dyn Baz` implements `Baz`.
         goal {
             not {
                 exists<T> { T: Baz }
             }
         } yields {
-            "Unique"
+            "Ambiguous; no inference guidance"
         }
 
         goal {
             not {
                 exists<T> { Foo<T>: Bar }
             }
+        } yields {
+            "Ambiguous; no inference guidance"
+        }
+
+        goal {
+            not {
+                forall<T> { Foo<Foo<T>>: Bar }
+            }
         } yields {
             "Unique"
         }
@@ -1578,13 +2654,14 @@ fn mixed_semantics() {
         }
 
         // We have a cycle `(T: Send) :- (T: Foo) :- (T: Send)` with a non-coinductive
-        // inner component `T: Foo` so we reject it.
+        // inner component `T: Foo` so we reject it. Trait objects may implement
+        // both traits though.
         goal {
             exists<T> {
                 T: Send
             }
         } yields {
-            "No possible solution"
+            "Ambiguous; no inference guidance"
         }
 
         goal {
@@ -1592,7 +2669,97 @@ fn mixed_semantics() {
                 T: Foo
             }
         } yields {
-            "No possible solution"
+            "Ambiguous; no inference guidance"
         }
     }
 }
+
+#[test]
+fn test_file() {
+    let text = "
+        // A comment before the program.
+        program {
+            struct Foo { }
+            struct Bar { }
+            trait Clone { } // A comment with a brace: {
+            impl Clone for Foo { }
+        }
+
+        goal {
+            Foo: Clone
+        } yields {
+            \"Unique; substitution [], lifetime constraints []\"
+        }
+
+        goal {
+            exists<T> { T: Clone }
+        } yields {
+            Ambiguous; suggested substitution [?0 := Foo]
+        } yields[SLG] {
+            Ambiguous
+        }
+
+        goal {
+            Bar: Clone
+        } yields {
+            No possible solution
+        }
+
+        goal {
+            Baz: Clone
+        } yields {
+            error: invalid type name `Baz`
+        }
+
+        goal {
+            Bar: Clone
+        } yields {
+            Unique
+        }
+    ";
+
+    let test_file = TestFile::parse(text).unwrap();
+    assert_eq!(test_file.goals.len(), 5);
+    assert_eq!(test_file.goals[0].line, Some(10));
+    assert!(test_file.goals[1].slg_expected.is_some());
+
+    let failures: Vec<_> = test_file.run(SolverConfig::default()).unwrap().iter().map(|failure| failure.to_string()).collect();
+    assert_eq!(failures, vec![
+        "goal at line 36 (Recursive engine):\n    Bar: Clone\n-   Unique\n+   No possible solution: no applicable candidates\n",
+        "goal at line 36 (Slg engine):\n    Bar: Clone\n-   Unique\n+   No possible solution: no solution found\n",
+    ]);
+}
+
+#[test]
+fn test_file_config() {
+    let test_file = TestFile::parse("
+        program {
+            trait Q { }
+            struct Z { }
+            struct G<X> { }
+            impl Q for Z { }
+            impl<X> Q for G<X> where X: Q { }
+        }
+
+        goal {
+            G<G<G<G<Z>>>>: Q
+        } yields {
+            Unique
+        }
+    ").unwrap();
+    assert!(test_file.run(SolverConfig::default()).unwrap().is_empty());
+
+    let config = SolverConfig { overflow_depth: 3, ..SolverConfig::default() };
+    let failures = test_file.run(config).unwrap();
+    let engines: Vec<_> = failures.iter().map(|failure| failure.engine).collect();
+    assert_eq!(engines, vec![Engine::Recursive, Engine::Slg]);
+}
+
+#[test]
+fn malformed_test_file() {
+    let error = TestFile::parse("program { struct Foo { } }\ngoal { Foo: Clone }").unwrap_err();
+    assert_eq!(error.to_string(), "malformed test file at line 2: expected `yields`, found `end of file`");
+
+    let error = TestFile::parse("program { struct Foo { }").unwrap_err();
+    assert_eq!(error.to_string(), "malformed test file at line 1: unclosed block");
+}
@@ -0,0 +1,348 @@
+//! Trace events emitted while lowering programs and solving goals, through
+//! the `debug!` family of macros.
+//!
+//! Events are only built when a `Tracer` is installed on the current thread,
+//! either with `with_tracer` or from the environment: setting `CHALK_DEBUG` to
+//! a filter (see `Filter::parse`; `1` traces everything) writes the events
+//! to stderr, or to the file named by `CHALK_DEBUG_FILE`.
+
+use std::cell::RefCell;
+use std::env;
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+
+use errors::*;
+
+/// How detailed an event is; a filter lets through the events up to a level.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    /// Top-level steps, e.g. each closed goal and its solution.
+    Info,
+    Debug,
+
+    /// The inner workings of inference: unification, instantiation and
+    /// canonicalization.
+    Trace,
+}
+
+/// The part of chalk which emitted an event, derived from its module.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Subsystem {
+    Lowering,
+    Solver,
+    Fulfill,
+    Unify,
+    Slg,
+}
+
+impl Subsystem {
+    fn from_module_path(path: &str) -> Subsystem {
+        let path = path.trim_left_matches("chalk::");
+        if path.starts_with("lower") || path.starts_with("coherence") {
+            Subsystem::Lowering
+        } else if path.starts_with("solve::fulfill") {
+            Subsystem::Fulfill
+        } else if path.starts_with("solve::infer") {
+            Subsystem::Unify
+        } else if path.starts_with("solve::slg") {
+            Subsystem::Slg
+        } else {
+            Subsystem::Solver
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Subsystem::Lowering => "lowering",
+            Subsystem::Solver => "solver",
+            Subsystem::Fulfill => "fulfill",
+            Subsystem::Unify => "unify",
+            Subsystem::Slg => "slg",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Event {
+    pub level: Level,
+    pub subsystem: Subsystem,
+
+    /// Number of headings the event is nested in.
+    pub depth: usize,
+
+    /// Whether the events which follow, up to the end of the heading, are
+    /// nested in this one.
+    pub heading: bool,
+    pub message: String,
+}
+
+/// Which events a `Tracer` passes on to its sink.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Filter {
+    /// The most detailed level let through.
+    pub level: Level,
+
+    /// The subsystems let through; all of them if empty.
+    pub subsystems: Vec<Subsystem>,
+
+    /// If given, only the headings whose message contains this text (e.g. a
+    /// goal, or the name of a trait) are let through, together with all the
+    /// events nested in them.
+    pub pattern: Option<String>,
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Filter { level: Level::Trace, subsystems: vec![], pattern: None }
+    }
+}
+
+impl Filter {
+    /// Parse a filter given as a comma-separated list of a level (`info`,
+    /// `debug` or `trace`) and of subsystems (`lowering`, `solver`, `fulfill`,
+    /// `unify` or `slg`), optionally ending with `match=<pattern>`; the
+    /// pattern extends to the end of the filter, so that it may contain
+    /// commas. For example:
+    ///
+    /// ```notrust
+    /// debug,solver,fulfill,match=Vec<Foo>: Clone
+    /// ```
+    ///
+    /// `1` and the empty filter let through every event.
+    pub fn parse(text: &str) -> Result<Filter> {
+        let mut filter = Filter::default();
+        let mut rest = text.trim();
+        if rest == "1" {
+            return Ok(filter);
+        }
+
+        while !rest.is_empty() {
+            if rest.starts_with("match=") {
+                filter.pattern = Some(rest["match=".len()..].to_string());
+                break;
+            }
+            let (item, tail) = match rest.find(',') {
+                Some(i) => (&rest[..i], &rest[i + 1..]),
+                None => (rest, ""),
+            };
+            rest = tail.trim_left();
+            match item.trim() {
+                "info" => filter.level = Level::Info,
+                "debug" => filter.level = Level::Debug,
+                "trace" => filter.level = Level::Trace,
+                "lowering" => filter.subsystems.push(Subsystem::Lowering),
+                "solver" => filter.subsystems.push(Subsystem::Solver),
+                "fulfill" => filter.subsystems.push(Subsystem::Fulfill),
+                "unify" => filter.subsystems.push(Subsystem::Unify),
+                "slg" => filter.subsystems.push(Subsystem::Slg),
+                item => bail!("unknown trace filter item `{}`", item),
+            }
+        }
+
+        Ok(filter)
+    }
+
+    fn lets_through(&self, event: &Event, in_match: bool) -> bool {
+        event.level <= self.level &&
+            (self.subsystems.is_empty() || self.subsystems.contains(&event.subsystem)) &&
+            (self.pattern.is_none() || in_match)
+    }
+}
+
+/// Where the events let through by a filter go: a callback, or a `WriteSink`.
+pub trait Sink {
+    fn event(&mut self, event: &Event);
+}
+
+impl<F: FnMut(&Event)> Sink for F {
+    fn event(&mut self, event: &Event) {
+        self(event)
+    }
+}
+
+/// Writes events, one line per line of their message, indented by their
+/// depth and tagged with their subsystem.
+pub struct WriteSink<W: Write>(pub W);
+
+impl<W: Write> Sink for WriteSink<W> {
+    fn event(&mut self, event: &Event) {
+        // Tracing is best-effort: write errors are ignored.
+        let _ = writeln!(self.0, "{}", event);
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, line) in self.message.lines().enumerate() {
+            if i > 0 {
+                writeln!(f, "")?;
+            }
+            let bar = if i == 0 { "| " } else { "  " };
+            for _ in 0..self.depth {
+                write!(f, "{}", bar)?;
+            }
+            if i == 0 {
+                write!(f, "[{}] ", self.subsystem.name())?;
+            }
+            write!(f, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+pub struct Tracer {
+    filter: Filter,
+    sink: Box<Sink>,
+}
+
+impl Tracer {
+    pub fn new<S: Sink + 'static>(filter: Filter, sink: S) -> Self {
+        Tracer { filter, sink: Box::new(sink) }
+    }
+
+    /// The tracer configured by `CHALK_DEBUG` and `CHALK_DEBUG_FILE`, if any.
+    pub fn from_env() -> Option<Tracer> {
+        let spec = match env::var("CHALK_DEBUG") {
+            Ok(spec) => spec,
+            Err(_) => return None,
+        };
+        let filter = Filter::parse(&spec).unwrap_or_else(|e| {
+            eprintln!("CHALK_DEBUG: {}; tracing everything", e);
+            Filter::default()
+        });
+
+        if let Ok(path) = env::var("CHALK_DEBUG_FILE") {
+            match OpenOptions::new().create(true).append(true).open(&path) {
+                Ok(file) => return Some(Tracer::new(filter, WriteSink(file))),
+                Err(e) => eprintln!("CHALK_DEBUG_FILE: cannot open `{}`: {}; tracing to stderr", path, e),
+            }
+        }
+        Some(Tracer::new(filter, WriteSink(io::stderr())))
+    }
+}
+
+/// The tracing state of a thread.
+struct State {
+    tracer: Option<Tracer>,
+    depth: usize,
+
+    /// The depth of the outermost heading, among those the current event is
+    /// nested in, whose message matched the pattern of the filter.
+    matched_at: Option<usize>,
+}
+
+thread_local! {
+    static STATE: RefCell<State> = RefCell::new(State {
+        tracer: Tracer::from_env(),
+        depth: 0,
+        matched_at: None,
+    });
+}
+
+/// Run `op` with `tracer` installed on the current thread, in place of the
+/// tracer configured by the environment. The previous tracer is restored
+/// even if `op` panics.
+pub fn with_tracer<F, R>(tracer: Tracer, op: F) -> R
+    where F: FnOnce() -> R
+{
+    let fresh = State { tracer: Some(tracer), depth: 0, matched_at: None };
+    let old = STATE.with(|state| ::std::mem::replace(&mut *state.borrow_mut(), fresh));
+    let _restore = RestoreState(Some(old));
+    op()
+}
+
+/// Puts the state it holds back in place when dropped.
+struct RestoreState(Option<State>);
+
+impl Drop for RestoreState {
+    fn drop(&mut self) {
+        if let Some(old) = self.0.take() {
+            STATE.with(|state| *state.borrow_mut() = old);
+        }
+    }
+}
+
+/// Whether events are traced on this thread; the macros only build them if
+/// so.
+pub fn enabled() -> bool {
+    STATE.with(|state| state.borrow().tracer.is_some())
+}
+
+/// Emit an event from the module at `module_path`; see the `debug!` macro.
+pub fn event(level: Level, module_path: &str, message: String) {
+    emit(level, module_path, message, false);
+}
+
+/// Emit a heading, in which the events that follow are nested until the
+/// returned value is dropped; see the `debug_heading!` macro.
+pub fn heading(level: Level, module_path: &str, message: String) -> Heading {
+    emit(level, module_path, message, true);
+    STATE.with(|state| state.borrow_mut().depth += 1);
+    Heading { active: true }
+}
+
+fn emit(level: Level, module_path: &str, message: String, heading: bool) {
+    // The sink is called once the state is no longer borrowed, so that it may
+    // itself emit events; the tracer is taken out meanwhile, so these are
+    // dropped.
+    let traced = STATE.with(|state| {
+        let state = &mut *state.borrow_mut();
+        let pass = {
+            let tracer = match state.tracer {
+                Some(ref tracer) => tracer,
+                None => return None,
+            };
+
+            let matches = tracer.filter.pattern.as_ref().map_or(false, |p| message.contains(&p[..]));
+            if heading && matches && state.matched_at.is_none() {
+                state.matched_at = Some(state.depth);
+            }
+
+            let event = Event {
+                level,
+                subsystem: Subsystem::from_module_path(module_path),
+                depth: state.depth,
+                heading,
+                message,
+            };
+            if tracer.filter.lets_through(&event, state.matched_at.is_some()) {
+                Some(event)
+            } else {
+                None
+            }
+        };
+        pass.map(|event| (state.tracer.take().unwrap(), event))
+    });
+
+    if let Some((mut tracer, event)) = traced {
+        tracer.sink.event(&event);
+        STATE.with(|state| state.borrow_mut().tracer = Some(tracer));
+    }
+}
+
+/// Ends a heading when dropped.
+pub struct Heading {
+    active: bool,
+}
+
+impl Heading {
+    /// A heading which was not emitted, because tracing is disabled.
+    pub fn inactive() -> Self {
+        Heading { active: false }
+    }
+}
+
+impl Drop for Heading {
+    fn drop(&mut self) {
+        if self.active {
+            STATE.with(|state| {
+                let state = &mut *state.borrow_mut();
+                state.depth -= 1;
+                if state.matched_at == Some(state.depth) {
+                    state.matched_at = None;
+                }
+            });
+        }
+    }
+}
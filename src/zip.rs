@@ -124,6 +124,7 @@ struct_zip!(InEnvironment[T] { environment, goal } where T: Zip);
 struct_zip!(ApplicationTy { name, parameters });
 struct_zip!(ProjectionTy { associated_ty_id, parameters });
 struct_zip!(Normalize { projection, ty });
+struct_zip!(Outlives { a, b });
 struct_zip!(EqGoal { a, b });
 
 impl Zip for Environment {
@@ -156,6 +157,7 @@ macro_rules! enum_zip {
 }
 
 enum_zip!(PolarizedTraitRef { Positive, Negative });
-enum_zip!(DomainGoal { Implemented, Normalize, WellFormed });
+enum_zip!(DomainGoal { Implemented, Normalize, WellFormed, FromEnv, Outlives });
 enum_zip!(LeafGoal { DomainGoal, EqGoal });
 enum_zip!(WellFormed { Ty, TraitRef });
+enum_zip!(FromEnv { Ty, TraitRef });
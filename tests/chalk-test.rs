@@ -0,0 +1,40 @@
+//! Runs the `chalk-test` binary on the test files of this directory.
+
+mod common;
+
+use std::env;
+use std::fs::File;
+use std::io::Write;
+
+use common::{stderr, stdout};
+
+#[test]
+fn regressions() {
+    let output = common::run("chalk-test", &["tests/regressions.chalk"]);
+    assert_eq!(stdout(&output), "tests/regressions.chalk: ok\n");
+    assert!(output.status.success());
+}
+
+#[test]
+fn failing_goal() {
+    let path = env::temp_dir().join("chalk-test-failing-goal.chalk");
+    File::create(&path)
+        .unwrap()
+        .write_all(b"program { struct Foo { } trait Clone { } }\n\ngoal { Foo: Clone } yields { Unique }\n")
+        .unwrap();
+    let path = path.to_str().unwrap();
+
+    let output = common::run("chalk-test", &[path]);
+    let stdout = stdout(&output);
+    assert!(stdout.contains(&format!("{}: goal at line 3 (Recursive engine):", path)));
+    assert!(stdout.contains(&format!("{}: goal at line 3 (Slg engine):", path)));
+    assert!(stdout.contains(&format!("{}: 2 failed", path)));
+    assert!(!output.status.success());
+}
+
+#[test]
+fn no_files() {
+    let output = common::run("chalk-test", &[]);
+    assert!(stderr(&output).contains("usage: chalk-test <file.chalk>..."));
+    assert!(!output.status.success());
+}
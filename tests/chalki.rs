@@ -0,0 +1,70 @@
+//! Runs the `chalki` binary in batch mode, checking its exit code.
+
+mod common;
+
+use std::env;
+use std::fs::File;
+use std::io::Write;
+use std::process::Output;
+
+use common::{stderr, stdout};
+
+/// Run `chalki` on a program with a unique, an ambiguous and an unprovable
+/// goal, named after `test`.
+fn chalki(test: &str, args: &[&str]) -> Output {
+    let path = env::temp_dir().join(format!("chalki-{}.chalk", test));
+    File::create(&path)
+        .unwrap()
+        .write_all(b"
+            struct Foo { }
+            struct Bar { }
+            trait Map<T> { }
+            impl Map<Foo> for Foo { }
+            impl Map<Bar> for Foo { }
+        ")
+        .unwrap();
+
+    let mut all_args = vec!["--program", path.to_str().unwrap()];
+    all_args.extend(args);
+    common::run("chalki", &all_args)
+}
+
+#[test]
+fn solved() {
+    let output = chalki("solved", &["--goal", "Foo: Map<Bar>"]);
+    assert_eq!(stdout(&output), "?- Foo: Map<Bar>\nUnique; substitution [], lifetime constraints []\n\n");
+    assert!(output.status.success());
+}
+
+#[test]
+fn unsolved() {
+    let output = chalki("unsolved", &["--goal", "Foo: Map<Bar>", "--goal", "Bar: Map<Foo>"]);
+    assert!(stderr(&output).contains("1 of 2 goals failed"));
+    assert!(!output.status.success());
+}
+
+#[test]
+fn invalid_goal() {
+    let output = chalki("invalid-goal", &["--goal", "Baz: Map<Foo>"]);
+    assert!(stdout(&output).contains("error: invalid type name `Baz`"));
+    assert!(!output.status.success());
+}
+
+#[test]
+fn ambiguous() {
+    let goal = "exists<T> { Foo: Map<T> }";
+    let output = chalki("ambiguous", &["--goal", goal]);
+    assert!(stdout(&output).contains("Ambiguous"));
+    assert!(output.status.success());
+
+    let output = chalki("ambiguous-unique", &["--goal", goal, "--require-unique"]);
+    assert!(stderr(&output).contains("1 of 1 goals failed"));
+    assert!(!output.status.success());
+}
+
+#[test]
+fn unknown_argument() {
+    let output = common::run("chalki", &["--verbose"]);
+    assert!(stderr(&output).contains("unknown argument `--verbose`, see `--help`"));
+    assert!(!output.status.success());
+}
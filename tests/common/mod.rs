@@ -0,0 +1,25 @@
+use std::env;
+use std::process::{Command, Output};
+
+/// Run one of the binaries of the crate, which cargo builds next to the
+/// directory of the tests.
+pub fn run(binary: &str, args: &[&str]) -> Output {
+    let mut path = env::current_exe().unwrap();
+    path.pop();
+    if path.ends_with("deps") {
+        path.pop();
+    }
+    Command::new(path.join(binary))
+        .args(args)
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .output()
+        .unwrap()
+}
+
+pub fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+pub fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}